@@ -46,10 +46,10 @@ fn generate_zobrist_numbers() -> Result<()> {
 
     writeln!(
         f,
-        "/// One zobrist number for each of the 16 possible castling rights combinations"
+        "/// One zobrist number for each castling right, in the order of the `CastleRights` flag bits"
     )?;
-    write!(f, "pub const ZOBRIST_CASTLING: [ZobristHash; 16] = [\n")?;
-    for _ in 0..8 {
+    write!(f, "pub const ZOBRIST_CASTLING: [ZobristHash; 4] = [\n")?;
+    for _ in 0..2 {
         let a = rng.next_u64();
         let b = rng.next_u64();
         writeln!(f, "    ZobristHash({a:0>20}), ZobristHash({b:0>20}),")?;
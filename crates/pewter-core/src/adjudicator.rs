@@ -0,0 +1,309 @@
+use crate::bitboard::masks;
+use crate::io::pgn::Game;
+use crate::movegen::legal_moves;
+use crate::state::GameResult;
+use crate::zobrist::ZobristHash;
+use crate::{Color, Move, Piece, State};
+
+/// Why a game came to an end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminationReason {
+    Checkmate,
+    Stalemate,
+    FiftyMove,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Adjudication {
+    pub result: GameResult,
+    pub reason: TerminationReason,
+}
+
+impl Adjudication {
+    const fn draw(reason: TerminationReason) -> Self {
+        Self {
+            result: GameResult::Draw,
+            reason,
+        }
+    }
+}
+
+/// Tracks the positions of a game as it is played, and decides when it is over.
+///
+/// Unlike `State::game_result`, which can only see a single position, the adjudicator remembers
+/// every position played so far and so can also detect draws by threefold repetition.
+#[derive(Clone, Debug)]
+pub struct Adjudicator {
+    current: State,
+
+    /// The hashes of every position before `current`, oldest first
+    history: Vec<ZobristHash>,
+}
+
+impl Adjudicator {
+    pub fn new(initial: State) -> Self {
+        Self {
+            current: initial,
+            history: Vec::new(),
+        }
+    }
+
+    /// Replay every move of the given game
+    pub fn from_game(game: &Game) -> Self {
+        let mut adjudicator = Self::new(game.initial);
+        for &m in &game.moves {
+            adjudicator.apply_move(m);
+        }
+        adjudicator
+    }
+
+    /// Build an adjudicator from a sequence of consecutive positions, the last of which is the
+    /// current position.
+    ///
+    /// Returns None if the sequence is empty.
+    pub fn from_states(states: &[State]) -> Option<Self> {
+        let (current, previous) = states.split_last()?;
+        Some(Self {
+            current: *current,
+            history: previous.iter().map(|s| s.zobrist).collect(),
+        })
+    }
+
+    /// The current position
+    pub fn state(&self) -> &State {
+        &self.current
+    }
+
+    pub fn apply_move(&mut self, m: Move) {
+        self.history.push(self.current.zobrist);
+        self.current = self.current.apply_move(m);
+    }
+
+    /// The number of times the current position has occurred so far, including the current
+    /// occurrence
+    pub fn repetition_count(&self) -> usize {
+        // Positions from before the last capture or pawn advance can never be repeated, so there's
+        // no need to look further back than the halfmove clock.
+        let lookback = (self.current.halfmove_clock as usize).min(self.history.len());
+        let recent = &self.history[self.history.len() - lookback..];
+        1 + recent
+            .iter()
+            .filter(|&&hash| hash == self.current.zobrist)
+            .count()
+    }
+
+    /// Decide whether the game is over in the current position, and if so why.
+    ///
+    /// Checkmate and stalemate take priority over other draw conditions, so a mating move that
+    /// also completes the fifty move rule is still reported as a checkmate.
+    pub fn adjudicate(&self) -> Option<Adjudication> {
+        let state = &self.current;
+
+        if !legal_moves(state).any() {
+            return Some(if state.in_check() {
+                Adjudication {
+                    result: match state.to_play {
+                        Color::White => GameResult::BlackWin,
+                        Color::Black => GameResult::WhiteWin,
+                    },
+                    reason: TerminationReason::Checkmate,
+                }
+            } else {
+                Adjudication::draw(TerminationReason::Stalemate)
+            });
+        }
+
+        if state.is_dead_position() {
+            Some(Adjudication::draw(TerminationReason::InsufficientMaterial))
+        } else if state.halfmove_clock >= 100 {
+            Some(Adjudication::draw(TerminationReason::FiftyMove))
+        } else if self.repetition_count() >= 3 {
+            Some(Adjudication::draw(TerminationReason::ThreefoldRepetition))
+        } else {
+            None
+        }
+    }
+
+    /// The result of the game, or `GameResult::Ongoing` if it isn't over yet
+    pub fn result(&self) -> GameResult {
+        self.adjudicate()
+            .map(|a| a.result)
+            .unwrap_or(GameResult::Ongoing)
+    }
+}
+
+/// Is there no sequence of legal moves that could lead to either side being checkmated.
+///
+/// This only recognises the standard dead positions: king against king, king and a single minor
+/// piece against king, and kings with any number of bishops that are all on the same color square.
+pub fn insufficient_material(state: &State) -> bool {
+    let board = &state.board;
+
+    let heavy_or_pawns = board
+        .piece_board(Piece::Pawn)
+        .union_with(board.piece_board(Piece::Rook))
+        .union_with(board.piece_board(Piece::Queen));
+    if heavy_or_pawns.any() {
+        return false;
+    }
+
+    let knights = board.piece_board(Piece::Knight);
+    let bishops = board.piece_board(Piece::Bishop);
+    let minor_count = knights.count() + bishops.count();
+
+    if minor_count <= 1 {
+        return true;
+    }
+
+    if knights.any() {
+        return false;
+    }
+
+    let on_light = bishops.intersect_with(masks::color_squares(Color::White));
+    let on_dark = bishops.intersect_with(masks::color_squares(Color::Black));
+    !on_light.any() || !on_dark.any()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::coordinates::consts::*;
+    use crate::io::fen::parse_fen;
+
+    fn adjudicate_moves(fen: &str, moves: &[(crate::BoardPos, crate::BoardPos)]) -> Adjudicator {
+        let mut adjudicator = Adjudicator::new(parse_fen(fen).unwrap());
+        for &(from, to) in moves {
+            adjudicator.apply_move(Move {
+                from,
+                to,
+                promotion: None,
+            });
+        }
+        adjudicator
+    }
+
+    #[test]
+    fn test_ongoing() {
+        let adjudicator = adjudicate_moves(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[(E2, E4), (E7, E5)],
+        );
+        assert_eq!(adjudicator.adjudicate(), None);
+        assert_eq!(adjudicator.result(), GameResult::Ongoing);
+    }
+
+    #[test]
+    fn test_checkmate() {
+        // Fool's mate
+        let adjudicator = adjudicate_moves(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[(F2, F3), (E7, E5), (G2, G4), (D8, H4)],
+        );
+        assert_eq!(
+            adjudicator.adjudicate(),
+            Some(Adjudication {
+                result: GameResult::BlackWin,
+                reason: TerminationReason::Checkmate,
+            })
+        );
+    }
+
+    #[test]
+    fn test_stalemate() {
+        let adjudicator = adjudicate_moves("7k/8/6K1/5Q2/8/8/8/8 w - - 0 1", &[(F5, F7)]);
+        assert_eq!(
+            adjudicator.adjudicate(),
+            Some(Adjudication::draw(TerminationReason::Stalemate))
+        );
+    }
+
+    #[test]
+    fn test_fifty_move() {
+        let adjudicator = adjudicate_moves("7k/8/8/8/8/8/8/R3K3 w - - 99 80", &[(A1, A2)]);
+        assert_eq!(
+            adjudicator.adjudicate(),
+            Some(Adjudication::draw(TerminationReason::FiftyMove))
+        );
+
+        // A mate delivered on the hundredth halfmove still counts
+        let adjudicator = adjudicate_moves("7k/8/6K1/8/8/8/8/R7 w - - 99 80", &[(A1, A8)]);
+        assert_eq!(
+            adjudicator.adjudicate().map(|a| a.reason),
+            Some(TerminationReason::Checkmate)
+        );
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        let shuffle = [(G1, F3), (G8, F6), (F3, G1), (F6, G8)];
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        let twice = adjudicate_moves(start, &shuffle);
+        assert_eq!(twice.repetition_count(), 2);
+        assert_eq!(twice.adjudicate(), None);
+
+        let moves = [shuffle, shuffle].concat();
+        let thrice = adjudicate_moves(start, &moves);
+        assert_eq!(thrice.repetition_count(), 3);
+        assert_eq!(
+            thrice.adjudicate(),
+            Some(Adjudication::draw(TerminationReason::ThreefoldRepetition))
+        );
+    }
+
+    #[test]
+    fn test_from_states_matches_moves() {
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let shuffle = [(B1, C3), (B8, C6), (C3, B1), (C6, B8)];
+
+        let mut states = vec![start];
+        for _ in 0..2 {
+            for &(from, to) in &shuffle {
+                let m = Move {
+                    from,
+                    to,
+                    promotion: None,
+                };
+                states.push(states.last().unwrap().apply_move(m));
+            }
+        }
+
+        let adjudicator = Adjudicator::from_states(&states).unwrap();
+        assert_eq!(
+            adjudicator.adjudicate().map(|a| a.reason),
+            Some(TerminationReason::ThreefoldRepetition)
+        );
+        assert!(Adjudicator::from_states(&[]).is_none());
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let dead = [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5N2/8 w - - 0 1",
+            "8/8/4k3/8/8/3KB3/8/8 b - - 0 1",
+            "8/8/4kb2/8/8/3KB3/8/8 w - - 0 1",
+        ];
+        for fen in dead {
+            let adjudicator = Adjudicator::new(parse_fen(fen).unwrap());
+            assert_eq!(
+                adjudicator.adjudicate(),
+                Some(Adjudication::draw(TerminationReason::InsufficientMaterial)),
+                "{}",
+                fen
+            );
+        }
+
+        let alive = [
+            "8/8/4k3/8/8/3K4/3P4/8 w - - 0 1",
+            "8/8/4k3/8/8/3KN3/5N2/8 w - - 0 1",
+            "8/8/4k1b1/8/8/3KB3/8/8 w - - 0 1",
+        ];
+        for fen in alive {
+            assert!(!insufficient_material(&parse_fen(fen).unwrap()), "{}", fen);
+        }
+    }
+}
@@ -135,20 +135,12 @@ pub const fn queen_rays(pos: BoardPos) -> BitBoard {
 
 /// All kingside castling moves
 pub const fn castling_moves_kingside() -> BitBoard {
-    BitBoard::new_empty()
-        .with_set(E1)
-        .with_set(G1)
-        .with_set(E8)
-        .with_set(G8)
+    crate::squares![E1, G1, E8, G8]
 }
 
 /// All kingside castling moves
 pub const fn castling_moves_queenside() -> BitBoard {
-    BitBoard::new_empty()
-        .with_set(E1)
-        .with_set(C1)
-        .with_set(E8)
-        .with_set(C8)
+    crate::squares![E1, C1, E8, C8]
 }
 
 /// The union of all castling moves
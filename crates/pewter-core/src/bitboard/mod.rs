@@ -1,9 +1,20 @@
 pub mod masks;
 
 use crate::BoardPos;
+use serde::{Deserialize, Serialize};
 use std::iter::FromIterator;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Build a `BitBoard` with each of the given squares set, eg `squares![E1, G1, E8]`
+#[macro_export]
+macro_rules! squares {
+    ($($pos:expr),* $(,)?) => {
+        $crate::BitBoard::from_squares(&[$($pos),*])
+    };
+}
+
+/// Serialized as the underlying `u64`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct BitBoard(pub u64);
 
 impl BitBoard {
@@ -19,6 +30,17 @@ impl BitBoard {
         Self(1 << pos.to_bitboard_offset())
     }
 
+    /// A board with exactly the given squares set
+    pub const fn from_squares(squares: &[BoardPos]) -> Self {
+        let mut bb = Self::new_empty();
+        let mut i = 0;
+        while i < squares.len() {
+            bb = bb.with_set(squares[i]);
+            i += 1;
+        }
+        bb
+    }
+
     pub const fn with_set(mut self, pos: BoardPos) -> Self {
         self.0 |= 1u64 << pos.to_bitboard_offset();
         self
@@ -94,16 +116,35 @@ impl BitBoard {
         }
     }
 
-    pub fn iter_all(self) -> impl Iterator<Item = BoardPos> {
-        (0..64)
-            .filter(move |i| (1 << i) & self.0 != 0)
-            .map(BoardPos::from_bitboard_offset)
+    /// Clear the lowest set bit, returning its position
+    pub fn pop_lsb(&mut self) -> Option<BoardPos> {
+        let pos = self.first_set();
+        // Clearing the lowest set bit of zero would underflow
+        if self.0 != 0 {
+            self.0 &= self.0 - 1;
+        }
+        pos
+    }
+
+    /// Equivalent to `iter_set`
+    pub fn iter_all(self) -> impl ExactSizeIterator<Item = BoardPos> {
+        self.iter_set()
     }
 
-    pub fn iter_set(self) -> impl Iterator<Item = BoardPos> {
+    /// Iterate over each set position, in increasing order of bitboard offset
+    pub fn iter_set(self) -> impl ExactSizeIterator<Item = BoardPos> {
         BitBoardIter(self)
     }
 
+    /// Fold over each set position, in increasing order of bitboard offset
+    pub fn fold<T>(mut self, init: T, mut f: impl FnMut(T, BoardPos) -> T) -> T {
+        let mut acc = init;
+        while let Some(pos) = self.pop_lsb() {
+            acc = f(acc, pos);
+        }
+        acc
+    }
+
     pub fn pretty_format(&self) -> String {
         use crate::io::ascii::pretty_format;
         pretty_format(|pos| if self.get(pos) { '#' } else { ' ' })
@@ -159,6 +200,18 @@ impl From<BoardPos> for BitBoard {
     }
 }
 
+impl From<u64> for BitBoard {
+    fn from(bits: u64) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<BitBoard> for u64 {
+    fn from(bb: BitBoard) -> Self {
+        bb.0
+    }
+}
+
 impl FromIterator<BoardPos> for BitBoard {
     fn from_iter<T: IntoIterator<Item = BoardPos>>(iter: T) -> Self {
         let mut bb = Self::new_empty();
@@ -177,10 +230,95 @@ impl Iterator for BitBoardIter {
     type Item = BoardPos;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pos = self.0.first_set();
-        if let Some(pos) = pos {
-            self.0.clear(pos);
-        }
-        pos
+        self.0.pop_lsb()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count() as usize;
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for BitBoardIter {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coordinates::consts::*;
+
+    #[test]
+    fn test_pop_lsb_order() {
+        let mut bb = BitBoard::new_empty()
+            .with_set(H8)
+            .with_set(C3)
+            .with_set(A1)
+            .with_set(B1);
+
+        assert_eq!(bb.pop_lsb(), Some(A1));
+        assert_eq!(bb.pop_lsb(), Some(B1));
+        assert_eq!(bb.pop_lsb(), Some(C3));
+        assert_eq!(bb.pop_lsb(), Some(H8));
+        assert_eq!(bb.pop_lsb(), None);
+        assert_eq!(bb, BitBoard::new_empty());
+        assert_eq!(bb.pop_lsb(), None);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let bb = BitBoard::new_empty().with_set(A1).with_set(E4).with_set(H8);
+        assert_eq!(BitBoard::from(u64::from(bb)), bb);
+
+        // The board is written exactly as its bits would be
+        let data = serde_cbor::to_vec(&bb).unwrap();
+        assert_eq!(data, serde_cbor::to_vec(&bb.0).unwrap());
+        assert_eq!(serde_cbor::from_slice::<BitBoard>(&data).unwrap(), bb);
+
+        let all = BitBoard::new_all();
+        let data = serde_cbor::to_vec(&all).unwrap();
+        assert_eq!(serde_cbor::from_slice::<BitBoard>(&data).unwrap(), all);
+    }
+
+    #[test]
+    fn test_fold() {
+        let bb = BitBoard::new_empty().with_set(A1).with_set(D4).with_set(H8);
+
+        let offset_sum = bb.fold(0u32, |acc, pos| acc + pos.to_bitboard_offset() as u32);
+        assert_eq!(offset_sum, 27 + 63);
+
+        let visited = bb.fold(Vec::new(), |mut acc, pos| {
+            acc.push(pos);
+            acc
+        });
+        assert_eq!(visited, vec![A1, D4, H8]);
+
+        assert_eq!(BitBoard::new_empty().fold(7, |acc, _| acc + 1), 7);
+    }
+
+    #[test]
+    fn test_iter_set_exact_size() {
+        let bb = BitBoard(0x8100_0000_0000_0181);
+        let mut iter = bb.iter_set();
+        assert_eq!(iter.len(), 5);
+        iter.next();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.collect::<Vec<_>>().len(), 4);
+        assert_eq!(BitBoard::new_all().iter_set().len(), 64);
+    }
+
+    #[test]
+    fn test_from_squares() {
+        let chained = BitBoard::new_empty().with_set(E1).with_set(G1).with_set(E8);
+        assert_eq!(BitBoard::from_squares(&[E1, G1, E8]), chained);
+        assert_eq!(squares![E1, G1, E8], chained);
+        assert_eq!(squares![E8, G1, E1,], chained);
+
+        // Repeated squares are only set once
+        assert_eq!(squares![E1, E1], BitBoard::single(E1));
+
+        assert_eq!(squares![], BitBoard::new_empty());
+        assert_eq!(BitBoard::from_squares(&[]), BitBoard::new_empty());
+
+        const CORNERS: BitBoard = squares![A1, H1, A8, H8];
+        assert_eq!(CORNERS, BitBoard(0x8100_0000_0000_0081));
     }
 }
@@ -1,6 +1,8 @@
+use crate::bitboard::masks;
+use crate::movegen::pseudo_legal;
 use crate::{BitBoard, BoardPos, Color, Piece};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Board {
     piece_boards: [BitBoard; Piece::VARIANT_COUNT],
     color_boards: [BitBoard; Color::VARIANT_COUNT],
@@ -14,6 +16,28 @@ impl Board {
         }
     }
 
+    /// Build a board from the piece on each square, indexed by bitboard offset (ie a1, b1, ..., h8)
+    pub fn from_array(squares: &[Option<(Color, Piece)>; 64]) -> Self {
+        let mut board = Self::new_empty();
+        for (offset, square) in squares.iter().enumerate() {
+            if let Some((color, piece)) = *square {
+                board.add_piece(BoardPos::from_bitboard_offset(offset as u8), color, piece);
+            }
+        }
+
+        board
+    }
+
+    /// The piece on each square, indexed by bitboard offset. The inverse of `Board::from_array`.
+    pub fn to_array(&self) -> [Option<(Color, Piece)>; 64] {
+        let mut squares = [None; 64];
+        for (pos, color, piece) in self.iter_pieces() {
+            squares[pos.to_bitboard_offset() as usize] = Some((color, piece));
+        }
+
+        squares
+    }
+
     pub const fn piece_board(&self, piece: Piece) -> BitBoard {
         self.piece_boards[piece.to_num() as usize]
     }
@@ -40,6 +64,70 @@ impl Board {
         .first_set()
     }
 
+    /// Is the king of the given color attacked by any of the opponent's pieces.
+    ///
+    /// Unlike `State::recompute_pins_and_checks` no pins are computed, and it returns as soon as
+    /// any attacker is found, so is cheap enough to test the result of hypothetical moves with.
+    /// Panics if there is no king of the given color.
+    pub fn is_king_attacked(&self, color: Color) -> bool {
+        let k_pos = self
+            .king_pos(color)
+            .expect("Expected there to be a king to test for attacks on");
+        let opp_pieces = self.color_board(!color);
+
+        let knights = opp_pieces
+            .intersect_with(self.piece_board(Piece::Knight))
+            .intersect_with(masks::knight_moves(k_pos));
+        let pawns = opp_pieces
+            .intersect_with(self.piece_board(Piece::Pawn))
+            .intersect_with(masks::pawn_attacks(color, k_pos));
+        let kings = opp_pieces
+            .intersect_with(self.piece_board(Piece::King))
+            .intersect_with(pseudo_legal::king_moves(k_pos, BitBoard::new_empty()));
+        if knights.union_with(pawns).union_with(kings).any() {
+            return true;
+        }
+
+        let queens = self.piece_board(Piece::Queen);
+        let rooks = self
+            .piece_board(Piece::Rook)
+            .union_with(queens)
+            .intersect_with(masks::rook_rays(k_pos));
+        let bishops = self
+            .piece_board(Piece::Bishop)
+            .union_with(queens)
+            .intersect_with(masks::bishop_rays(k_pos));
+
+        let occupied = self.all_union_board();
+        rooks
+            .union_with(bishops)
+            .intersect_with(opp_pieces)
+            .iter_set()
+            .any(|slider| !masks::between(k_pos, slider).intersect_with(occupied).any())
+    }
+
+    /// A key identifying the material on the board, ie how many of each kind of piece each side
+    /// has, regardless of where they are.
+    ///
+    /// Each (color, piece) count is packed into its own 4 bit field, which is enough for any
+    /// position reachable in a legal game. Used to recognize specific material configurations
+    /// (eg KQvK) for specialized evaluation and tablebase probes.
+    pub fn material_key(&self) -> u64 {
+        let mut key = 0;
+        for &color in Color::all() {
+            for piece in Piece::all() {
+                let count = self.color_piece_board(color, piece).count() as u64;
+                debug_assert!(count < 16);
+
+                let shift =
+                    4 * (color.to_num() as usize * Piece::VARIANT_COUNT + piece.to_num() as usize);
+                key |= count << shift;
+            }
+        }
+
+        key
+    }
+
     pub fn xor_inplace(&mut self, color: Color, piece: Piece, arg: BitBoard) {
         self.color_boards[color.to_num() as usize].xor_inplace(arg);
         self.piece_boards[piece.to_num() as usize].xor_inplace(arg);
@@ -74,6 +162,35 @@ impl Board {
             return None;
         };
 
+        Some((color, self.occupied_piece_kind(pos)))
+    }
+
+    /// The type of the piece on the given square, regardless of its color
+    pub fn piece_kind_at(&self, pos: BoardPos) -> Option<Piece> {
+        if self.all_union_board()[pos] {
+            Some(self.occupied_piece_kind(pos))
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over every piece on the board, in bitboard offset order of the squares they occupy
+    pub fn iter_pieces(&self) -> impl Iterator<Item = (BoardPos, Color, Piece)> + '_ {
+        let white = self.color_board(Color::White);
+        self.all_union_board().iter_set().map(move |pos| {
+            let color = if white[pos] {
+                Color::White
+            } else {
+                Color::Black
+            };
+            (pos, color, self.occupied_piece_kind(pos))
+        })
+    }
+
+    /// Resolves the type of the piece on a square that is known to be occupied
+    fn occupied_piece_kind(&self, pos: BoardPos) -> Piece {
+        debug_assert!(self.all_union_board()[pos]);
+
         let p = self.piece_board(Piece::Pawn);
         let r = self.piece_board(Piece::Rook);
         let n = self.piece_board(Piece::Knight);
@@ -81,7 +198,7 @@ impl Board {
         let k = self.piece_board(Piece::King);
 
         // Tree-style search benchmarked a little faster than a linear search through each piece
-        let piece = if (p | r)[pos] {
+        if (p | r)[pos] {
             if p[pos] {
                 Piece::Pawn
             } else {
@@ -101,9 +218,7 @@ impl Board {
                     Piece::Queen
                 }
             }
-        };
-
-        Some((color, piece))
+        }
     }
 
     pub fn add_piece(&mut self, pos: BoardPos, color: Color, piece: Piece) {
@@ -142,3 +257,154 @@ impl Board {
         assert_eq!(piece_union, color_union);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::io::fen::{format_fen, parse_fen};
+    use crate::movegen::legal_moves;
+    use crate::piece::piece_to_fen_char;
+    use crate::{File, Rank};
+
+    use proptest::collection::vec;
+    use proptest::prelude::any;
+    use proptest::proptest;
+
+    /// Build the placement field of a FEN string from `Board::iter_pieces`
+    fn placement_from_iter_pieces(board: &Board) -> String {
+        let mut squares = [[None; 8]; 8];
+        for (pos, color, piece) in board.iter_pieces() {
+            let sym = piece_to_fen_char(color, piece);
+            squares[pos.rank.to_num() as usize][pos.file.to_num() as usize] = Some(sym);
+        }
+
+        let mut ranks = Vec::new();
+        for rank in squares.iter().rev() {
+            let mut out = String::new();
+            let mut empty = 0;
+            for sym in rank {
+                match sym {
+                    Some(sym) => {
+                        if empty > 0 {
+                            out.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        out.push(*sym);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                out.push_str(&empty.to_string());
+            }
+            ranks.push(out);
+        }
+
+        ranks.join("/")
+    }
+
+    #[test]
+    fn test_is_king_attacked() {
+        let cases = [
+            // Check from each kind of piece
+            ("4k3/8/8/8/8/8/4q3/4K3 w - - 0 1", Color::White),
+            ("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1", Color::White),
+            ("4k3/8/8/8/8/3n4/8/4K3 w - - 0 1", Color::White),
+            ("4k3/8/8/8/1b6/8/8/4K3 w - - 0 1", Color::White),
+            ("4k3/8/8/8/8/8/8/r3K3 w - - 0 1", Color::White),
+            ("4k3/4R3/8/8/8/8/8/4K3 b - - 0 1", Color::Black),
+        ];
+        for (fen, attacked) in cases.iter() {
+            let board = parse_fen(fen).unwrap().board;
+            assert!(board.is_king_attacked(*attacked), "{}", fen);
+            assert!(!board.is_king_attacked(!*attacked), "{}", fen);
+        }
+
+        // Blocked sliders, and pawns that only push towards the king, don't attack it
+        for fen in [
+            "4k3/8/8/8/1b6/2P5/8/4K3 w - - 0 1",
+            "4k3/4p3/8/8/8/8/4P3/r2NK3 w - - 0 1",
+            "4k3/8/8/8/8/8/4p3/4K3 w - - 0 1",
+        ] {
+            let board = parse_fen(fen).unwrap().board;
+            assert!(!board.is_king_attacked(Color::White), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_material_key() {
+        let key = |fen: &str| parse_fen(fen).unwrap().board.material_key();
+
+        // The same material in different places shares a key
+        assert_eq!(
+            key("4k3/8/8/8/8/8/8/3QK3 w - - 0 1"),
+            key("8/8/3k4/8/8/5Q2/8/K7 b - - 0 1")
+        );
+        assert_eq!(
+            key("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            key("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4")
+        );
+
+        // Different material, including the same pieces with the colors swapped, doesn't
+        let keys = [
+            key("4k3/8/8/8/8/8/8/3QK3 w - - 0 1"),
+            key("3qk3/8/8/8/8/8/8/4K3 w - - 0 1"),
+            key("4k3/8/8/8/8/8/8/3RK3 w - - 0 1"),
+            key("4k3/8/8/8/8/8/8/2QQK3 w - - 0 1"),
+            key("4k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+        ];
+        for (i, a) in keys.iter().enumerate() {
+            for b in &keys[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_is_king_attacked_matches_checkers(move_choices in vec(any::<usize>(), 0..60)) {
+            let mut state =
+                parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+
+            for choice in move_choices {
+                let moves = legal_moves(&state).iter().collect::<Vec<_>>();
+                if moves.is_empty() {
+                    break;
+                }
+                state = state.apply_move(moves[choice % moves.len()]);
+
+                assert_eq!(state.board.is_king_attacked(state.to_play), state.in_check());
+                assert!(!state.board.is_king_attacked(!state.to_play));
+            }
+        }
+
+        #[test]
+        fn test_iter_pieces_matches_fen(move_choices in vec(any::<usize>(), 0..60)) {
+            let mut state =
+                parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+
+            for choice in move_choices {
+                let moves = legal_moves(&state).iter().collect::<Vec<_>>();
+                if moves.is_empty() {
+                    break;
+                }
+                state = state.apply_move(moves[choice % moves.len()]);
+            }
+
+            let fen = format_fen(&state);
+            let expected_placement = fen.split(' ').next().unwrap();
+            assert_eq!(placement_from_iter_pieces(&state.board), expected_placement);
+
+            for &file in File::all() {
+                for &rank in Rank::all() {
+                    let pos = BoardPos::from_file_rank(file, rank);
+                    assert_eq!(
+                        state.board.piece_kind_at(pos),
+                        state.board.get(pos).map(|(_color, piece)| piece)
+                    );
+                }
+            }
+        }
+    }
+}
@@ -6,14 +6,101 @@ use thiserror::Error;
 use crate::BitBoard;
 use crate::BoardPos;
 use crate::Piece;
-
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+use crate::{CastleSide, File, State};
+
+/// Moves are ordered by source square, then destination square, then promotion.
+///
+/// Promotions are ordered from most to least valuable piece (queen, rook, bishop, knight), so that
+/// a sorted list of moves lists the promotion that is almost always best first. Non-promotions
+/// sort before promotions, though a single source and destination can't be both.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Move {
     pub from: BoardPos,
     pub to: BoardPos,
     pub promotion: Option<Piece>,
 }
 
+/// What a move does, as determined by the position it is played in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveKind {
+    Quiet,
+
+    /// Captures the given piece, other than by en passant
+    Capture(Piece),
+
+    DoublePawnPush,
+    EnPassant,
+    Castle(CastleSide),
+
+    /// Promotes to the given piece without capturing
+    Promotion(Piece),
+
+    /// Captures the first piece, promoting to the second
+    PromotionCapture(Piece, Piece),
+}
+
+impl MoveKind {
+    /// The piece removed from the board by this move, if any
+    pub const fn captured_piece(self) -> Option<Piece> {
+        match self {
+            MoveKind::Capture(piece) | MoveKind::PromotionCapture(piece, _) => Some(piece),
+            MoveKind::EnPassant => Some(Piece::Pawn),
+            _ => None,
+        }
+    }
+
+    pub const fn is_capture(self) -> bool {
+        self.captured_piece().is_some()
+    }
+
+    pub const fn is_castle(self) -> bool {
+        matches!(self, MoveKind::Castle(_))
+    }
+
+    pub const fn is_en_passant(self) -> bool {
+        matches!(self, MoveKind::EnPassant)
+    }
+
+    pub const fn is_promotion(self) -> bool {
+        matches!(
+            self,
+            MoveKind::Promotion(_) | MoveKind::PromotionCapture(_, _)
+        )
+    }
+}
+
+/// Classify a move by looking at the board it will be played on.
+///
+/// Panics if there is no piece at the source of the move.
+pub fn move_kind(state: &State, m: Move) -> MoveKind {
+    let piece = match state.board.get(m.from) {
+        Some((_, piece)) => piece,
+        None => panic!("Expected there to be a piece at the source of the move being classified"),
+    };
+    let captured = state.board.get(m.to).map(|(_, captured)| captured);
+
+    match (piece, captured, m.promotion) {
+        (_, Some(captured), Some(promotion)) => MoveKind::PromotionCapture(captured, promotion),
+        (_, None, Some(promotion)) => MoveKind::Promotion(promotion),
+        (_, Some(captured), None) => MoveKind::Capture(captured),
+        (Piece::Pawn, None, None) if state.en_passant == Some(m.to) => MoveKind::EnPassant,
+        (Piece::Pawn, None, None)
+            if (m.from.rank.to_num() as i8 - m.to.rank.to_num() as i8).abs() == 2 =>
+        {
+            MoveKind::DoublePawnPush
+        }
+        (Piece::King, None, None)
+            if (m.from.file.to_num() as i8 - m.to.file.to_num() as i8).abs() == 2 =>
+        {
+            MoveKind::Castle(match m.to.file {
+                File::G => CastleSide::Kingside,
+                _ => CastleSide::Queenside,
+            })
+        }
+        _ => MoveKind::Quiet,
+    }
+}
+
 #[derive(Clone, Copy, Debug, Error)]
 pub enum ParseLongAlgebraicError {
     #[error("There were non-ascii bytes in a long-algebraic move string")]
@@ -30,6 +117,21 @@ pub enum ParseLongAlgebraicError {
 }
 
 impl Move {
+    /// A move that doesn't move any piece, standing in for passing the turn or for no move at all.
+    ///
+    /// Its source and destination are the same square, so it can't be mistaken for a real move.
+    pub const fn null() -> Self {
+        Self {
+            from: crate::coordinates::consts::A1,
+            to: crate::coordinates::consts::A1,
+            promotion: None,
+        }
+    }
+
+    pub const fn is_null(&self) -> bool {
+        self.from.to_bitboard_offset() == self.to.to_bitboard_offset()
+    }
+
     pub fn from_long_algebraic(algebraic_str: &str) -> Result<Self, ParseLongAlgebraicError> {
         if algebraic_str.len() < 4 {
             return Err(ParseLongAlgebraicError::MissingChars);
@@ -64,7 +166,12 @@ impl Move {
         Ok(m)
     }
 
+    /// Formats the move as in UCI, where the null move is "0000"
     pub fn format_long_algebraic(&self) -> String {
+        if self.is_null() {
+            return "0000".to_string();
+        }
+
         let mut out = format!("{}{}", self.from.to_algebraic(), self.to.to_algebraic());
 
         if let Some(promotion) = self.promotion {
@@ -79,6 +186,95 @@ impl Move {
 
         out
     }
+
+    /// Pack the move into 16 bits, for storing many of them compactly.
+    ///
+    /// The destination square is in bits 0-5 and the source square in bits 6-11, as offsets in the
+    /// style of `BoardPos::to_bitboard_offset`. Bit 14 is set for promotions, in which case bits
+    /// 12-13 are the promoted piece, counting up from a knight to a queen. Bit 15 is always clear.
+    pub const fn to_u16(&self) -> u16 {
+        let promotion = match self.promotion {
+            None => 0b000,
+            Some(Piece::Knight) => 0b100,
+            Some(Piece::Bishop) => 0b101,
+            Some(Piece::Rook) => 0b110,
+            Some(Piece::Queen) => 0b111,
+            Some(Piece::Pawn) | Some(Piece::King) => panic!("Can't promote to a pawn or a king"),
+        };
+
+        self.to.to_bitboard_offset() as u16
+            | (self.from.to_bitboard_offset() as u16) << 6
+            | promotion << 12
+    }
+
+    /// Unpack a move packed by `to_u16`, or None if the packing is invalid because bit 15 is set,
+    /// or because bits 12-13 are set without the promotion bit.
+    pub const fn from_u16(packed: u16) -> Option<Self> {
+        let promotion = match packed >> 12 {
+            0b000 => None,
+            0b100 => Some(Piece::Knight),
+            0b101 => Some(Piece::Bishop),
+            0b110 => Some(Piece::Rook),
+            0b111 => Some(Piece::Queen),
+            _ => return None,
+        };
+
+        Some(Self {
+            from: BoardPos::from_bitboard_offset(((packed >> 6) & 0x3f) as u8),
+            to: BoardPos::from_bitboard_offset((packed & 0x3f) as u8),
+            promotion,
+        })
+    }
+
+    /// The sort key used for this move's promotion by the `Ord` implementation, lower first
+    pub const fn promotion_rank(&self) -> u8 {
+        match self.promotion {
+            None => 0,
+            Some(Piece::Queen) => 1,
+            Some(Piece::Rook) => 2,
+            Some(Piece::Bishop) => 3,
+            Some(Piece::Knight) => 4,
+            // Never legal, but still given distinct ranks so that `Ord` agrees with `Eq`
+            Some(Piece::Pawn) => 5,
+            Some(Piece::King) => 6,
+        }
+    }
+
+    /// Equivalent to `move_kind(state, self).is_capture()`
+    pub fn is_capture(self, state: &State) -> bool {
+        move_kind(state, self).is_capture()
+    }
+
+    /// Equivalent to `move_kind(state, self).is_castle()`
+    pub fn is_castle(self, state: &State) -> bool {
+        move_kind(state, self).is_castle()
+    }
+
+    /// Equivalent to `move_kind(state, self).is_en_passant()`
+    pub fn is_en_passant(self, state: &State) -> bool {
+        move_kind(state, self).is_en_passant()
+    }
+
+    /// Equivalent to `move_kind(state, self).is_promotion()`
+    pub fn is_promotion(self, state: &State) -> bool {
+        move_kind(state, self).is_promotion()
+    }
+}
+
+impl PartialOrd for Move {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Move {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.from, self.to, self.promotion_rank()).cmp(&(
+            other.from,
+            other.to,
+            other.promotion_rank(),
+        ))
+    }
 }
 
 impl std::fmt::Debug for Move {
@@ -128,20 +324,29 @@ impl<'de> serde::de::Visitor<'de> for LongAlgebraicStrVisitor {
     }
 }
 
+/// The pieces that a pawn can promote to, in the order that a chunk's promotions are iterated in
+pub const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
 /// Represents a set of moves originating from a single position
 #[derive(Clone, Copy, Debug)]
 pub struct MoveSetChunk {
     pub source: BoardPos,
     pub dest_set: BitBoard,
-    pub promotion: bool,
+
+    /// The pieces that each move to the destinations promotes to, as a mask with one bit per entry
+    /// of `PROMOTION_PIECES`, or 0 if these moves aren't promotions
+    pub promotions: u8,
 }
 
 impl MoveSetChunk {
+    /// The `promotions` of a chunk of pawn moves that can promote to any piece
+    pub const ALL_PROMOTIONS: u8 = 0b1111;
+
     pub const fn new_empty(source: BoardPos) -> Self {
         Self {
             source,
             dest_set: BitBoard::new_empty(),
-            promotion: false,
+            promotions: 0,
         }
     }
 
@@ -153,7 +358,7 @@ impl MoveSetChunk {
     }
 
     pub fn len(self) -> u8 {
-        self.dest_set.count() * if self.promotion { 4 } else { 1 }
+        self.dest_set.count() * std::cmp::max(1, self.promotions.count_ones() as u8)
     }
 
     pub fn any(self) -> bool {
@@ -170,43 +375,36 @@ impl Iterator for MoveSetChunkIter {
     type Item = Move;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(dest) = self.inner.dest_set.first_set() {
-            let mut m = Move {
-                from: self.inner.source,
-                to: dest,
-                promotion: None,
-            };
-
-            if self.inner.promotion {
-                match self.promotion_idx % 4 {
-                    0 => m.promotion = Some(Piece::Queen),
-                    1 => m.promotion = Some(Piece::Rook),
-                    2 => m.promotion = Some(Piece::Bishop),
-                    3 => {
-                        m.promotion = Some(Piece::Knight);
-                        self.inner.dest_set.clear(dest);
-                    }
-                    _ => {
-                        unreachable!()
-                    }
-                }
+        let dest = self.inner.dest_set.first_set()?;
+        let mut m = Move {
+            from: self.inner.source,
+            to: dest,
+            promotion: None,
+        };
 
+        if self.inner.promotions != 0 {
+            while self.inner.promotions & (1 << self.promotion_idx) == 0 {
                 self.promotion_idx += 1;
-            } else {
-                self.inner.dest_set.clear(dest);
             }
+            m.promotion = Some(PROMOTION_PIECES[self.promotion_idx as usize]);
+            self.promotion_idx += 1;
 
-            Some(m)
+            // Move on to the next destination once every promotion to this one has been seen
+            if self.inner.promotions >> self.promotion_idx == 0 {
+                self.inner.dest_set.clear(dest);
+                self.promotion_idx = 0;
+            }
         } else {
-            None
+            self.inner.dest_set.clear(dest);
         }
+
+        Some(m)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let s = self.inner.dest_set.count() as usize;
-
-        // 4x as many items if iterating promotions
-        let s = s << if self.inner.promotion { 2 } else { 0 };
+        // Less the promotions to the current destination that have already been seen
+        let seen = self.inner.promotions & ((1 << self.promotion_idx) - 1);
+        let s = self.inner.len() as usize - seen.count_ones() as usize;
 
         (s, Some(s))
     }
@@ -218,33 +416,163 @@ impl ExactSizeIterator for MoveSetChunkIter {}
 pub struct MoveSet {
     // 16, as there is at most one chunk per piece
     chunks: ArrayVec<MoveSetChunk, 16>,
+
+    // Positions set up from a FEN string or `State::from_placement` can have more than 16 pieces
+    // per side, even though no game can reach them. Any chunks past the first 16 go here, which
+    // doesn't allocate until it has to.
+    overflow: Vec<MoveSetChunk>,
 }
 
 impl MoveSet {
     pub fn new_empty() -> Self {
         Self {
             chunks: ArrayVec::new(),
+            overflow: Vec::new(),
         }
     }
 
     pub fn push(&mut self, chunk: MoveSetChunk) {
         if chunk.dest_set.any() {
-            self.chunks.push(chunk);
+            self.push_chunk(chunk);
+        }
+    }
+
+    fn push_chunk(&mut self, chunk: MoveSetChunk) {
+        if let Err(err) = self.chunks.try_push(chunk) {
+            self.overflow.push(err.element());
+        }
+    }
+
+    pub(crate) fn all_chunks(&self) -> impl Iterator<Item = &MoveSetChunk> {
+        self.chunks.iter().chain(self.overflow.iter())
+    }
+
+    fn all_chunks_mut(&mut self) -> impl Iterator<Item = &mut MoveSetChunk> {
+        self.chunks.iter_mut().chain(self.overflow.iter_mut())
+    }
+
+    /// Add the promotions given by the `promotions` mask of the pawn on `source` to `dest`, along
+    /// with any promotions to the same square that are already in the set.
+    ///
+    /// All of the promotions from one square to another are kept in a single chunk, so chunks
+    /// never overlap, but the set can end up with empty chunks.
+    fn insert_promotions(&mut self, source: BoardPos, dest: BoardPos, mut promotions: u8) {
+        for chunk in self.all_chunks_mut() {
+            if chunk.source == source && chunk.promotions != 0 && chunk.dest_set.get(dest) {
+                promotions |= chunk.promotions;
+                chunk.dest_set.clear(dest);
+            }
+        }
+
+        let existing = self
+            .all_chunks_mut()
+            .find(|c| c.source == source && c.promotions == promotions);
+        match existing {
+            Some(existing) => existing.dest_set.set(dest),
+            None => {
+                let mut chunk = MoveSetChunk::new_empty(source);
+                chunk.dest_set.set(dest);
+                chunk.promotions = promotions;
+                self.push_chunk(chunk);
+            }
+        }
+    }
+
+    fn remove_empty_chunks(&mut self) {
+        self.chunks.retain(|c| c.any());
+        self.overflow.retain(|c| c.any());
+    }
+
+    /// Every move that is in either this set or the other.
+    ///
+    /// Chunks of moves from the same source square are merged into one, so when both sets are of
+    /// moves from the same position there is still at most one chunk per piece.
+    pub fn union(mut self, other: MoveSet) -> MoveSet {
+        for chunk in other.all_chunks() {
+            if chunk.promotions != 0 {
+                for dest in chunk.dest_set.iter_set() {
+                    self.insert_promotions(chunk.source, dest, chunk.promotions);
+                }
+                continue;
+            }
+
+            let existing = self
+                .all_chunks_mut()
+                .find(|c| c.source == chunk.source && c.promotions == 0);
+            match existing {
+                Some(existing) => existing.dest_set.union_inplace(chunk.dest_set),
+                None => self.push_chunk(*chunk),
+            }
+        }
+
+        self.remove_empty_chunks();
+        self
+    }
+
+    /// Remove every move for which the predicate is false, keeping the rest in their chunks.
+    ///
+    /// Each promotion is kept or dropped on its own. Promotions from one square that end up with
+    /// different pieces for different destinations are split into a chunk per set of pieces.
+    pub fn retain(&mut self, mut predicate: impl FnMut(&Move) -> bool) {
+        let mut split = Vec::new();
+        for chunk in self.all_chunks_mut() {
+            for dest in chunk.dest_set.iter_set() {
+                let mut m = Move {
+                    from: chunk.source,
+                    to: dest,
+                    promotion: None,
+                };
+
+                if chunk.promotions == 0 {
+                    if !predicate(&m) {
+                        chunk.dest_set.clear(dest);
+                    }
+                    continue;
+                }
+
+                let mut kept = 0;
+                for (idx, &piece) in PROMOTION_PIECES.iter().enumerate() {
+                    m.promotion = Some(piece);
+                    if chunk.promotions & (1 << idx) != 0 && predicate(&m) {
+                        kept |= 1 << idx;
+                    }
+                }
+                if kept != chunk.promotions {
+                    chunk.dest_set.clear(dest);
+                    if kept != 0 {
+                        split.push((chunk.source, dest, kept));
+                    }
+                }
+            }
         }
+
+        for (source, dest, promotions) in split {
+            self.insert_promotions(source, dest, promotions);
+        }
+        self.remove_empty_chunks();
     }
 
     pub fn len(&self) -> usize {
-        self.chunks.iter().map(|c| c.len() as usize).sum()
+        self.all_chunks().map(|c| c.len() as usize).sum()
     }
 
     // TODO: This iterator isn't an ExactSizeIterator, but notionally could be
     // Probably doesn't matter, but perhaps worth exploring when optimizing performance
     pub fn iter(&self) -> impl Iterator<Item = Move> + '_ {
-        self.chunks.iter().flat_map(|c| c.iter())
+        self.all_chunks().flat_map(|c| c.iter())
     }
 
     pub fn any(&self) -> bool {
-        self.chunks.iter().any(|c| c.any())
+        self.all_chunks().any(|c| c.any())
+    }
+
+    /// Every move in this set, in the order given by `Move`'s `Ord` implementation.
+    ///
+    /// Unlike `iter`, this ordering doesn't depend on the order that chunks were pushed in.
+    pub fn sorted_vec(&self) -> Vec<Move> {
+        let mut moves = self.iter().collect::<Vec<_>>();
+        moves.sort_unstable();
+        moves
     }
 }
 
@@ -252,8 +580,208 @@ impl FromIterator<MoveSetChunk> for MoveSet {
     fn from_iter<T: IntoIterator<Item = MoveSetChunk>>(iter: T) -> Self {
         let mut ms = Self::new_empty();
         for chunk in iter {
-            ms.chunks.push(chunk);
+            ms.push_chunk(chunk);
         }
         ms
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fen::parse_fen;
+
+    fn kind_of(fen: &str, lan_move: &str) -> MoveKind {
+        let state = parse_fen(fen).expect("Expect test case FEN to be correct");
+        let m = Move::from_long_algebraic(lan_move).expect("Expect test case move to be correct");
+        move_kind(&state, m)
+    }
+
+    #[test]
+    fn test_null_move() {
+        let null = Move::null();
+        assert!(null.is_null());
+        assert_eq!(null.format_long_algebraic(), "0000");
+
+        for lan in ["a1a2", "b1a1", "e7e8q"] {
+            let m = Move::from_long_algebraic(lan).unwrap();
+            assert!(!m.is_null(), "{}", lan);
+            assert_ne!(m, null);
+        }
+    }
+
+    #[test]
+    fn test_u16_round_trip() {
+        let promotions = [
+            None,
+            Some(Piece::Knight),
+            Some(Piece::Bishop),
+            Some(Piece::Rook),
+            Some(Piece::Queen),
+        ];
+
+        let mut seen = std::collections::HashSet::new();
+        for from in 0..64 {
+            for to in 0..64 {
+                for promotion in promotions {
+                    let m = Move {
+                        from: BoardPos::from_bitboard_offset(from),
+                        to: BoardPos::from_bitboard_offset(to),
+                        promotion,
+                    };
+                    let packed = m.to_u16();
+                    assert_eq!(Move::from_u16(packed), Some(m), "{:?}", m);
+                    assert!(seen.insert(packed), "{:?}", m);
+                }
+            }
+        }
+
+        assert_eq!(std::mem::size_of_val(&Move::null().to_u16()), 2);
+        assert_eq!(Move::null().to_u16(), 0);
+        assert_eq!(Move::from_long_algebraic("e7e8q").unwrap().to_u16(), 0x7d3c);
+
+        // The top bit is never set, nor is a promotion piece without the promotion flag
+        assert_eq!(Move::from_u16(0x8000), None);
+        assert_eq!(Move::from_u16(0x1000), None);
+        assert_eq!(Move::from_u16(0x3fff), None);
+    }
+
+    #[test]
+    fn test_move_kind() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(kind_of(start, "g1f3"), MoveKind::Quiet);
+        assert_eq!(kind_of(start, "e2e3"), MoveKind::Quiet);
+        assert_eq!(kind_of(start, "e2e4"), MoveKind::DoublePawnPush);
+
+        let scandinavian = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        assert_eq!(
+            kind_of(scandinavian, "e4d5"),
+            MoveKind::Capture(Piece::Pawn)
+        );
+
+        let en_passant = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        assert_eq!(kind_of(en_passant, "e5f6"), MoveKind::EnPassant);
+        assert_eq!(kind_of(en_passant, "e5e6"), MoveKind::Quiet);
+
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(
+            kind_of(castling, "e1g1"),
+            MoveKind::Castle(CastleSide::Kingside)
+        );
+        assert_eq!(
+            kind_of(castling, "e1c1"),
+            MoveKind::Castle(CastleSide::Queenside)
+        );
+        assert_eq!(kind_of(castling, "e1f1"), MoveKind::Quiet);
+
+        let promotion = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            kind_of(promotion, "a7a8q"),
+            MoveKind::Promotion(Piece::Queen)
+        );
+        assert_eq!(
+            kind_of(promotion, "a7b8n"),
+            MoveKind::PromotionCapture(Piece::Rook, Piece::Knight)
+        );
+    }
+
+    #[test]
+    fn test_retain_splits_promotions() {
+        let state = parse_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let lan = |m| Move::from_long_algebraic(m).unwrap();
+        let wanted = [lan("a7a8n"), lan("a7b8q"), lan("a7b8r")];
+
+        let mut moves = crate::movegen::legal_moves(&state);
+        moves.retain(|m| wanted.contains(m));
+
+        assert_eq!(moves.sorted_vec(), wanted);
+        assert_eq!(moves.len(), 3);
+
+        // Each chunk's iterator knows exactly how many moves it has left
+        for chunk in moves.all_chunks() {
+            let mut iter = chunk.iter();
+            for remaining in (0..chunk.len() as usize).rev() {
+                assert!(iter.next().is_some());
+                assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+            }
+            assert_eq!(iter.next(), None);
+        }
+    }
+
+    #[test]
+    fn test_promotions_sort_most_valuable_first() {
+        let mut moves = ["a7a8n", "a7a8b", "a7a8q", "a7a8r"]
+            .iter()
+            .map(|lan| Move::from_long_algebraic(lan).unwrap())
+            .collect::<Vec<_>>();
+        moves.sort();
+
+        let promotions = moves.iter().map(|m| m.promotion).collect::<Vec<_>>();
+        assert_eq!(
+            promotions,
+            [
+                Some(Piece::Queen),
+                Some(Piece::Rook),
+                Some(Piece::Bishop),
+                Some(Piece::Knight)
+            ]
+        );
+
+        // Moves only compare equal when they are equal, even for promotions that are never legal
+        let from = BoardPos::from_bitboard_offset(48);
+        let to = BoardPos::from_bitboard_offset(56);
+        for a in Piece::all() {
+            for b in Piece::all() {
+                let a = Move {
+                    from,
+                    to,
+                    promotion: Some(a),
+                };
+                let b = Move {
+                    from,
+                    to,
+                    promotion: Some(b),
+                };
+                assert_eq!(
+                    a.cmp(&b) == std::cmp::Ordering::Equal,
+                    a == b,
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_move_kind_predicates() {
+        let kinds = [
+            MoveKind::Quiet,
+            MoveKind::Capture(Piece::Bishop),
+            MoveKind::DoublePawnPush,
+            MoveKind::EnPassant,
+            MoveKind::Castle(CastleSide::Kingside),
+            MoveKind::Promotion(Piece::Queen),
+            MoveKind::PromotionCapture(Piece::Rook, Piece::Queen),
+        ];
+
+        let captures = kinds.iter().map(|k| k.captured_piece()).collect::<Vec<_>>();
+        assert_eq!(
+            captures,
+            [
+                None,
+                Some(Piece::Bishop),
+                None,
+                Some(Piece::Pawn),
+                None,
+                None,
+                Some(Piece::Rook)
+            ]
+        );
+
+        let castles = kinds.iter().filter(|k| k.is_castle()).count();
+        let en_passants = kinds.iter().filter(|k| k.is_en_passant()).count();
+        let promotions = kinds.iter().filter(|k| k.is_promotion()).count();
+        assert_eq!((castles, en_passants, promotions), (1, 1, 2));
+    }
+}
@@ -24,6 +24,10 @@ impl Color {
         }
     }
 
+    pub const fn all() -> &'static [Self] {
+        &[Color::White, Color::Black]
+    }
+
     /// The nth rank as seen by this color
     /// ```
     /// # use pewter_core::{Color, Rank};
@@ -37,15 +41,52 @@ impl Color {
         };
         Rank::from_num(num)
     }
+
+    /// The other color, also available as `!color`
+    /// ```
+    /// # use pewter_core::Color;
+    /// assert_eq!(Color::White.opponent(), Color::Black);
+    /// assert_eq!(!Color::Black, Color::White);
+    /// ```
+    pub const fn opponent(self) -> Self {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    pub const fn is_white(self) -> bool {
+        matches!(self, Color::White)
+    }
+
+    pub const fn is_black(self) -> bool {
+        matches!(self, Color::Black)
+    }
 }
 
+/// Shorthand for `Color::opponent`
 impl std::ops::Not for Color {
     type Output = Self;
 
     fn not(self) -> Self::Output {
-        match self {
-            Color::White => Color::Black,
-            Color::Black => Color::White,
+        self.opponent()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opponent() {
+        for &color in Color::all() {
+            assert_ne!(color.opponent(), color);
+            assert_eq!(color.opponent().opponent(), color);
+            assert_eq!(!color, color.opponent());
+            assert_ne!(color.is_white(), color.is_black());
         }
+
+        assert!(Color::White.is_white());
+        assert!(Color::Black.is_black());
     }
 }
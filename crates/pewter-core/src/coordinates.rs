@@ -226,11 +226,37 @@ impl BoardPos {
         format!("{}{}", file, rank)
     }
 
+    /// The number of orthogonal steps it takes to get from one square to the other, ie the sum of
+    /// the file and rank distances between them
     pub const fn manhattan_distance(&self, other: &BoardPos) -> u8 {
-        (self.rank.to_num() as i16 + self.file.to_num() as i16
-            - other.rank.to_num() as i16
-            - other.file.to_num() as i16)
-            .abs() as u8
+        self.file.to_num().abs_diff(other.file.to_num())
+            + self.rank.to_num().abs_diff(other.rank.to_num())
+    }
+
+    /// The number of moves it takes a king to get from one square to the other, ie the larger of
+    /// the file and rank distances between them
+    pub const fn chebyshev_distance(&self, other: &BoardPos) -> u8 {
+        let df = self.file.to_num().abs_diff(other.file.to_num());
+        let dr = self.rank.to_num().abs_diff(other.rank.to_num());
+        if df > dr {
+            df
+        } else {
+            dr
+        }
+    }
+
+    /// The manhattan distance from this square to the nearest of the four center squares, from 0
+    /// for d4, e4, d5, and e5 up to 6 in the corners
+    pub const fn center_distance(&self) -> u8 {
+        const fn from_center(num: u8) -> u8 {
+            if num <= 3 {
+                3 - num
+            } else {
+                num - 4
+            }
+        }
+
+        from_center(self.file.to_num()) + from_center(self.rank.to_num())
     }
 
     #[cfg(test)]
@@ -283,6 +309,44 @@ impl BoardPos {
         }
         .map(|file| Self::from_file_rank(file, self.rank))
     }
+
+    /// The position `df` files and `dr` ranks away from this one, or None if that is off the board
+    pub const fn offset(&self, df: i8, dr: i8) -> Option<Self> {
+        let file = self.file.to_num() as i8 + df;
+        let rank = self.rank.to_num() as i8 + dr;
+
+        if file >= 0 && file <= 7 && rank >= 0 && rank <= 7 {
+            Some(Self::from_file_rank(
+                File::from_num(file as u8),
+                Rank::from_num(rank as u8),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Each successive position in the given direction, until the edge of the board.
+    ///
+    /// Doesn't include this position itself.
+    pub fn ray(self, df: i8, dr: i8) -> impl Iterator<Item = Self> {
+        std::iter::successors(self.offset(df, dr), move |pos| pos.offset(df, dr))
+    }
+
+    /// Each of the up to 8 positions adjacent to this one, including diagonally
+    pub fn neighbors(self) -> impl Iterator<Item = Self> {
+        const DIRS: [(i8, i8); 8] = [
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+            (-1, 0),
+            (1, 0),
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+        ];
+
+        DIRS.iter().filter_map(move |&(df, dr)| self.offset(df, dr))
+    }
 }
 
 impl From<(Rank, File)> for BoardPos {
@@ -448,4 +512,78 @@ mod test {
             assert!(pos.to_bitboard_offset() <= 63);
         }
     }
+
+    #[test]
+    fn test_ray() {
+        use consts::*;
+
+        let from_corner = A1.ray(1, 1).collect::<Vec<_>>();
+        assert_eq!(from_corner, [B2, C3, D4, E5, F6, G7, H8]);
+        assert_eq!(A1.ray(-1, 0).count(), 0);
+        assert_eq!(A1.ray(0, 1).last(), Some(A8));
+
+        let from_center = D4.ray(-1, 1).collect::<Vec<_>>();
+        assert_eq!(from_center, [C5, B6, A7]);
+        assert_eq!(D4.ray(1, 0).collect::<Vec<_>>(), [E4, F4, G4, H4]);
+        assert_eq!(D4.ray(2, 1).collect::<Vec<_>>(), [F5, H6]);
+    }
+
+    #[test]
+    fn test_distances() {
+        use consts::*;
+
+        // (from, to, manhattan, chebyshev)
+        let cases = [
+            (A1, A1, 0, 0),
+            (A1, H8, 14, 7),
+            // The file and rank differences cancel out along this diagonal
+            (A8, H1, 14, 7),
+            (H1, A8, 14, 7),
+            (B2, C4, 3, 2),
+            (E4, D5, 2, 1),
+            (D1, D8, 7, 7),
+            (G3, B3, 5, 5),
+        ];
+        let distances =
+            |a: BoardPos, b: BoardPos| (a.manhattan_distance(&b), a.chebyshev_distance(&b));
+        for (from, to, manhattan, chebyshev) in cases {
+            assert_eq!(
+                distances(from, to),
+                (manhattan, chebyshev),
+                "{:?}",
+                (from, to)
+            );
+            assert_eq!(
+                distances(to, from),
+                (manhattan, chebyshev),
+                "{:?}",
+                (to, from)
+            );
+        }
+
+        for center in [D4, E4, D5, E5] {
+            assert_eq!(center.center_distance(), 0);
+        }
+        for corner in [A1, H1, A8, H8] {
+            assert_eq!(corner.center_distance(), 6);
+        }
+        assert_eq!(C3.center_distance(), 2);
+        assert_eq!(E7.center_distance(), 2);
+        assert_eq!(H5.center_distance(), 3);
+    }
+
+    #[test]
+    fn test_neighbors() {
+        use consts::*;
+
+        assert_eq!(A1.neighbors().count(), 3);
+        assert_eq!(H8.neighbors().count(), 3);
+        assert_eq!(A4.neighbors().count(), 5);
+        assert_eq!(E1.neighbors().count(), 5);
+        assert_eq!(D4.neighbors().count(), 8);
+
+        let mut corner = A1.neighbors().collect::<Vec<_>>();
+        corner.sort();
+        assert_eq!(corner, [B1, A2, B2]);
+    }
 }
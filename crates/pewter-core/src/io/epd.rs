@@ -0,0 +1,249 @@
+use thiserror::Error;
+
+use crate::io::fen::{parse_fen, FenParseError};
+use crate::io::pgn::{parse_san_move, PgnParseError};
+use crate::{Move, State};
+
+/// A single operation of an EPD record, eg `bm Qxf7+;`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpdOperation {
+    pub opcode: String,
+
+    /// The operands in order, with the quotes removed from any quoted strings
+    pub operands: Vec<String>,
+}
+
+/// A position along with the operations describing it, as found on each line of an EPD file
+#[derive(Clone, Debug)]
+pub struct EpdRecord {
+    pub state: State,
+    pub operations: Vec<EpdOperation>,
+}
+
+#[derive(Error, Debug)]
+pub enum EpdParseError {
+    #[error("Invalid position: {0}")]
+    Fen(#[from] FenParseError),
+
+    #[error("A quoted string operand was not closed")]
+    UnterminatedString,
+
+    #[error("An operation had no opcode")]
+    MissingOpcode,
+
+    #[error("The operand \"{operand}\" of the {opcode} operation is not a legal move: {source}")]
+    BadMove {
+        opcode: String,
+        operand: String,
+        source: PgnParseError,
+    },
+}
+
+impl EpdRecord {
+    /// The operands of the first operation with the given opcode, if there is one
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|op| op.opcode == opcode)
+            .map(|op| op.operands.as_slice())
+    }
+
+    /// The name given to the position by the "id" operation
+    pub fn id(&self) -> Option<&str> {
+        self.operands("id")
+            .and_then(|operands| operands.first())
+            .map(|id| id.as_str())
+    }
+
+    /// The moves of the "bm" operation, any of which is considered the best move
+    pub fn best_moves(&self) -> Result<Vec<Move>, EpdParseError> {
+        self.moves("bm")
+    }
+
+    /// The moves of the "am" operation, none of which should be played
+    pub fn avoid_moves(&self) -> Result<Vec<Move>, EpdParseError> {
+        self.moves("am")
+    }
+
+    /// The operands of the given operation decoded as SAN moves, or empty if there's no such
+    /// operation
+    pub fn moves(&self, opcode: &str) -> Result<Vec<Move>, EpdParseError> {
+        self.operands(opcode)
+            .unwrap_or_default()
+            .iter()
+            .map(|operand| {
+                parse_san_move(&self.state, operand).map_err(|source| EpdParseError::BadMove {
+                    opcode: opcode.to_string(),
+                    operand: operand.clone(),
+                    source,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Split the operation section of an EPD record into the opcode and operands of each operation
+fn parse_operations(ops_str: &str) -> Result<Vec<EpdOperation>, EpdParseError> {
+    let mut operations = Vec::new();
+    let mut tokens = Vec::new();
+    let mut chars = ops_str.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+
+        match chars.peek() {
+            None => break,
+            Some(';') => {
+                chars.next();
+                let mut tokens = std::mem::take(&mut tokens).into_iter();
+                let opcode = tokens.next().ok_or(EpdParseError::MissingOpcode)?;
+                operations.push(EpdOperation {
+                    opcode,
+                    operands: tokens.collect(),
+                });
+            }
+            Some('"') => {
+                chars.next();
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => token.push(c),
+                        None => return Err(EpdParseError::UnterminatedString),
+                    }
+                }
+                tokens.push(token);
+            }
+            Some(_) => {
+                let mut token = String::new();
+                while let Some(c) = chars.next_if(|&c| !c.is_ascii_whitespace() && c != ';') {
+                    token.push(c);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+
+    // Tolerate the final operation missing its terminating semicolon
+    let mut tokens = tokens.into_iter();
+    if let Some(opcode) = tokens.next() {
+        operations.push(EpdOperation {
+            opcode,
+            operands: tokens.collect(),
+        });
+    }
+
+    Ok(operations)
+}
+
+/// Parse a single EPD record, which is the first four fields of a FEN string followed by any
+/// number of semicolon terminated operations.
+pub fn parse_epd(epd_str: &str) -> Result<EpdRecord, EpdParseError> {
+    let epd_str = epd_str.trim();
+
+    // The end of the fourth whitespace separated field
+    let mut fields_end = 0;
+    for _ in 0..4 {
+        let rest = &epd_str[fields_end..];
+        let field_start = rest.len() - rest.trim_start().len();
+        let field_len = rest[field_start..]
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len() - field_start);
+        fields_end += field_start + field_len;
+    }
+
+    let state = parse_fen(&epd_str[..fields_end])?;
+    let operations = parse_operations(&epd_str[fields_end..])?;
+
+    Ok(EpdRecord { state, operations })
+}
+
+/// Parse every record of an EPD file, skipping blank lines
+pub fn parse_multi_epd(multi_epd_str: &str) -> Vec<Result<EpdRecord, EpdParseError>> {
+    multi_epd_str
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_epd)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::io::fen::format_fen;
+
+    #[test]
+    fn test_parse_epd() {
+        let record =
+            parse_epd("1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - - bm Qd1+; id \"BK.01\" ;")
+                .unwrap();
+
+        assert_eq!(
+            format_fen(&record.state),
+            "1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - - 0 1"
+        );
+        assert_eq!(record.id(), Some("BK.01"));
+        assert_eq!(
+            record.best_moves().unwrap(),
+            vec![Move::from_long_algebraic("d6d1").unwrap()]
+        );
+        assert_eq!(record.avoid_moves().unwrap(), Vec::new());
+        assert_eq!(record.operands("c0"), None);
+    }
+
+    #[test]
+    fn test_parse_epd_operations() {
+        let record = parse_epd(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -  bm e4 d4;am Nh3; \
+             c0 \"a; quoted comment\"; noop",
+        )
+        .unwrap();
+
+        let opcodes = record
+            .operations
+            .iter()
+            .map(|op| op.opcode.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(opcodes, vec!["bm", "am", "c0", "noop"]);
+        assert_eq!(record.best_moves().unwrap().len(), 2);
+        assert_eq!(
+            record.avoid_moves().unwrap(),
+            vec![Move::from_long_algebraic("g1h3").unwrap()]
+        );
+        assert_eq!(
+            record.operands("c0"),
+            Some(&["a; quoted comment".to_string()][..])
+        );
+        assert_eq!(record.operands("noop"), Some(&[][..]));
+
+        // Moves which aren't legal in the position are only an error once they are decoded
+        let record =
+            parse_epd("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e5;").unwrap();
+        assert!(matches!(
+            record.best_moves(),
+            Err(EpdParseError::BadMove { .. })
+        ));
+
+        for bad in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"unterminated;",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4;;",
+        ] {
+            assert!(parse_epd(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_multi_epd() {
+        let records = parse_multi_epd(
+            "4k3/8/8/8/8/8/8/4K2R w K - id \"one\";\n\n4k3/8/8/8/8/8/8/4K2R b K - id \"two\";\n",
+        );
+
+        let ids = records
+            .iter()
+            .map(|r| r.as_ref().unwrap().id().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["one", "two"]);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{BoardPos, CastleRights, Color, File, Piece, Rank, State};
+use crate::piece::{fen_char_to_piece, piece_to_fen_char};
+use crate::{BoardPos, CastleRights, Color, File, Rank, State};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FenParseError {
@@ -14,7 +15,7 @@ pub enum FenParseError {
     /// The "next to move" field was not 'w' or 'b'
     InvalidColor(char),
 
-    /// A castling rights char was not one of 'kqKQ-'
+    /// A castling rights char was not one of 'kqKQ-', or the file of a rook's starting square
     InvalidCastlingRightsChar(char),
 
     /// There was an invalid number in the halfmove/fullmove counters
@@ -29,41 +30,6 @@ impl std::fmt::Display for FenParseError {
 
 impl std::error::Error for FenParseError {}
 
-fn format_piece_symbol(color: Color, piece: Piece) -> char {
-    match (color, piece) {
-        (Color::Black, Piece::Pawn) => 'p',
-        (Color::Black, Piece::Rook) => 'r',
-        (Color::Black, Piece::Knight) => 'n',
-        (Color::Black, Piece::Bishop) => 'b',
-        (Color::Black, Piece::King) => 'k',
-        (Color::Black, Piece::Queen) => 'q',
-        (Color::White, Piece::Pawn) => 'P',
-        (Color::White, Piece::Rook) => 'R',
-        (Color::White, Piece::Knight) => 'N',
-        (Color::White, Piece::Bishop) => 'B',
-        (Color::White, Piece::King) => 'K',
-        (Color::White, Piece::Queen) => 'Q',
-    }
-}
-
-fn parse_piece_symbol(symbol: char) -> Result<(Color, Piece), FenParseError> {
-    match symbol {
-        'p' => Ok((Color::Black, Piece::Pawn)),
-        'r' => Ok((Color::Black, Piece::Rook)),
-        'n' => Ok((Color::Black, Piece::Knight)),
-        'b' => Ok((Color::Black, Piece::Bishop)),
-        'k' => Ok((Color::Black, Piece::King)),
-        'q' => Ok((Color::Black, Piece::Queen)),
-        'P' => Ok((Color::White, Piece::Pawn)),
-        'R' => Ok((Color::White, Piece::Rook)),
-        'N' => Ok((Color::White, Piece::Knight)),
-        'B' => Ok((Color::White, Piece::Bishop)),
-        'K' => Ok((Color::White, Piece::King)),
-        'Q' => Ok((Color::White, Piece::Queen)),
-        _ => Err(FenParseError::InvalidPieceChar(symbol)),
-    }
-}
-
 const FEN_RANKS: [Rank; 8] = [
     Rank::R8,
     Rank::R7,
@@ -95,7 +61,8 @@ fn parse_fen_placements(placement_str: &str, state: &mut State) -> Result<(), Fe
                     files.next();
                 }
             } else {
-                let (color, piece) = parse_piece_symbol(sym)?;
+                let (color, piece) =
+                    fen_char_to_piece(sym).ok_or(FenParseError::InvalidPieceChar(sym))?;
                 let file = files.next().ok_or(FenParseError::TooLargeRank)?;
                 state.add_piece(color, piece, (*rank, *file).into())
             }
@@ -105,49 +72,44 @@ fn parse_fen_placements(placement_str: &str, state: &mut State) -> Result<(), Fe
     Ok(())
 }
 
+/// Parse a FEN string into a State.
+///
+/// Fields may be separated by any amount of whitespace, the color to move may be in either case,
+/// and the castling rights may be given in any order.
 pub fn parse_fen(fen_str: &str) -> Result<State, FenParseError> {
     let mut state = State::new_empty();
 
-    let mut fields = fen_str.split(" ");
+    let mut fields = fen_str.split_ascii_whitespace();
 
     let placement_str = fields.next().ok_or(FenParseError::MissingFields)?;
     parse_fen_placements(placement_str, &mut state)?;
 
     match fields.next().map(|s| s.chars().next()).flatten() {
-        Some('w') => state.to_play = Color::White,
-        Some('b') => state.to_play = Color::Black,
+        Some('w' | 'W') => state.to_play = Color::White,
+        Some('b' | 'B') => state.to_play = Color::Black,
         Some(c) => return Err(FenParseError::InvalidColor(c)),
         None => return Err(FenParseError::MissingFields),
     }
 
-    state.recompute_pins_and_checks();
-
     let castling_str = fields.next().ok_or(FenParseError::MissingFields)?;
-    for c in castling_str.chars() {
-        match c {
-            'K' => state.castle_rights.insert(CastleRights::WHITE_KINGSIDE),
-            'Q' => state.castle_rights.insert(CastleRights::WHITE_QUEENSIDE),
-            'k' => state.castle_rights.insert(CastleRights::BLACK_KINGSIDE),
-            'q' => state.castle_rights.insert(CastleRights::BLACK_QUEENSIDE),
-            '-' => (),
-            _ => return Err(FenParseError::InvalidCastlingRightsChar(c)),
-        }
-    }
+    state.castle_rights = CastleRights::from_fen_str(castling_str)?;
 
     let en_passant_str = fields.next().ok_or(FenParseError::MissingFields)?;
     state.en_passant = BoardPos::from_algebraic(en_passant_str);
 
-    let halfmove_clock_str = fields.next().ok_or(FenParseError::MissingFields)?;
-    state.halfmove_clock = halfmove_clock_str
-        .parse()
-        .map_err(|_| FenParseError::InvalidNumber)?;
+    // The move counters are frequently omitted (eg in EPD records), so default them to the values
+    // they would have at the start of a game.
+    state.halfmove_clock = match fields.next() {
+        Some(s) => s.parse().map_err(|_| FenParseError::InvalidNumber)?,
+        None => 0,
+    };
 
-    let fullmove_counter_str = fields.next().ok_or(FenParseError::MissingFields)?;
-    state.fullmove_counter = fullmove_counter_str
-        .parse()
-        .map_err(|_| FenParseError::InvalidNumber)?;
+    state.fullmove_counter = match fields.next() {
+        Some(s) => s.parse().map_err(|_| FenParseError::InvalidNumber)?,
+        None => 1,
+    };
 
-    state.zobrist = crate::zobrist::calculate_entire_zobrist(&state);
+    state.recompute_all();
 
     if fields.next().is_some() {
         Err(FenParseError::ExcessFields)
@@ -167,7 +129,7 @@ fn format_fen_positions(state: &State, out: &mut String) {
                         out.push_str(&format!("{}", empty_squares));
                         empty_squares = 0;
                     }
-                    out.push_str(&format!("{}", format_piece_symbol(color, piece)));
+                    out.push_str(&format!("{}", piece_to_fen_char(color, piece)));
                 }
                 None => empty_squares += 1,
             };
@@ -183,6 +145,26 @@ fn format_fen_positions(state: &State, out: &mut String) {
 }
 
 pub fn format_fen(state: &State) -> String {
+    let mut out = format_fen_fields(state, state.en_passant);
+
+    out.push_str(&format!(
+        " {} {}",
+        state.halfmove_clock, state.fullmove_counter
+    ));
+
+    out
+}
+
+/// Format the first four fields of the FEN string for the given state, leaving out the move
+/// counters.
+///
+/// The en passant field is only filled in if an en passant capture is actually possible, so any
+/// two states where `State::same_board` is true give the same string.
+pub fn fen_without_counters(state: &State) -> String {
+    format_fen_fields(state, state.effective_en_passant())
+}
+
+fn format_fen_fields(state: &State, en_passant: Option<BoardPos>) -> String {
     // Should be more than enough for the largest possible FEN string
     let mut out = String::with_capacity(128);
 
@@ -193,34 +175,14 @@ pub fn format_fen(state: &State) -> String {
         Color::Black => out.push_str(" b "),
     }
 
-    if state.castle_rights.contains(CastleRights::WHITE_KINGSIDE) {
-        out.push('K');
-    }
-    if state.castle_rights.contains(CastleRights::WHITE_QUEENSIDE) {
-        out.push('Q');
-    }
-    if state.castle_rights.contains(CastleRights::BLACK_KINGSIDE) {
-        out.push('k');
-    }
-    if state.castle_rights.contains(CastleRights::BLACK_QUEENSIDE) {
-        out.push('q');
-    }
-
-    if state.castle_rights.is_empty() {
-        out.push('-');
-    }
+    out.push_str(&state.castle_rights.to_fen_string());
 
-    if let Some(ep) = state.en_passant {
+    if let Some(ep) = en_passant {
         out.push_str(&format!(" {}", ep.to_algebraic()));
     } else {
         out.push_str(" -");
     }
 
-    out.push_str(&format!(
-        " {} {}",
-        state.halfmove_clock, state.fullmove_counter
-    ));
-
     out
 }
 
@@ -240,4 +202,59 @@ mod tests {
             assert_eq!(example_fen_str, &roundtripped_fen_str);
         }
     }
+
+    #[test]
+    fn test_fen_parse_without_clocks() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3")
+            .expect("Expected a FEN string without move counters to parse");
+
+        assert_eq!(state.halfmove_clock, 0);
+        assert_eq!(state.fullmove_counter, 1);
+        assert_eq!(
+            format_fen(&state),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+    }
+
+    #[test]
+    fn test_fen_parse_extra_whitespace() {
+        let state =
+            parse_fen("  rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR  b\tKQkq  e3 0   1 ")
+                .expect("Expected a FEN string with extra whitespace to parse");
+
+        assert_eq!(
+            format_fen(&state),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+    }
+
+    #[test]
+    fn test_fen_parse_unconventional_castling_and_color() {
+        let state = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R W qkQK - 0 1")
+            .expect("Expected castling rights out of order to parse");
+
+        assert_eq!(format_fen(&state), "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(
+            parse_fen("r3k2r/8/8/8/8/8/8/R3K2R B Kq - 0 1").map(|state| format_fen(&state)),
+            Ok("r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1".to_string())
+        );
+
+        // Characters which don't mean anything are still rejected
+        assert_eq!(
+            parse_fen("r3k2r/8/8/8/8/8/8/R3K2R x KQkq - 0 1").err(),
+            Some(FenParseError::InvalidColor('x'))
+        );
+        assert_eq!(
+            parse_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQxq - 0 1").err(),
+            Some(FenParseError::InvalidCastlingRightsChar('x'))
+        );
+    }
+
+    #[test]
+    fn test_fen_parse_missing_en_passant() {
+        assert_eq!(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq").err(),
+            Some(FenParseError::MissingFields)
+        );
+    }
 }
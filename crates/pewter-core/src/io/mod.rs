@@ -1,4 +1,5 @@
 pub mod ascii;
+pub mod epd;
 pub mod fen;
 pub mod pgn;
 pub mod uci;
@@ -1,8 +1,9 @@
 use thiserror::Error;
 
 use crate::{
-    coordinates::consts::*, io::fen::parse_fen, movegen::legal_moves, state::GameResult, BoardPos,
-    Color, File, Move, Piece, Rank, State,
+    chessmove::move_kind, coordinates::consts::*, io::fen::parse_fen, movegen::legal_moves,
+    piece::piece_to_fen_char, state::GameResult, BoardPos, CastleSide, Color, File, Move, MoveKind,
+    Piece, Rank, State,
 };
 
 pub struct Game {
@@ -11,6 +12,22 @@ pub struct Game {
     pub result: GameResult,
 }
 
+impl Game {
+    /// Every position of the game in order, along with the move that was played from it
+    pub fn positions(&self) -> impl Iterator<Item = (State, Move)> + '_ {
+        self.moves.iter().scan(self.initial, |state, &m| {
+            let position = *state;
+            *state = state.apply_move(m);
+            Some((position, m))
+        })
+    }
+
+    /// The position reached after playing every move of the game
+    pub fn final_state(&self) -> State {
+        self.initial.apply_moves(&self.moves)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum PgnParseError {
     #[error("A SAN encoded move could not be decoded")]
@@ -29,7 +46,7 @@ pub enum PgnParseError {
     NonAscii,
 }
 
-fn parse_san_move(state: &State, move_str: &str) -> Result<Move, PgnParseError> {
+pub(crate) fn parse_san_move(state: &State, move_str: &str) -> Result<Move, PgnParseError> {
     if !move_str.is_ascii() || move_str.len() < 2 {
         return Err(PgnParseError::BadMoveString);
     }
@@ -131,6 +148,133 @@ fn parse_san_move(state: &State, move_str: &str) -> Result<Move, PgnParseError>
     }
 }
 
+/// Which parts of the source square a SAN move needs to include to tell it apart from the other
+/// legal moves in the same position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disambiguation {
+    /// No other piece of the same type can move to the destination square
+    None,
+    /// The source file alone is enough, eg "Nbd2"
+    File,
+    /// The source rank alone is enough, eg "R1a3"
+    Rank,
+    /// Both the source file and rank are needed, eg "Qa4b3"
+    Both,
+}
+
+/// Work out how a legal move in the given state has to be disambiguated when formatted as SAN.
+///
+/// Pawn moves never need disambiguating, as a pawn capture always includes its source file anyway.
+pub fn san_disambiguation(state: &State, m: Move) -> Disambiguation {
+    let piece = match state.board.get(m.from) {
+        Some((_, Piece::Pawn)) => return Disambiguation::None,
+        Some((_, piece)) => piece,
+        None => panic!("Expected there to be a piece at the source of the move"),
+    };
+
+    // Other pieces of the same type which could also move to the destination square
+    let others = legal_moves(state)
+        .iter()
+        .filter(|o| o.to == m.to && o.from != m.from)
+        .filter(|o| state.board.get(o.from) == Some((state.to_play, piece)))
+        .map(|o| o.from)
+        .collect::<Vec<_>>();
+
+    if others.is_empty() {
+        Disambiguation::None
+    } else if others.iter().all(|o| o.file != m.from.file) {
+        Disambiguation::File
+    } else if others.iter().all(|o| o.rank != m.from.rank) {
+        Disambiguation::Rank
+    } else {
+        Disambiguation::Both
+    }
+}
+
+/// Format a legal move in the given state as a SAN string, eg "Nbd2", "exd5", "O-O", "e8=Q+"
+pub fn format_san_move(state: &State, m: Move) -> String {
+    let piece = match state.board.get(m.from) {
+        Some((_, piece)) => piece,
+        None => panic!("Expected there to be a piece at the source of the move being formatted"),
+    };
+
+    let mut san = String::new();
+
+    let file_char = |file: File| (b'a' + file.to_num()) as char;
+    let rank_char = |rank: Rank| (b'1' + rank.to_num()) as char;
+
+    let kind = move_kind(state, m);
+    if let MoveKind::Castle(side) = kind {
+        match side {
+            CastleSide::Kingside => san.push_str("O-O"),
+            CastleSide::Queenside => san.push_str("O-O-O"),
+        }
+    } else {
+        let capture = kind.is_capture();
+
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(file_char(m.from.file));
+            }
+        } else {
+            san.push(piece_to_fen_char(Color::White, piece));
+
+            match san_disambiguation(state, m) {
+                Disambiguation::None => (),
+                Disambiguation::File => san.push(file_char(m.from.file)),
+                Disambiguation::Rank => san.push(rank_char(m.from.rank)),
+                Disambiguation::Both => {
+                    san.push(file_char(m.from.file));
+                    san.push(rank_char(m.from.rank));
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&m.to.to_algebraic());
+
+        if let Some(promotion) = m.promotion {
+            san.push('=');
+            san.push(piece_to_fen_char(Color::White, promotion));
+        }
+    }
+
+    let next_state = state.apply_move(m);
+    if next_state.in_check() {
+        if legal_moves(&next_state).any() {
+            san.push('+');
+        } else {
+            san.push('#');
+        }
+    }
+
+    san
+}
+
+/// Replay the given moves from the initial state, producing numbered SAN movetext in the same
+/// style as a PGN file, eg "1.d4 d5 2.c4 c6".
+///
+/// The moves are assumed to all be legal.
+pub fn movetext(initial: &State, moves: &[Move]) -> String {
+    let mut state = *initial;
+    let mut tokens = Vec::with_capacity(moves.len());
+
+    for (idx, &m) in moves.iter().enumerate() {
+        let san = format_san_move(&state, m);
+        let token = match state.to_play {
+            Color::White => format!("{}.{}", state.fullmove_counter, san),
+            Color::Black if idx == 0 => format!("{}...{}", state.fullmove_counter, san),
+            Color::Black => san,
+        };
+        tokens.push(token);
+        state = state.apply_move(m);
+    }
+
+    tokens.join(" ")
+}
+
 /// Strips prefixes that match the pattern `[0-9]\.`.
 ///
 ///  - Turn a string of the form "13.Nxd3" into "Nxd3"
@@ -263,7 +407,7 @@ pub fn parse_multi_pgn(
 
 #[cfg(test)]
 mod tests {
-    use crate::io::fen::parse_fen;
+    use crate::io::fen::{format_fen, parse_fen};
 
     use super::*;
 
@@ -324,6 +468,111 @@ mod tests {
         assert_eq!(game.moves[93].format_long_algebraic(), "g2g1");
     }
 
+    #[test]
+    fn test_movetext() {
+        let game =
+            parse_single_pgn(EXAMPLE_PGN).expect("Expected EXAMPLE_PGN to parse successfully");
+
+        assert_eq!(
+            movetext(&game.initial, &game.moves[..20]),
+            "1.d4 d5 2.c4 c6 3.Nc3 Nf6 4.Nf3 e6 5.Bg5 h6 6.Bh4 dxc4 7.e4 g5 8.Bg3 b5 9.Be2 Bb7 \
+             10.Qc2 Nh5"
+        );
+    }
+
+    #[test]
+    fn test_movetext_roundtrips_example_game() {
+        let game =
+            parse_single_pgn(EXAMPLE_PGN).expect("Expected EXAMPLE_PGN to parse successfully");
+
+        let text = movetext(&game.initial, &game.moves);
+        assert!(text.contains("15.Ne4 O-O-O 16.Nd6+ Rxd6"));
+        assert!(text.contains("22.Rfe1"));
+        assert!(text.contains("34.Rexd6"));
+
+        let reparsed = parse_single_pgn(&text).expect("Expected movetext to parse successfully");
+        assert_eq!(reparsed.moves, game.moves);
+    }
+
+    #[test]
+    fn test_game_positions() {
+        let game =
+            parse_single_pgn(EXAMPLE_PGN).expect("Expected EXAMPLE_PGN to parse successfully");
+
+        let positions = game.positions().collect::<Vec<_>>();
+        assert_eq!(positions.len(), game.moves.len());
+
+        let mut state = game.initial;
+        for ((position, m), &expected) in positions.iter().zip(&game.moves) {
+            assert_eq!(*m, expected);
+            assert_eq!(position.zobrist, state.zobrist);
+            state = state.apply_move(expected);
+        }
+
+        assert_eq!(game.final_state().zobrist, state.zobrist);
+        assert_eq!(format_fen(&game.final_state()), format_fen(&state));
+    }
+
+    #[test]
+    fn test_format_san_move() {
+        let cases = [
+            // Promotion with mate, and a capture promotion
+            ("7k/P5pp/8/8/8/8/8/K7 w - - 0 1", "a7a8q", "a8=Q#"),
+            ("1r5k/P7/8/8/8/8/8/K7 w - - 0 1", "a7b8n", "axb8=N"),
+            // En-passant capture
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "exd6"),
+            // Disambiguation by rank and by both file and rank
+            ("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", "R1a3"),
+            ("4k3/8/8/8/Q1Q5/8/Q7/4K3 w - - 0 1", "a4b3", "Qa4b3"),
+        ];
+
+        for (fen, lan, expected) in cases {
+            let state = parse_fen(fen).unwrap();
+            let m = Move::from_long_algebraic(lan).unwrap();
+            assert_eq!(format_san_move(&state, m), expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_san_disambiguation() {
+        // Three queens which can all reach b2
+        let fen = "8/8/7k/8/8/Q7/8/Q1Q4K w - - 0 1";
+        let cases = [
+            (fen, "c1b2", Disambiguation::File),
+            (fen, "a3b2", Disambiguation::Rank),
+            (fen, "a1b2", Disambiguation::Both),
+            (fen, "h1g2", Disambiguation::None),
+            // Knights on the same rank
+            (
+                "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1",
+                "b1d2",
+                Disambiguation::File,
+            ),
+            // Pawn captures are never disambiguated further
+            (
+                "4k3/8/8/3p4/2P1P3/8/8/4K3 w - - 0 1",
+                "c4d5",
+                Disambiguation::None,
+            ),
+        ];
+
+        for (fen, lan, expected) in cases {
+            let state = parse_fen(fen).unwrap();
+            let m = Move::from_long_algebraic(lan).unwrap();
+            assert_eq!(san_disambiguation(&state, m), expected, "{} {}", fen, lan);
+        }
+    }
+
+    #[test]
+    fn test_movetext_starting_with_black() {
+        let state = parse_fen("4k3/8/8/8/8/8/8/4K2R b K - 0 30").unwrap();
+        let moves = [
+            Move::from_long_algebraic("e8d7").unwrap(),
+            Move::from_long_algebraic("e1g1").unwrap(),
+        ];
+        assert_eq!(movetext(&state, &moves), "30...Kd7 31.O-O");
+    }
+
     const EXAMPLE_MULTI_PGN: &'static str = r#"[Event "Superbet Classic 2021"]
 [Site "Bucharest ROU"]
 [Date "2021.06.05"]
@@ -4,6 +4,8 @@ use anyhow::Result;
 use crossbeam_channel::{select, unbounded, Receiver, Sender};
 use std::fmt::Write;
 use std::io::BufRead;
+use std::iter::Peekable;
+use std::str::FromStr;
 use std::sync::RwLock;
 use std::time::Duration;
 
@@ -21,6 +23,16 @@ pub enum Position {
     FenString(String),
 }
 
+impl Position {
+    /// The FEN string describing this position
+    pub fn fen(&self) -> &str {
+        match self {
+            Position::StartPos => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            Position::FenString(fen) => fen,
+        }
+    }
+}
+
 /// The payload for EngineCommand::Go
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct GoCommand {
@@ -194,10 +206,11 @@ pub enum RegistrationMessage {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InfoScore {
     /// The score from the engine's point of view in centipawns
-    pub centipawns: u16,
+    pub centipawns: i32,
 
-    /// Found mate in this many moves (NB moves not plies).
-    pub mate: Option<u16>,
+    /// Found mate in this many moves (NB moves not plies), or negative if the engine is getting
+    /// mated. When given, this takes the place of the centipawn score.
+    pub mate: Option<i32>,
 
     /// The given score is just a lower bound
     pub lowerbound: bool,
@@ -420,6 +433,18 @@ pub enum EngineCommandParseError {
     InvalidCommand(String),
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineMessageParseError {
+    /// The message being parsed was completely empty
+    EmptyMessage,
+
+    /// Didn't recognize the starting keyword of the message
+    UnrecognizedMessage(String),
+
+    /// Recognized the message keyword, but what followed was invalid in some way
+    InvalidMessage(String),
+}
+
 fn parse_setoption(cmd_str: &str) -> Result<UciCommand, EngineCommandParseError> {
     assert!(cmd_str.starts_with("setoption"));
 
@@ -481,33 +506,67 @@ fn parse_register(cmd_str: &str) -> Result<UciCommand, EngineCommandParseError>
     Ok(UciCommand::Register { name, code })
 }
 
-fn parse_position(cmd_str: &str) -> Result<UciCommand, EngineCommandParseError> {
-    let mut parts = cmd_str.split_ascii_whitespace();
+/// Parse a "position" command with its moves given in SAN rather than long algebraic notation, as
+/// sent by some non-standard tools or pasted from a game score, eg
+/// `position startpos moves e4 e5 Nf3`.
+///
+/// Each move is resolved against the position reached by the moves before it, so the resulting
+/// `UciCommand::Position` is the same as if the moves had been given in long algebraic notation.
+pub fn parse_position_san(cmd_str: &str) -> Result<UciCommand, EngineCommandParseError> {
+    if cmd_str.split_ascii_whitespace().next() != Some("position") {
+        return Err(EngineCommandParseError::UnrecognizedCommand(
+            cmd_str.to_string(),
+        ));
+    }
+
+    parse_position(cmd_str, true)
+}
+
+fn parse_san_moves<'a>(
+    position: &Position,
+    move_strs: impl Iterator<Item = &'a str>,
+) -> Option<Vec<Move>> {
+    let mut state = crate::io::fen::parse_fen(position.fen()).ok()?;
+    move_strs
+        .map(|move_str| {
+            let m = crate::io::pgn::parse_san_move(&state, move_str).ok()?;
+            state = state.apply_move(m);
+            Some(m)
+        })
+        .collect()
+}
+
+fn parse_position(cmd_str: &str, san: bool) -> Result<UciCommand, EngineCommandParseError> {
+    let mut parts = cmd_str.split_ascii_whitespace().peekable();
 
     assert_eq!(parts.next(), Some("position"));
 
     let position = match parts.next() {
         Some("startpos") => Position::StartPos,
         Some("fen") => {
-            // A valid FEN string has 6 whitespace separated components
-            let mut fen_str = String::new();
-            for idx in 0..6 {
-                match parts.next() {
-                    Some(part) => fen_str.push_str(part),
-                    None => Err(EngineCommandParseError::InvalidCommand(cmd_str.to_string()))?,
+            // A valid FEN string has 6 whitespace separated components, though the trailing
+            // halfmove/fullmove counters are commonly omitted.
+            let mut fen_parts = Vec::with_capacity(6);
+            while fen_parts.len() < 6 {
+                match parts.peek() {
+                    Some(&"moves") | None => break,
+                    Some(part) => fen_parts.push(*part),
                 }
+                parts.next();
+            }
 
-                if idx < 5 {
-                    fen_str.push(' ');
-                }
+            if fen_parts.len() < 4 {
+                Err(EngineCommandParseError::InvalidCommand(cmd_str.to_string()))?;
             }
 
-            Position::FenString(fen_str)
+            Position::FenString(fen_parts.join(" "))
         }
         _ => Err(EngineCommandParseError::InvalidCommand(cmd_str.to_string()))?,
     };
 
     let moves = match parts.next() {
+        Some("moves") if san => parse_san_moves(&position, parts)
+            .ok_or_else(|| EngineCommandParseError::InvalidCommand(cmd_str.to_string()))?,
         Some("moves") => parts
             .map(|p| Move::from_long_algebraic(p))
             .collect::<Result<_, _>>()
@@ -584,9 +643,10 @@ pub fn parse_command(cmd_str: &str) -> Result<UciCommand, EngineCommandParseErro
     let cmd = match parts.next() {
         Some("uci") => UciCommand::Uci,
         Some("debug") => {
+            // The UCI spec has "debug [ on | off ]", but "true" and "false" are accepted too
             let arg = match parts.next() {
-                Some("true") => true,
-                Some("false") => false,
+                Some("on" | "true") => true,
+                Some("off" | "false") => false,
                 _ => Err(invalid_cmd())?,
             };
             UciCommand::Debug(arg)
@@ -595,7 +655,7 @@ pub fn parse_command(cmd_str: &str) -> Result<UciCommand, EngineCommandParseErro
         Some("setoption") => parse_setoption(cmd_str)?,
         Some("register") => parse_register(cmd_str)?,
         Some("ucinewgame") => UciCommand::UciNewGame,
-        Some("position") => parse_position(cmd_str)?,
+        Some("position") => parse_position(cmd_str, false)?,
         Some("go") => parse_go(cmd_str)?,
         Some("stop") => UciCommand::Stop,
         Some("ponderhit") => UciCommand::PonderHit,
@@ -638,9 +698,9 @@ fn format_info_message(msg: InfoMessage) -> String {
     }
 
     if let Some(x) = msg.score {
-        write!(out, " score {}", x.centipawns).unwrap();
-        if let Some(mate) = x.mate {
-            write!(out, " mate {}", mate).unwrap();
+        match x.mate {
+            Some(mate) => write!(out, " score mate {}", mate).unwrap(),
+            None => write!(out, " score cp {}", x.centipawns).unwrap(),
         }
 
         if x.lowerbound {
@@ -756,9 +816,9 @@ pub fn format_message(msg: UciMessage) -> String {
             None => format!("bestmove {:?}", best_move),
         },
         UciMessage::CopyProtection(c) => match c {
-            CopyProtectionMessage::Checking => format!("copprotection checking"),
-            CopyProtectionMessage::Ok => format!("copprotection ok"),
-            CopyProtectionMessage::Error => format!("copprotection error"),
+            CopyProtectionMessage::Checking => format!("copyprotection checking"),
+            CopyProtectionMessage::Ok => format!("copyprotection ok"),
+            CopyProtectionMessage::Error => format!("copyprotection error"),
         },
         UciMessage::Registration(r) => match r {
             RegistrationMessage::Checking => format!("registration checking"),
@@ -770,6 +830,234 @@ pub fn format_message(msg: UciMessage) -> String {
     }
 }
 
+/// Chomp tokens until the first one that isn't a valid algebraic move
+fn take_moves<'a>(parts: &mut Peekable<impl Iterator<Item = &'a str>>) -> Vec<Move> {
+    let mut moves = Vec::new();
+    while let Some(m) = parts
+        .peek()
+        .and_then(|tok| Move::from_long_algebraic(tok).ok())
+    {
+        moves.push(m);
+        parts.next();
+    }
+    moves
+}
+
+fn parse_info_message(msg_str: &str) -> Result<UciMessage, EngineMessageParseError> {
+    let invalid_msg = || EngineMessageParseError::InvalidMessage(msg_str.to_string());
+
+    fn parse_num<'a, T: FromStr>(parts: &mut impl Iterator<Item = &'a str>) -> Option<T> {
+        parts.next()?.parse().ok()
+    }
+
+    // The string takes up the rest of the line, so has to be split off before tokenizing
+    let (msg_str, string) = match msg_str.find(" string ") {
+        Some(idx) => (&msg_str[..idx], Some(&msg_str[(idx + " string ".len())..])),
+        None => (msg_str, None),
+    };
+
+    let mut parts = msg_str.split_ascii_whitespace().peekable();
+    assert_eq!(parts.next(), Some("info"));
+
+    let mut info = InfoMessage {
+        string: string.map(|s| s.to_string()),
+        ..InfoMessage::default()
+    };
+    while let Some(tok) = parts.next() {
+        match tok {
+            "depth" => info.depth = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "seldepth" => {
+                info.selective_depth = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?)
+            }
+            "time" => {
+                let millis = parse_num(&mut parts).ok_or_else(invalid_msg)?;
+                info.time = Some(Duration::from_millis(millis));
+            }
+            "nodes" => info.nodes = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "pv" => info.principal_variation = Some(take_moves(&mut parts)),
+            "multipv" => info.multipv = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "score" => {
+                let mut score = InfoScore {
+                    centipawns: 0,
+                    mate: None,
+                    lowerbound: false,
+                    upperbound: false,
+                };
+
+                // Accept a bare centipawn value as well as the usual "score cp 20"
+                if parts.peek() == Some(&"cp") {
+                    parts.next();
+                }
+                if let Some(centipawns) = parts.peek().and_then(|tok| tok.parse().ok()) {
+                    score.centipawns = centipawns;
+                    parts.next();
+                }
+
+                while let Some(&tok) = parts.peek() {
+                    match tok {
+                        "mate" => {
+                            parts.next();
+                            score.mate = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?);
+                        }
+                        "lowerbound" => {
+                            parts.next();
+                            score.lowerbound = true;
+                        }
+                        "upperbound" => {
+                            parts.next();
+                            score.upperbound = true;
+                        }
+                        _ => break,
+                    }
+                }
+
+                info.score = Some(score);
+            }
+            "currmove" => {
+                let m = parts.next().ok_or_else(invalid_msg)?;
+                info.curr_move = Some(Move::from_long_algebraic(m).map_err(|_| invalid_msg())?);
+            }
+            "currmovenumber" => {
+                info.curr_move_number = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?)
+            }
+            "hashfull" => info.hash_full = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "nps" => info.nodes_per_second = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "tbhits" => info.table_hits = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "sbhits" => info.shredder_hits = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "cpuload" => info.cpu_load = Some(parse_num(&mut parts).ok_or_else(invalid_msg)?),
+            "refutation" => {
+                let mut moves = take_moves(&mut parts).into_iter();
+                info.refutation = Some(InfoRefutation {
+                    refuted_move: moves.next().ok_or_else(invalid_msg)?,
+                    refutation_line: moves.collect(),
+                });
+            }
+            "currline" => {
+                let cpu_number = parts.peek().and_then(|tok| tok.parse().ok());
+                if cpu_number.is_some() {
+                    parts.next();
+                }
+                info.current_line = Some(InfoCurrLine {
+                    cpu_number,
+                    line: take_moves(&mut parts),
+                });
+            }
+            _ => Err(invalid_msg())?,
+        }
+    }
+
+    Ok(UciMessage::Info(info))
+}
+
+fn parse_option_message(msg_str: &str) -> Result<UciMessage, EngineMessageParseError> {
+    let invalid_msg = || EngineMessageParseError::InvalidMessage(msg_str.to_string());
+
+    let mut parts = msg_str.split_ascii_whitespace();
+    assert_eq!(parts.next(), Some("option"));
+
+    // Group the tokens by the keyword that precedes them, as names and values can contain spaces
+    let mut fields: Vec<(&str, Vec<&str>)> = Vec::new();
+    for tok in parts {
+        match tok {
+            "name" | "type" | "default" | "min" | "max" | "var" => fields.push((tok, Vec::new())),
+            _ => match fields.last_mut() {
+                Some((_keyword, values)) => values.push(tok),
+                None => Err(invalid_msg())?,
+            },
+        }
+    }
+
+    let mut option_name = None;
+    let mut option_type = None;
+    let mut msg = OptionMessage {
+        option_name: String::new(),
+        option_type: OptionType::Check,
+        default: None,
+        min: None,
+        max: None,
+        combo_options: None,
+    };
+    for (keyword, values) in fields {
+        let value = values.join(" ");
+        match keyword {
+            "name" => option_name = Some(value),
+            "type" => {
+                option_type = Some(match value.as_str() {
+                    "check" => OptionType::Check,
+                    "spin" => OptionType::Spin,
+                    "combo" => OptionType::Combo,
+                    "button" => OptionType::Button,
+                    "string" => OptionType::String,
+                    _ => Err(invalid_msg())?,
+                })
+            }
+            "default" => msg.default = Some(value),
+            "min" => msg.min = Some(value.parse().map_err(|_| invalid_msg())?),
+            "max" => msg.max = Some(value.parse().map_err(|_| invalid_msg())?),
+            "var" => msg.combo_options.get_or_insert_with(Vec::new).push(value),
+            _ => unreachable!(),
+        }
+    }
+
+    msg.option_name = option_name.ok_or_else(invalid_msg)?;
+    msg.option_type = option_type.ok_or_else(invalid_msg)?;
+    Ok(UciMessage::Option(msg))
+}
+
+/// Parse a message sent by an engine, the inverse of `format_message`.
+///
+/// This is what an interface talking to a UCI engine needs, eg for running matches between
+/// engines.
+pub fn parse_message(msg_str: &str) -> Result<UciMessage, EngineMessageParseError> {
+    let invalid_msg = || EngineMessageParseError::InvalidMessage(msg_str.to_string());
+    let parse_move = |s: &str| Move::from_long_algebraic(s).map_err(|_| invalid_msg());
+
+    let msg_str = msg_str.trim();
+    let mut parts = msg_str.split_ascii_whitespace();
+
+    let msg = match parts.next() {
+        Some("id") => match (parts.next(), msg_str.splitn(3, ' ').nth(2)) {
+            (Some("name"), Some(name)) => UciMessage::Id(EngineId::Name(name.to_string())),
+            (Some("author"), Some(author)) => UciMessage::Id(EngineId::Author(author.to_string())),
+            _ => Err(invalid_msg())?,
+        },
+        Some("uciok") => UciMessage::UciOk,
+        Some("readyok") => UciMessage::ReadyOk,
+        Some("bestmove") => {
+            let best_move = parse_move(parts.next().ok_or_else(invalid_msg)?)?;
+            let ponder_move = match (parts.next(), parts.next()) {
+                (Some("ponder"), Some(m)) => Some(parse_move(m)?),
+                (None, _) => None,
+                _ => Err(invalid_msg())?,
+            };
+            UciMessage::BestMove {
+                best_move,
+                ponder_move,
+            }
+        }
+        Some("copyprotection") => match parts.next() {
+            Some("checking") => UciMessage::CopyProtection(CopyProtectionMessage::Checking),
+            Some("ok") => UciMessage::CopyProtection(CopyProtectionMessage::Ok),
+            Some("error") => UciMessage::CopyProtection(CopyProtectionMessage::Error),
+            _ => Err(invalid_msg())?,
+        },
+        Some("registration") => match parts.next() {
+            Some("checking") => UciMessage::Registration(RegistrationMessage::Checking),
+            Some("ok") => UciMessage::Registration(RegistrationMessage::Ok),
+            Some("error") => UciMessage::Registration(RegistrationMessage::Error),
+            _ => Err(invalid_msg())?,
+        },
+        Some("info") => parse_info_message(msg_str)?,
+        Some("option") => parse_option_message(msg_str)?,
+        Some(other) => Err(EngineMessageParseError::UnrecognizedMessage(
+            other.to_string(),
+        ))?,
+        None => Err(EngineMessageParseError::EmptyMessage)?,
+    };
+
+    Ok(msg)
+}
+
 pub trait UciOptions: Default {
     type SetOptionError;
 
@@ -786,12 +1074,24 @@ pub struct UciInterface<Options: UciOptions> {
 impl<Options: UciOptions> UciInterface<Options> {
     /// Spawns the IO thread and negotiates intial setup over the interface
     pub fn startup() -> Result<Self> {
+        Self::with_io(std::io::BufReader::new(std::io::stdin()), std::io::stdout())
+    }
+
+    /// Spawns the IO thread, speaking the UCI protocol over the given reader/writer pair instead
+    /// of stdin/stdout.
+    ///
+    /// Reaching the end of the reader is treated as an implicit Quit command.
+    pub fn with_io<R, W>(reader: R, writer: W) -> Result<Self>
+    where
+        R: BufRead + Send + 'static,
+        W: std::io::Write + Send + 'static,
+    {
         let (messages_tx, messages_rx) = unbounded();
         let (commands_tx, commands_rx) = unbounded();
 
         std::thread::Builder::new()
             .name("UCI broker".to_string())
-            .spawn(|| uci_interface_thread(messages_rx, commands_tx))?;
+            .spawn(move || uci_interface_thread(reader, writer, messages_rx, commands_tx))?;
 
         Ok(Self {
             opts: Options::default().into(),
@@ -801,30 +1101,35 @@ impl<Options: UciOptions> UciInterface<Options> {
     }
 }
 
-fn uci_interface_thread(messages_rx: Receiver<UciMessage>, commands_tx: Sender<UciCommand>) {
-    use std::io::Write;
-
-    let stdout = std::io::stdout();
-    let mut stdout_handle = stdout.lock();
-
-    let (stdin_lines_tx, stdin_lines_rx) = unbounded();
+fn uci_interface_thread<R, W>(
+    reader: R,
+    mut writer: W,
+    messages_rx: Receiver<UciMessage>,
+    commands_tx: Sender<UciCommand>,
+) where
+    R: BufRead + Send + 'static,
+    W: std::io::Write,
+{
+    let (input_lines_tx, input_lines_rx) = unbounded();
 
     // TODO: clean up this thread on shutdown explicitly.
     std::thread::Builder::new()
         .name("UCI reader".to_string())
         .spawn(move || {
-            let stdin = std::io::stdin();
-            let stdin_handle = stdin.lock();
-            let mut lines = stdin_handle.lines();
+            let mut lines = reader.lines();
             while let Some(Ok(line)) = lines.next() {
                 log::debug!("UCI rx: {}", line);
-                stdin_lines_tx
+                input_lines_tx
                     .send(line)
                     .expect("Error pushing raw UCI command to internal channel");
             }
         })
         .expect("Failed to start UCI reader thread");
 
+    // Replaced with a channel that never receives anything once the input is exhausted, so that
+    // responses to the final commands are still written out.
+    let mut input_lines_rx = input_lines_rx;
+
     loop {
         select! {
             recv(messages_rx) -> msg => {
@@ -837,23 +1142,29 @@ fn uci_interface_thread(messages_rx: Receiver<UciMessage>, commands_tx: Sender<U
                 };
                 let msg = format_message(msg);
                 log::debug!("UCI tx: {}", msg);
-                writeln!(stdout_handle, "{}", msg)
+                writeln!(writer, "{}", msg)
+                    .and_then(|_| writer.flush())
                     .expect("Error sending UCI message to interface");
             },
-            recv(stdin_lines_rx) -> line => {
+            recv(input_lines_rx) -> line => {
                 let line = match line {
                     Ok(line) => line,
                     Err(_) => {
-                        log::info!("EOF received on stdin, sending implicit Quit command and stopping UCI thread");
-                        commands_tx.send(UciCommand::Quit)
-                            .expect("Error pushing UCI command to internal channel");
-                        break;
+                        log::info!("EOF received on UCI input, sending implicit Quit command");
+                        if commands_tx.send(UciCommand::Quit).is_err() {
+                            // The engine has already gone away, eg after an explicit quit
+                            break;
+                        }
+                        input_lines_rx = crossbeam_channel::never();
+                        continue;
                     }
                 };
                 if let Ok(cmd) = parse_command(&line) {
                     log::debug!("Received command {:?}", cmd);
-                    commands_tx.send(cmd)
-                        .expect("Error pushing UCI command to internal channel")
+                    if commands_tx.send(cmd).is_err() {
+                        log::info!("UCI commands channel disconnected, shutting down UCI threads");
+                        break;
+                    }
                 }
             }
         }
@@ -873,6 +1184,9 @@ mod tests {
     fn test_parse_debug() {
         assert_eq!(parse_command("debug true"), Ok(UciCommand::Debug(true)));
         assert_eq!(parse_command("debug false"), Ok(UciCommand::Debug(false)));
+        assert_eq!(parse_command("debug on"), Ok(UciCommand::Debug(true)));
+        assert_eq!(parse_command("debug off"), Ok(UciCommand::Debug(false)));
+        assert!(parse_command("debug maybe").is_err());
     }
 
     #[test]
@@ -970,6 +1284,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_position_san() {
+        let lan = |moves: &[&str]| {
+            moves
+                .iter()
+                .map(|m| Move::from_long_algebraic(m).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            parse_position_san("position startpos moves e4 e5 Nf3"),
+            Ok(UciCommand::Position {
+                position: Position::StartPos,
+                moves: lan(&["e2e4", "e7e5", "g1f3"]),
+            })
+        );
+        assert_eq!(
+            parse_position_san("position startpos moves e4 e5 Nf3"),
+            parse_command("position startpos moves e2e4 e7e5 g1f3")
+        );
+
+        let example_fen = "7k/2P5/3p4/7r/K7/8/8/8 w - - 0 1".to_string();
+        assert_eq!(
+            parse_position_san(&format!("position fen {} moves c8=Q+ Kg7", &example_fen)),
+            Ok(UciCommand::Position {
+                position: Position::FenString(example_fen.clone()),
+                moves: lan(&["c7c8q", "h8g7"]),
+            })
+        );
+
+        assert_eq!(
+            parse_position_san("position startpos"),
+            parse_command("position startpos")
+        );
+
+        // Moves that can't be played in the position they're reached in are rejected
+        assert_eq!(
+            parse_position_san("position startpos moves e4 e4"),
+            Err(EngineCommandParseError::InvalidCommand(
+                "position startpos moves e4 e4".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_position_san("go depth 3"),
+            Err(EngineCommandParseError::UnrecognizedCommand(
+                "go depth 3".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_position_without_clocks() {
+        let short_fen = "7k/2P5/3p4/7r/K7/8/8/8 w - -".to_string();
+        let cmd = parse_command(&format!("position fen {} moves c7c8q", &short_fen));
+        assert_eq!(
+            cmd,
+            Ok(UciCommand::Position {
+                position: Position::FenString(short_fen.clone()),
+                moves: vec![Move::from_long_algebraic("c7c8q").unwrap()],
+            })
+        );
+
+        let state = crate::io::fen::parse_fen(&short_fen)
+            .expect("Expected a FEN string without move counters to parse");
+        assert_eq!(state.halfmove_clock, 0);
+        assert_eq!(state.fullmove_counter, 1);
+
+        assert_eq!(
+            parse_command("position fen 7k/2P5/3p4/7r/K7/8/8/8 w moves c7c8q"),
+            Err(EngineCommandParseError::InvalidCommand(
+                "position fen 7k/2P5/3p4/7r/K7/8/8/8 w moves c7c8q".to_string()
+            ))
+        );
+    }
+
     #[test]
     fn test_parse_go() {
         assert_eq!(
@@ -1020,4 +1409,144 @@ mod tests {
     fn test_parse_quit() {
         assert_eq!(parse_command("quit"), Ok(UciCommand::Quit));
     }
+
+    fn lan(m: &str) -> Move {
+        Move::from_long_algebraic(m).unwrap()
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let messages = vec![
+            UciMessage::Id(EngineId::Name("pewter".to_string())),
+            UciMessage::Id(EngineId::Author("Joe Roberts".to_string())),
+            UciMessage::UciOk,
+            UciMessage::ReadyOk,
+            UciMessage::BestMove {
+                best_move: lan("e2e4"),
+                ponder_move: None,
+            },
+            UciMessage::BestMove {
+                best_move: lan("a7a8q"),
+                ponder_move: Some(lan("h2h1n")),
+            },
+            UciMessage::CopyProtection(CopyProtectionMessage::Ok),
+            UciMessage::Registration(RegistrationMessage::Checking),
+            UciMessage::Info(InfoMessage {
+                depth: Some(12),
+                selective_depth: Some(18),
+                time: Some(Duration::from_millis(1234)),
+                nodes: Some(987654),
+                principal_variation: Some(vec![lan("e2e4"), lan("e7e5"), lan("g1f3")]),
+                score: Some(InfoScore {
+                    centipawns: -35,
+                    mate: None,
+                    lowerbound: true,
+                    upperbound: false,
+                }),
+                nodes_per_second: Some(800000),
+                hash_full: Some(250),
+                ..InfoMessage::default()
+            }),
+            UciMessage::Info(InfoMessage {
+                score: Some(InfoScore {
+                    centipawns: 0,
+                    mate: Some(-3),
+                    lowerbound: false,
+                    upperbound: false,
+                }),
+                curr_move: Some(lan("d2d4")),
+                curr_move_number: Some(2),
+                refutation: Some(InfoRefutation {
+                    refuted_move: lan("d1h5"),
+                    refutation_line: vec![lan("g7g6")],
+                }),
+                current_line: Some(InfoCurrLine {
+                    cpu_number: Some(1),
+                    line: vec![lan("d2d4"), lan("d7d5")],
+                }),
+                string: Some("some  spaced out string".to_string()),
+                ..InfoMessage::default()
+            }),
+            UciMessage::Option(OptionMessage {
+                option_name: "Hash".to_string(),
+                option_type: OptionType::Spin,
+                default: Some("16".to_string()),
+                min: Some(1),
+                max: Some(1024),
+                combo_options: None,
+            }),
+            UciMessage::Option(OptionMessage {
+                option_name: "Play Style".to_string(),
+                option_type: OptionType::Combo,
+                default: Some("Very Solid".to_string()),
+                min: None,
+                max: None,
+                combo_options: Some(vec!["Very Solid".to_string(), "Risky".to_string()]),
+            }),
+        ];
+
+        for msg in messages {
+            let formatted = format_message(msg.clone());
+            assert_eq!(parse_message(&formatted), Ok(msg), "{}", formatted);
+        }
+
+        assert_eq!(
+            format_message(UciMessage::CopyProtection(CopyProtectionMessage::Error)),
+            "copyprotection error"
+        );
+    }
+
+    #[test]
+    fn test_parse_message() {
+        assert_eq!(
+            parse_message("info depth 10 seldepth 14 score cp 35 nodes 12345 pv e2e4 e7e5"),
+            Ok(UciMessage::Info(InfoMessage {
+                depth: Some(10),
+                selective_depth: Some(14),
+                score: Some(InfoScore {
+                    centipawns: 35,
+                    mate: None,
+                    lowerbound: false,
+                    upperbound: false,
+                }),
+                nodes: Some(12345),
+                principal_variation: Some(vec![lan("e2e4"), lan("e7e5")]),
+                ..InfoMessage::default()
+            }))
+        );
+
+        assert_eq!(
+            parse_message(""),
+            Err(EngineMessageParseError::EmptyMessage)
+        );
+        assert_eq!(
+            parse_message("hello"),
+            Err(EngineMessageParseError::UnrecognizedMessage(
+                "hello".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_message("bestmove e2"),
+            Err(EngineMessageParseError::InvalidMessage(
+                "bestmove e2".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_message("info depth ten"),
+            Err(EngineMessageParseError::InvalidMessage(
+                "info depth ten".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_interface_thread_exits_once_engine_has_quit() {
+        // The engine hangs up after the explicit quit, before the client closes its end
+        let (_messages_tx, messages_rx) = unbounded();
+        let (commands_tx, commands_rx) = unbounded();
+        drop(commands_rx);
+
+        let input = std::io::Cursor::new(b"quit\n".to_vec());
+        uci_interface_thread(input, Vec::new(), messages_rx, commands_tx);
+    }
 }
@@ -1,3 +1,4 @@
+pub mod adjudicator;
 pub mod bitboard;
 pub mod board;
 pub mod chessmove;
@@ -6,12 +7,14 @@ pub mod coordinates;
 pub mod io;
 pub mod movegen;
 pub mod piece;
+pub mod psqt;
 pub mod state;
+pub mod tactics;
 pub mod zobrist;
 
 pub use crate::bitboard::BitBoard;
 pub use crate::board::Board;
-pub use crate::chessmove::{Move, MoveSet};
+pub use crate::chessmove::{Move, MoveKind, MoveSet};
 pub use crate::color::Color;
 pub use crate::coordinates::{BoardPos, File, Rank};
 pub use crate::piece::Piece;
@@ -0,0 +1,115 @@
+use crate::{bitboard::masks, BitBoard, BoardPos, Color, Piece, State};
+
+use super::pseudo_legal;
+
+/// The set of `color` pieces that attack the given position.
+///
+/// A piece attacks a square if it could capture an enemy piece standing there, regardless of what
+/// actually occupies the square, so pieces defending a friendly piece count as attackers. Pins and
+/// checks are ignored.
+///
+/// Sliding pieces only attack along unobstructed lines - a rook stacked behind another rook on the
+/// same file does not attack through the first one (no x-rays).
+pub fn attackers_of(state: &State, pos: BoardPos, color: Color) -> BitBoard {
+    let board = &state.board;
+    let pieces = board.color_board(color);
+    let occupied = board.all_union_board();
+
+    let rooks = board
+        .piece_board(Piece::Rook)
+        .union_with(board.piece_board(Piece::Queen))
+        .intersect_with(pieces)
+        .intersect_with(masks::rook_rays(pos));
+
+    let bishops = board
+        .piece_board(Piece::Bishop)
+        .union_with(board.piece_board(Piece::Queen))
+        .intersect_with(pieces)
+        .intersect_with(masks::bishop_rays(pos));
+
+    let mut attackers = BitBoard::new_empty();
+    for slider in rooks.union_with(bishops).iter_set() {
+        if !masks::between(pos, slider).intersect_with(occupied).any() {
+            attackers.set(slider);
+        }
+    }
+
+    let knights = board
+        .color_piece_board(color, Piece::Knight)
+        .intersect_with(masks::knight_moves(pos));
+
+    // The squares that a pawn on `pos` of the opposite color would attack are exactly the squares
+    // that a pawn of this color could attack `pos` from.
+    let pawns = board
+        .color_piece_board(color, Piece::Pawn)
+        .intersect_with(masks::pawn_attacks(!color, pos));
+
+    let kings = board
+        .color_piece_board(color, Piece::King)
+        .intersect_with(pseudo_legal::king_moves(pos, BitBoard::new_empty()));
+
+    attackers
+        .union_with(knights)
+        .union_with(pawns)
+        .union_with(kings)
+}
+
+/// For each square, indexed by its bitboard offset, the number of `color` pieces attacking it.
+///
+/// Follows the same rules as `attackers_of`, so blocked sliding pieces are not counted.
+pub fn attack_counts(state: &State, color: Color) -> [u8; 64] {
+    let mut counts = [0; 64];
+    for (offset, count) in counts.iter_mut().enumerate() {
+        let pos = BoardPos::from_bitboard_offset(offset as u8);
+        *count = attackers_of(state, pos, color).count();
+    }
+    counts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::coordinates::consts::*;
+    use crate::io::fen::parse_fen;
+
+    fn count_at(counts: &[u8; 64], pos: BoardPos) -> u8 {
+        counts[pos.to_bitboard_offset() as usize]
+    }
+
+    #[test]
+    fn test_attack_counts_starting() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+            .expect("Expect test case FEN to be correct");
+
+        let white = attack_counts(&state, Color::White);
+        assert_eq!(count_at(&white, F3), 3);
+        assert_eq!(count_at(&white, D3), 2);
+        assert_eq!(count_at(&white, A3), 2);
+        assert_eq!(count_at(&white, D2), 4);
+        assert_eq!(count_at(&white, E4), 0);
+        assert_eq!(count_at(&white, A1), 0);
+
+        let black = attack_counts(&state, Color::Black);
+        assert_eq!(count_at(&black, F6), 3);
+        assert_eq!(count_at(&black, F3), 0);
+    }
+
+    #[test]
+    fn test_attack_counts_no_xray() {
+        let state = parse_fen("4k3/8/8/8/8/8/R7/R3K3 w - - 0 1")
+            .expect("Expect test case FEN to be correct");
+
+        let white = attack_counts(&state, Color::White);
+        // Only the front rook sees up the file, but both rooks see each other
+        assert_eq!(count_at(&white, A3), 1);
+        assert_eq!(count_at(&white, A8), 1);
+        assert_eq!(count_at(&white, A2), 1);
+        assert_eq!(count_at(&white, A1), 1);
+        // The back rook and the king both attack d1
+        assert_eq!(count_at(&white, D1), 2);
+        assert_eq!(
+            attackers_of(&state, D1, Color::White),
+            crate::squares![A1, E1]
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    bitboard::masks, chessmove::MoveSetChunk, BitBoard, BoardPos, CastleSide, MoveSet, Piece, State,
+    bitboard::masks, chessmove::MoveSetChunk, BitBoard, BoardPos, CastleSide, File, Move, MoveSet,
+    Piece, State,
 };
 
 use super::pseudo_legal;
@@ -27,12 +28,7 @@ pub fn legal_moves(state: &State) -> MoveSet {
     // With zero or one opposing pieces giving check, it is possible for any non-pinned piece to
     // have legal moves.
     if checker_count <= 1 {
-        // If currently in check, this mask is the set of positions that a legal move could land on,
-        // such that it either blocks or captures the single piece giving check.
-        let check_mask = match state.checkers.first_set() {
-            Some(pos) => masks::between(pos, k_pos).union_with(state.checkers),
-            None => BitBoard::new_empty().inverse(),
-        };
+        let check_mask = check_mask(state);
 
         let non_pinned_color_mask = state
             .board
@@ -65,6 +61,113 @@ pub fn legal_moves(state: &State) -> MoveSet {
     move_set
 }
 
+/// The legal moves of just the friendly piece on the given square, eg for highlighting where a
+/// piece picked up in a UI can be dropped.
+///
+/// Empty if there is no piece belonging to the player to move on that square.
+pub fn legal_moves_from(state: &State, source: BoardPos) -> MoveSetChunk {
+    let piece = match state.board.get(source) {
+        Some((color, piece)) if color == state.to_play => piece,
+        _ => return MoveSetChunk::new_empty(source),
+    };
+
+    let checker_count = state.checkers.count();
+    let pinned = state.pinned.get(source);
+    if (checker_count > 1 && piece != Piece::King) || (checker_count > 0 && pinned) {
+        return MoveSetChunk::new_empty(source);
+    }
+
+    let mut chunk = legal_move_chunk(state, piece, source, check_mask(state));
+    if pinned {
+        let k_pos = state.king_pos(state.to_play);
+        chunk.dest_set.intersect_inplace(masks::line(source, k_pos));
+    }
+
+    chunk
+}
+
+/// The legal moves that capture a piece, including en-passant captures and promotions that
+/// capture.
+pub fn legal_captures(state: &State) -> MoveSet {
+    let enemies = state.board.color_board(!state.to_play);
+    let pawns = state.board.color_piece_board(state.to_play, Piece::Pawn);
+
+    legal_moves(state)
+        .all_chunks()
+        .map(|&chunk| {
+            let mut targets = enemies;
+            if let Some(ep) = state.en_passant.filter(|_| pawns.get(chunk.source)) {
+                targets.set(ep);
+            }
+            MoveSetChunk {
+                dest_set: chunk.dest_set.intersect_with(targets),
+                ..chunk
+            }
+        })
+        .filter(|chunk| chunk.any())
+        .collect()
+}
+
+/// A legal move along with some properties of it that are commonly needed when ordering or
+/// displaying moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnnotatedMove {
+    pub m: Move,
+
+    /// Whether the move captures a piece, including by en passant
+    pub is_capture: bool,
+
+    /// Whether the move puts the opponent in check
+    pub gives_check: bool,
+
+    pub is_castle: bool,
+}
+
+/// Every legal move, annotated with whether it is a capture, gives check, or castles.
+///
+/// The captures and castles fall out of each chunk as it is generated. Finding checks means
+/// playing out each move, though without working out the pins of the resulting position.
+pub fn legal_moves_annotated(state: &State) -> Vec<AnnotatedMove> {
+    let us = state.to_play;
+    let enemies = state.board.color_board(!us);
+
+    let mut annotated = Vec::new();
+    for &chunk in legal_moves(state).all_chunks() {
+        let piece = state
+            .board
+            .piece_kind_at(chunk.source)
+            .expect("Expected a piece at the source of every legal move chunk");
+
+        let mut captures = enemies;
+        if let Some(ep) = state.en_passant.filter(|_| piece == Piece::Pawn) {
+            captures.set(ep);
+        }
+
+        for m in chunk.iter() {
+            annotated.push(AnnotatedMove {
+                m,
+                is_capture: captures[m.to],
+                gives_check: state.apply_move_no_legality(m).board.is_king_attacked(!us),
+                // The king can only ever move two files at once by castling
+                is_castle: piece == Piece::King
+                    && m.from.file.to_num().abs_diff(m.to.file.to_num()) == 2,
+            });
+        }
+    }
+
+    annotated
+}
+
+/// If currently in check, the set of positions that a legal move could land on, such that it
+/// either blocks or captures the single piece giving check.
+fn check_mask(state: &State) -> BitBoard {
+    let k_pos = state.king_pos(state.to_play);
+    match state.checkers.first_set() {
+        Some(pos) => masks::between(pos, k_pos).union_with(state.checkers),
+        None => BitBoard::new_empty().inverse(),
+    }
+}
+
 fn legal_move_chunk(
     state: &State,
     piece: Piece,
@@ -234,3 +337,70 @@ fn king_special(state: &State, k_pos: BoardPos, chunk: &mut MoveSetChunk) {
         }
     }
 }
+
+/// Every legal move in the given state, in the same order as `MoveSet::sorted_vec`.
+///
+/// Found by trying every pseudo-legal move, including en-passant and castling, and keeping the ones
+/// that don't leave the king in check. Much slower than `legal_moves`, but simple enough to be
+/// obviously correct, so that it can be used to check the real move generator.
+pub fn legal_moves_reference(state: &State) -> Vec<Move> {
+    let us = state.to_play;
+    let mut candidates = pseudo_legal::all_pseudo_legal(state)
+        .iter()
+        .collect::<Vec<_>>();
+
+    // En-passant captures by any pawn attacking the en-passant square
+    if let Some(ep) = state.en_passant {
+        let pawns = masks::pawn_attacks(!us, ep)
+            .intersect_with(state.board.color_piece_board(us, Piece::Pawn));
+        for from in pawns.iter_set() {
+            candidates.push(Move {
+                from,
+                to: ep,
+                promotion: None,
+            });
+        }
+    }
+
+    // Castling, where the squares between the king and rook must be empty, and the king may not
+    // start on, pass through, or land on an attacked square
+    let back_rank = |file| BoardPos::from_file_rank(file, us.numbered_rank(1));
+    let castles: [(CastleSide, &[File], [File; 3]); 2] = [
+        (
+            CastleSide::Kingside,
+            &[File::F, File::G],
+            [File::E, File::F, File::G],
+        ),
+        (
+            CastleSide::Queenside,
+            &[File::B, File::C, File::D],
+            [File::E, File::D, File::C],
+        ),
+    ];
+    for (side, empty, king_path) in castles.iter() {
+        let allowed = state.castle_rights.get(us, *side)
+            && empty
+                .iter()
+                .all(|&f| state.board.get(back_rank(f)).is_none())
+            && king_path
+                .iter()
+                .all(|&f| !state.is_square_attacked(back_rank(f), !us));
+        if allowed {
+            candidates.push(Move {
+                from: back_rank(File::E),
+                to: back_rank(king_path[2]),
+                promotion: None,
+            });
+        }
+    }
+
+    let mut moves = candidates
+        .into_iter()
+        .filter(|&m| {
+            let next = state.apply_move(m);
+            !next.board.is_king_attacked(us)
+        })
+        .collect::<Vec<_>>();
+    moves.sort_unstable();
+    moves
+}
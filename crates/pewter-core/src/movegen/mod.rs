@@ -1,12 +1,21 @@
+pub mod attacks;
 pub mod bmi;
 pub mod legal;
 pub mod pseudo_legal;
 
-pub use legal::legal_moves;
+pub use attacks::{attack_counts, attackers_of};
+pub use legal::{legal_captures, legal_moves, legal_moves_annotated, AnnotatedMove};
+
+use std::collections::HashSet;
+
+use crate::zobrist::ZobristHash;
 
 pub fn perft(state: crate::State, depth: u8) -> usize {
     match depth {
         0 => 1,
+        // Counting the leaves directly means promotions never need expanding into their four
+        // moves here. Above this each promotion leads to a different position, so there is nothing
+        // to share between them.
         1 => legal::legal_moves(&state).len(),
         _ => legal::legal_moves(&state)
             .iter()
@@ -15,12 +24,47 @@ pub fn perft(state: crate::State, depth: u8) -> usize {
     }
 }
 
+/// The perft count alongside the number of distinct positions among those leaves, as
+/// (total_leaves, unique_leaves), showing how much the tree transposes into itself at this depth.
+///
+/// Leaves are told apart by the incrementally updated zobrist hash of each state, the same hash
+/// that the search's transposition table sees.
+pub fn perft_unique(state: crate::State, depth: u8) -> (usize, usize) {
+    fn visit_leaves(
+        state: crate::State,
+        depth: u8,
+        total: &mut usize,
+        unique: &mut HashSet<ZobristHash>,
+    ) {
+        if depth == 0 {
+            *total += 1;
+            unique.insert(state.zobrist);
+            return;
+        }
+
+        for m in legal::legal_moves(&state).iter() {
+            visit_leaves(state.apply_move(m), depth - 1, total, unique);
+        }
+    }
+
+    let mut total = 0;
+    let mut unique = HashSet::new();
+    visit_leaves(state, depth, &mut total, &mut unique);
+    (total, unique.len())
+}
+
+/// The perft count beneath each legal move, sorted by move
 pub fn perft_breakdown(state: crate::State, depth: u8) -> Vec<(crate::Move, usize)> {
     match depth {
         0 => vec![],
-        1 => legal::legal_moves(&state).iter().map(|m| (m, 1)).collect(),
+        1 => legal::legal_moves(&state)
+            .sorted_vec()
+            .into_iter()
+            .map(|m| (m, 1))
+            .collect(),
         _ => legal::legal_moves(&state)
-            .iter()
+            .sorted_vec()
+            .into_iter()
             .map(|m| (m, perft(state.apply_move(m), depth - 1)))
             .collect(),
     }
@@ -30,10 +74,60 @@ pub fn perft_breakdown(state: crate::State, depth: u8) -> Vec<(crate::Move, usiz
 mod tests {
     use std::time::Instant;
 
+    use proptest::collection::vec;
+    use proptest::prelude::{any, ProptestConfig};
+    use proptest::proptest;
+
     use crate::io::fen::parse_fen;
+    use crate::{Move, Piece};
 
     use super::*;
 
+    fn brute_force_perft(state: crate::State, depth: u8) -> usize {
+        match depth {
+            0 => 1,
+            _ => legal::legal_moves_reference(&state)
+                .into_iter()
+                .map(|m| brute_force_perft(state.apply_move(m), depth - 1))
+                .sum(),
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(8))]
+
+        #[test]
+        fn test_perft_matches_brute_force(
+            start in 0..3usize,
+            move_choices in vec(any::<usize>(), 0..40),
+        ) {
+            // Positions with castling, en-passant, and promotion opportunities
+            let fens = [
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            ];
+
+            // Play random moves to reach an arbitrary legal position
+            let mut state = parse_fen(fens[start]).unwrap();
+            for choice in move_choices {
+                let moves = legal_moves(&state).sorted_vec();
+                if moves.is_empty() {
+                    break;
+                }
+                state = state.apply_move(moves[choice % moves.len()]);
+            }
+
+            let fen = crate::io::fen::format_fen(&state);
+            let expected = legal::legal_moves_reference(&state);
+            assert_eq!(legal_moves(&state).sorted_vec(), expected, "{}", fen);
+
+            for depth in 2..=3 {
+                assert_eq!(perft(state, depth), brute_force_perft(state, depth), "{}", fen);
+            }
+        }
+    }
+
     fn perft_helper_inner(initial_state: crate::State, expected_values: &[usize]) {
         for (depth, expected) in expected_values.iter().enumerate() {
             let depth = depth + 1;
@@ -62,6 +156,208 @@ mod tests {
         perft_helper_inner(initial_state, expected_values);
     }
 
+    #[test]
+    fn test_sorted_moves_starting() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+            .expect("Expected unit test to have valid FEN string");
+
+        // Ordered by source square (rank first, then file), then by destination square
+        let expected = [
+            "b1a3", "b1c3", "g1f3", "g1h3", "a2a3", "a2a4", "b2b3", "b2b4", "c2c3", "c2c4", "d2d3",
+            "d2d4", "e2e3", "e2e4", "f2f3", "f2f4", "g2g3", "g2g4", "h2h3", "h2h4",
+        ];
+
+        let sorted = legal_moves(&state).sorted_vec();
+        let sorted_lan = sorted
+            .iter()
+            .map(|m| m.format_long_algebraic())
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_lan, expected);
+
+        // Stable regardless of how many times it is computed
+        assert_eq!(legal_moves(&state).sorted_vec(), sorted);
+
+        let breakdown_moves = perft_breakdown(state, 2)
+            .into_iter()
+            .map(|(m, _count)| m)
+            .collect::<Vec<_>>();
+        assert_eq!(breakdown_moves, sorted);
+    }
+
+    #[test]
+    fn test_legal_moves_match_reference() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1b1k1nr/ppq2p1p/6pb/4p3/2BpP3/5QPP/PPPN4/R3K2R w KQkq - 1 13",
+            "7k/3p4/8/K1P4r/8/8/8/8 w - - 0 1",
+            "8/1p3k2/8/2P5/2K5/8/8/8 b - - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+
+        for fen in fens {
+            let state = parse_fen(fen).unwrap();
+            let reference = legal::legal_moves_reference(&state);
+            assert_eq!(legal_moves(&state).sorted_vec(), reference, "{}", fen);
+
+            // Including every position one move on, to cover the replies to each move
+            for m in reference {
+                let next = state.apply_move(m);
+                assert_eq!(
+                    legal_moves(&next).sorted_vec(),
+                    legal::legal_moves_reference(&next),
+                    "{} {}",
+                    fen,
+                    m
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_legal_moves_with_many_pieces() {
+        // A king with a pinned piece on every side of it, and the rest of its army besides
+        let pinned = parse_fen("3r3k/b5b1/P6N/2NQB3/r1RKR2r/2BPQP2/P5PP/b2r2b1 w - - 0 1").unwrap();
+        assert_eq!(pinned.pinned.count(), 8);
+        assert_eq!(legal_moves(&pinned).len(), 23);
+        assert_eq!(
+            legal_moves(&pinned).sorted_vec(),
+            legal::legal_moves_reference(&pinned)
+        );
+
+        // More pieces than any game can reach, each with moves of its own
+        let crowded = parse_fen("NNNNNNNN/NNNNNNNN/NNNN4/8/8/8/8/K5k1 w - - 0 1").unwrap();
+        let moves = legal_moves(&crowded);
+        assert_eq!(moves.len(), moves.iter().count());
+        assert_eq!(moves.sorted_vec(), legal::legal_moves_reference(&crowded));
+    }
+
+    #[test]
+    fn test_move_set_union_and_retain() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+
+        for fen in fens {
+            let state = parse_fen(fen).unwrap();
+            let all = legal_moves(&state);
+
+            let mut captures = all.clone();
+            captures.retain(|m| m.is_capture(&state));
+            assert_eq!(
+                captures.sorted_vec(),
+                legal_captures(&state).sorted_vec(),
+                "{}",
+                fen
+            );
+
+            let mut quiets = all.clone();
+            quiets.retain(|m| !m.is_capture(&state));
+            assert_eq!(captures.len() + quiets.len(), all.len(), "{}", fen);
+
+            // The captures and quiets of each piece are merged back into a single chunk
+            let union = captures.union(quiets);
+            assert_eq!(union.sorted_vec(), all.sorted_vec(), "{}", fen);
+            assert_eq!(
+                union.all_chunks().count(),
+                all.all_chunks().count(),
+                "{}",
+                fen
+            );
+        }
+
+        // Each promotion is kept or dropped on its own
+        let state = parse_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let promotions_to = |piece| {
+            let mut moves = legal_moves(&state);
+            moves.retain(|m| m.promotion == Some(piece));
+            moves
+        };
+        let knights = promotions_to(Piece::Knight);
+        assert_eq!(
+            knights.sorted_vec(),
+            [Move::from_long_algebraic("a7a8n").unwrap()]
+        );
+        assert_eq!(knights.len(), 1);
+
+        // Promotions to the same square are merged back together
+        let union = knights.union(promotions_to(Piece::Queen));
+        assert_eq!(union.len(), 2);
+        assert_eq!(union.all_chunks().count(), 1);
+        assert_eq!(
+            union.iter().collect::<Vec<_>>(),
+            ["a7a8q", "a7a8n"].map(|m| Move::from_long_algebraic(m).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_legal_moves_annotated() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            "3k4/8/8/8/8/8/3P4/R3K3 w Q - 0 1",
+        ];
+
+        let (mut captures, mut checks, mut castles) = (0, 0, 0);
+        for fen in fens {
+            let state = parse_fen(fen).unwrap();
+            let annotated = legal_moves_annotated(&state);
+
+            let mut moves = annotated.iter().map(|a| a.m).collect::<Vec<_>>();
+            moves.sort();
+            assert_eq!(moves, legal_moves(&state).sorted_vec(), "{}", fen);
+
+            for a in annotated {
+                let kind = crate::chessmove::move_kind(&state, a.m);
+                assert_eq!(a.is_capture, kind.is_capture(), "{} {}", fen, a.m);
+                assert_eq!(a.is_castle, kind.is_castle(), "{} {}", fen, a.m);
+                assert_eq!(
+                    a.gives_check,
+                    state.apply_move(a.m).in_check(),
+                    "{} {}",
+                    fen,
+                    a.m
+                );
+
+                captures += a.is_capture as usize;
+                checks += a.gives_check as usize;
+                castles += a.is_castle as usize;
+            }
+        }
+
+        assert!(captures > 0 && checks > 0 && castles > 0);
+    }
+
+    #[test]
+    fn test_perft_unique() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+
+        // Nothing can transpose until one side has made two moves
+        assert_eq!(perft_unique(state, 0), (1, 1));
+        assert_eq!(perft_unique(state, 1), (20, 20));
+        assert_eq!(perft_unique(state, 2), (400, 400));
+
+        // eg 1. Nf3 e5 2. Nc3 and 1. Nc3 e5 2. Nf3 reach the same position
+        for depth in 3..=4 {
+            let (total, unique) = perft_unique(state, depth);
+            assert_eq!(total, perft(state, depth));
+            assert!(
+                unique < total,
+                "depth {}: {} unique of {}",
+                depth,
+                unique,
+                total
+            );
+        }
+    }
+
     #[test]
     fn perft_test_starting() {
         perft_helper(
@@ -97,4 +393,13 @@ mod tests {
         // NB: At depth 2 tests that you are allowed to capture a pawn that is giving check en-passant
         perft_helper("8/1p3k2/8/2P5/2K5/8/8/8 b - - 0 1", &[10, 80, 688])
     }
+
+    #[test]
+    fn perft_test_promotions() {
+        // Pawns for both sides on the verge of promoting, with and without capturing
+        perft_helper(
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            &[24, 496, 9_483, 182_838],
+        )
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    chessmove::MoveSetChunk, BitBoard, BoardPos, Color, File, MoveSet, Piece, Rank, State, bitboard::masks,
+    bitboard::masks, chessmove::MoveSetChunk, BitBoard, BoardPos, Color, MoveSet, Piece, State,
 };
 
 pub fn all_pseudo_legal(state: &State) -> MoveSet {
@@ -43,7 +43,7 @@ pub fn pseudo_legal_moves(state: &State, piece: Piece, source: BoardPos) -> Move
             MoveSetChunk {
                 source,
                 dest_set: r.dest_set.union_with(b.dest_set),
-                promotion: false,
+                promotions: 0,
             }
         }
     }
@@ -70,11 +70,15 @@ fn pawn_psuedo_legal(
         .intersect_with(opp_pieces);
 
     let dest_set = pushes.union_with(attacks);
-    let promotion = source.rank == color.numbered_rank(7);
+    let promotions = if source.rank == color.numbered_rank(7) {
+        MoveSetChunk::ALL_PROMOTIONS
+    } else {
+        0
+    };
     MoveSetChunk {
         source,
         dest_set,
-        promotion,
+        promotions,
     }
 }
 
@@ -85,7 +89,7 @@ fn knight_pseudo_legal(source: BoardPos, our_pieces: BitBoard) -> MoveSetChunk {
     MoveSetChunk {
         source,
         dest_set,
-        promotion: false,
+        promotions: 0,
     }
 }
 
@@ -97,25 +101,8 @@ fn sliding_piece_pseudo_legal(
 ) -> MoveSetChunk {
     let mut chunk = MoveSetChunk::new_empty(source);
 
-    let source_nums = (source.file.to_num() as i8, source.rank.to_num() as i8);
-    for dir in dirs {
-        let mut dest_nums = source_nums.clone();
-        loop {
-            dest_nums.0 += dir.0;
-            dest_nums.1 += dir.1;
-
-            if dest_nums.0 < 0 || dest_nums.0 >= 8 {
-                break;
-            }
-            if dest_nums.1 < 0 || dest_nums.1 >= 8 {
-                break;
-            }
-
-            let dest = BoardPos::from_file_rank(
-                File::from_num(dest_nums.0 as u8),
-                Rank::from_num(dest_nums.1 as u8),
-            );
-
+    for &(df, dr) in dirs {
+        for dest in source.ray(df, dr) {
             let dest_mask = BitBoard::single(dest);
             if our_pieces.intersect_with(dest_mask).any() {
                 break;
@@ -134,45 +121,19 @@ fn sliding_piece_pseudo_legal(
 const BISHOP_DIRS: [(i8, i8); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
 const ROOK_DIRS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
 
-const KING_MOVE_OFFSETS: &[(i8, i8)] = &[
-    (-1, 1),
-    (0, 1),
-    (1, 1),
-    (-1, -1),
-    (0, -1),
-    (1, -1),
-    (-1, 0),
-    (1, 0),
-];
-
 fn king_pseudo_legal(source: BoardPos, our_pieces: BitBoard) -> MoveSetChunk {
     MoveSetChunk {
         source,
         dest_set: king_moves(source, our_pieces),
-        promotion: false,
+        promotions: 0,
     }
 }
 
 pub fn king_moves(source: BoardPos, our_pieces: BitBoard) -> BitBoard {
-    let mut moves = BitBoard::new_empty();
-
-    let source_nums = (source.file.to_num() as i8, source.rank.to_num() as i8);
-    for dest_nums in KING_MOVE_OFFSETS
-        .iter()
-        .map(|km_off| (source_nums.0 + km_off.0, source_nums.1 + km_off.1))
-        .filter(|(x, _)| *x >= 0 && *x <= 7)
-        .filter(|(_, y)| *y >= 0 && *y <= 7)
-    {
-        let dest = BoardPos::from_file_rank(
-            File::from_num(dest_nums.0 as u8),
-            Rank::from_num(dest_nums.1 as u8),
-        );
-
-        moves.set(dest);
-    }
-
-    moves.intersect_inplace(!our_pieces);
-    moves
+    source
+        .neighbors()
+        .collect::<BitBoard>()
+        .intersect_with(!our_pieces)
 }
 
 #[cfg(test)]
@@ -1,5 +1,7 @@
 use variant_count::VariantCount;
 
+use crate::Color;
+
 #[derive(Clone, Copy, Debug, VariantCount, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Piece {
     Pawn,
@@ -45,6 +47,46 @@ impl Piece {
         }
     }
 
+    /// The inverse of `to_char`, ie accepts only lowercase symbols
+    pub const fn from_char(c: char) -> Option<Self> {
+        match c {
+            'p' => Some(Piece::Pawn),
+            'r' => Some(Piece::Rook),
+            'n' => Some(Piece::Knight),
+            'b' => Some(Piece::Bishop),
+            'k' => Some(Piece::King),
+            'q' => Some(Piece::Queen),
+            _ => None,
+        }
+    }
+
+    /// The material value of this piece, in centipawns
+    pub const fn value(self) -> i32 {
+        match self {
+            Piece::Pawn => 100,
+            Piece::Rook => 525,
+            Piece::Knight => 350,
+            Piece::Bishop => 350,
+            Piece::King => 0,
+            Piece::Queen => 1000,
+        }
+    }
+
+    /// Whether this piece moves any distance along a line until it is blocked
+    pub const fn is_sliding(self) -> bool {
+        matches!(self, Piece::Rook | Piece::Bishop | Piece::Queen)
+    }
+
+    /// Whether this is a knight or a bishop
+    pub const fn is_minor(self) -> bool {
+        matches!(self, Piece::Knight | Piece::Bishop)
+    }
+
+    /// Whether this is a rook or a queen
+    pub const fn is_major(self) -> bool {
+        matches!(self, Piece::Rook | Piece::Queen)
+    }
+
     pub const fn all() -> [Self; Self::VARIANT_COUNT] {
         [
             Piece::Pawn,
@@ -56,3 +98,78 @@ impl Piece {
         ]
     }
 }
+
+/// The symbol used for a piece in FEN strings - uppercase for white and lowercase for black
+pub const fn piece_to_fen_char(color: Color, piece: Piece) -> char {
+    let c = piece.to_char();
+    match color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+/// The inverse of `piece_to_fen_char`
+pub const fn fen_char_to_piece(c: char) -> Option<(Color, Piece)> {
+    let color = if c.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+
+    match Piece::from_char(c.to_ascii_lowercase()) {
+        Some(piece) => Some((color, piece)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_piece_classification() {
+        let classify = |piece: Piece| {
+            (
+                piece.value(),
+                piece.is_sliding(),
+                piece.is_minor(),
+                piece.is_major(),
+            )
+        };
+
+        assert_eq!(classify(Piece::Pawn), (100, false, false, false));
+        assert_eq!(classify(Piece::Knight), (350, false, true, false));
+        assert_eq!(classify(Piece::Bishop), (350, true, true, false));
+        assert_eq!(classify(Piece::Rook), (525, true, false, true));
+        assert_eq!(classify(Piece::Queen), (1000, true, false, true));
+        assert_eq!(classify(Piece::King), (0, false, false, false));
+
+        // Every piece other than pawns and kings is exactly one of minor or major
+        for piece in Piece::all() {
+            let kind_count = piece.is_minor() as u8 + piece.is_major() as u8;
+            let expected = match piece {
+                Piece::Pawn | Piece::King => 0,
+                _ => 1,
+            };
+            assert_eq!(kind_count, expected, "{:?}", piece);
+        }
+    }
+
+    #[test]
+    fn test_fen_char_roundtrips() {
+        let mut seen = Vec::new();
+        for &color in Color::all() {
+            for piece in Piece::all() {
+                let c = piece_to_fen_char(color, piece);
+                assert_eq!(fen_char_to_piece(c), Some((color, piece)));
+                assert!(!seen.contains(&c));
+                seen.push(c);
+            }
+        }
+        assert_eq!(seen.len(), 12);
+
+        for c in ['x', 'X', '1', '/', ' '] {
+            assert_eq!(fen_char_to_piece(c), None);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Material values and piece-square tables, along with a running total of them that is kept up to
+//! date incrementally as moves are applied to a `State`.
+
+use crate::{BoardPos, Color, Piece, State};
+
+#[rustfmt::skip]
+const PAWN_SQUARE_TABLE: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0
+];
+
+#[rustfmt::skip]
+const KNIGHT_SQUARE_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_SQUARE_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_SQUARE_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0
+];
+
+#[rustfmt::skip]
+const QUEEN_SQUARE_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20
+];
+
+const NULL_SQUARE_TABLE: [i32; 64] = [0; 64];
+
+pub const fn piece_square_table(piece: Piece) -> [i32; 64] {
+    match piece {
+        Piece::Pawn => PAWN_SQUARE_TABLE,
+        Piece::Knight => KNIGHT_SQUARE_TABLE,
+        Piece::Bishop => BISHOP_SQUARE_TABLE,
+        Piece::Rook => ROOK_SQUARE_TABLE,
+        Piece::Queen => QUEEN_SQUARE_TABLE,
+        Piece::King => NULL_SQUARE_TABLE,
+    }
+}
+
+/// The piece-square table value of a single piece
+pub const fn piece_square_value(color: Color, piece: Piece, pos: BoardPos) -> i32 {
+    let table = piece_square_table(piece);
+    let index = match color {
+        Color::White => pos.to_bitboard_offset(),
+        Color::Black => 63 - pos.to_bitboard_offset(),
+    };
+
+    table[index as usize]
+}
+
+/// How much each piece counts towards the game phase, see `State::phase_score`
+pub const fn phase_weight(piece: Piece) -> i32 {
+    match piece {
+        Piece::Knight | Piece::Bishop => 1,
+        Piece::Rook => 2,
+        Piece::Queen => 4,
+        Piece::Pawn | Piece::King => 0,
+    }
+}
+
+/// The combined phase weight of every piece in the starting position
+pub const STARTING_PHASE_WEIGHT: i32 = 24;
+
+/// Running per-color totals of material and piece-square table values, along with the phase
+/// weight of the pieces of both colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalAccumulator {
+    material: [i32; 2],
+    piece_square: [i32; 2],
+    phase_weight: i32,
+}
+
+impl EvalAccumulator {
+    pub const fn new_empty() -> Self {
+        Self {
+            material: [0; 2],
+            piece_square: [0; 2],
+            phase_weight: 0,
+        }
+    }
+
+    /// The total value of material in centipawns for the given color
+    pub const fn material(&self, color: Color) -> i32 {
+        self.material[color.to_num() as usize]
+    }
+
+    /// The sum of the piece-square table values for every piece of the given color
+    pub const fn piece_square(&self, color: Color) -> i32 {
+        self.piece_square[color.to_num() as usize]
+    }
+
+    /// The sum of the phase weights of every piece of either color
+    pub const fn phase_weight(&self) -> i32 {
+        self.phase_weight
+    }
+
+    pub fn add_piece(&mut self, color: Color, piece: Piece, pos: BoardPos) {
+        self.material[color.to_num() as usize] += piece.value();
+        self.piece_square[color.to_num() as usize] += piece_square_value(color, piece, pos);
+        self.phase_weight += phase_weight(piece);
+    }
+
+    pub fn remove_piece(&mut self, color: Color, piece: Piece, pos: BoardPos) {
+        self.material[color.to_num() as usize] -= piece.value();
+        self.piece_square[color.to_num() as usize] -= piece_square_value(color, piece, pos);
+        self.phase_weight -= phase_weight(piece);
+    }
+
+    pub fn move_piece(&mut self, color: Color, piece: Piece, from: BoardPos, to: BoardPos) {
+        self.piece_square[color.to_num() as usize] +=
+            piece_square_value(color, piece, to) - piece_square_value(color, piece, from);
+    }
+}
+
+// Used when initialising positions, and for checking the efficient updates
+pub fn calculate_entire_accumulator(state: &State) -> EvalAccumulator {
+    let mut acc = EvalAccumulator::new_empty();
+    for (pos, color, piece) in state.board.iter_pieces() {
+        acc.add_piece(color, piece, pos);
+    }
+    acc
+}
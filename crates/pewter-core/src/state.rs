@@ -1,15 +1,43 @@
 use bitflags::bitflags;
+use thiserror::Error;
 
 use crate::bitboard::masks;
+use crate::chessmove::MoveSetChunk;
 use crate::coordinates::consts::*;
+use crate::io::fen::FenParseError;
+use crate::psqt::{self, EvalAccumulator};
 use crate::zobrist::{self, ZobristHash};
 use crate::{BitBoard, Board, BoardPos, Color, File, Move, Piece, Rank};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CastleSide {
     Kingside,
     Queenside,
 }
 
+impl CastleSide {
+    pub const fn all() -> &'static [Self] {
+        &[CastleSide::Kingside, CastleSide::Queenside]
+    }
+
+    /// Every combination of color and castling side
+    pub fn all_with_colors() -> impl Iterator<Item = (Color, CastleSide)> {
+        Color::all()
+            .iter()
+            .flat_map(|&color| Self::all().iter().map(move |&side| (color, side)))
+    }
+
+    /// The starting and ending squares of the rook when `color` castles on this side
+    pub const fn rook_squares(self, color: Color) -> (BoardPos, BoardPos) {
+        match (color, self) {
+            (Color::White, CastleSide::Kingside) => (H1, F1),
+            (Color::White, CastleSide::Queenside) => (A1, D1),
+            (Color::Black, CastleSide::Kingside) => (H8, F8),
+            (Color::Black, CastleSide::Queenside) => (A8, D8),
+        }
+    }
+}
+
 bitflags! {
     pub struct CastleRights: u8 {
         const WHITE_KINGSIDE  = 0b0001;
@@ -23,16 +51,94 @@ bitflags! {
 }
 
 impl CastleRights {
-    pub const fn get(self, color: Color, side: CastleSide) -> bool {
+    /// The single flag for `color` being allowed to castle on `side`
+    pub const fn flag(color: Color, side: CastleSide) -> Self {
         use CastleSide::*;
         use Color::*;
         match (color, side) {
-            (White, Kingside) => self.contains(Self::WHITE_KINGSIDE),
-            (White, Queenside) => self.contains(Self::WHITE_QUEENSIDE),
-            (Black, Kingside) => self.contains(Self::BLACK_KINGSIDE),
-            (Black, Queenside) => self.contains(Self::BLACK_QUEENSIDE),
+            (White, Kingside) => Self::WHITE_KINGSIDE,
+            (White, Queenside) => Self::WHITE_QUEENSIDE,
+            (Black, Kingside) => Self::BLACK_KINGSIDE,
+            (Black, Queenside) => Self::BLACK_QUEENSIDE,
         }
     }
+
+    pub const fn get(self, color: Color, side: CastleSide) -> bool {
+        self.contains(Self::flag(color, side))
+    }
+
+    /// Iterate over the (color, side) combinations that are still allowed to castle
+    pub fn iter_set(self) -> impl Iterator<Item = (Color, CastleSide)> {
+        CastleSide::all_with_colors().filter(move |&(color, side)| self.get(color, side))
+    }
+
+    /// The castling rights field of a FEN string, eg "KQkq", or "-" if neither player can castle
+    pub fn to_fen_string(self) -> String {
+        self.format_with(|color, side| match (color, side) {
+            (Color::White, CastleSide::Kingside) => 'K',
+            (Color::White, CastleSide::Queenside) => 'Q',
+            (Color::Black, CastleSide::Kingside) => 'k',
+            (Color::Black, CastleSide::Queenside) => 'q',
+        })
+    }
+
+    /// The castling rights field of a Shredder-FEN string, which names the file of each rook that
+    /// can still castle instead of its side, eg "HAha"
+    pub fn to_shredder_fen_string(self) -> String {
+        self.format_with(Self::shredder_char)
+    }
+
+    /// Parse the castling rights field of a FEN string, accepting both the usual "KQkq" letters
+    /// and Shredder-FEN rook files.
+    ///
+    /// Rook files are only accepted for the rooks' starting squares, as those are the only rooks
+    /// that are ever allowed to castle.
+    pub fn from_fen_str(s: &str) -> Result<Self, FenParseError> {
+        let mut rights = Self::empty();
+        for c in s.chars() {
+            let flag = match c {
+                'K' => Self::WHITE_KINGSIDE,
+                'Q' => Self::WHITE_QUEENSIDE,
+                'k' => Self::BLACK_KINGSIDE,
+                'q' => Self::BLACK_QUEENSIDE,
+                '-' => Self::empty(),
+                _ => CastleSide::all_with_colors()
+                    .find(|&(color, side)| Self::shredder_char(color, side) == c)
+                    .map(|(color, side)| Self::flag(color, side))
+                    .ok_or(FenParseError::InvalidCastlingRightsChar(c))?,
+            };
+            rights.insert(flag);
+        }
+
+        Ok(rights)
+    }
+
+    /// The file of the rook that castles on `side`, uppercase for white and lowercase for black
+    fn shredder_char(color: Color, side: CastleSide) -> char {
+        let (rook, _) = side.rook_squares(color);
+        let c = (b'A' + rook.file.to_num()) as char;
+        match color {
+            Color::White => c,
+            Color::Black => c.to_ascii_lowercase(),
+        }
+    }
+
+    fn format_with(self, to_char: impl Fn(Color, CastleSide) -> char) -> String {
+        if self.is_empty() {
+            return "-".to_string();
+        }
+
+        // White's rights come first, and kingside before queenside for each color
+        self.iter_set()
+            .map(|(color, side)| to_char(color, side))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for CastleRights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_fen_string())
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -43,6 +149,89 @@ pub enum GameResult {
     Draw,
 }
 
+/// Why a move couldn't be applied by `State::apply_move_checked`
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+pub enum MoveError {
+    #[error("There is no piece on the source square of the move")]
+    NoPieceOnSource,
+
+    #[error("The piece on the source square of the move doesn't belong to the player to move")]
+    WrongColor,
+
+    #[error("The move isn't legal in this position")]
+    NotLegal,
+
+    #[error("The move is missing a promotion, or has a promotion when it shouldn't")]
+    BadPromotion,
+}
+
+/// Why a sequence of moves couldn't be applied by `State::apply_moves_checked`
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+#[error("Move {index} of the sequence couldn't be applied: {error}")]
+pub struct MoveSequenceError {
+    /// The index of the first move in the sequence that couldn't be applied
+    pub index: usize,
+
+    /// Why that move couldn't be applied
+    pub error: MoveError,
+}
+
+/// Why a position couldn't be set up by `State::from_placement`
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+pub enum PlacementError {
+    #[error("{0:?} doesn't have exactly one king")]
+    KingCount(Color),
+
+    #[error("There is a pawn on the first or last rank at {0:?}")]
+    PawnOnBackRank(BoardPos),
+
+    #[error("The player who isn't to move is in check")]
+    OpponentInCheck,
+}
+
+/// A coarse classification of how far a game has progressed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamePhase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+/// The boundaries between each `GamePhase`, as used by `State::game_phase_with`.
+///
+/// Non-pawn material is the combined value in centipawns of every knight, bishop, rook, and queen
+/// of both colors, 6900 in the starting position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GamePhaseThresholds {
+    /// The game is still in the opening while there is at least this much non-pawn material...
+    pub opening_min_material: i32,
+
+    /// ...and at least this many knights and bishops are still on their starting squares
+    pub opening_min_undeveloped: u8,
+
+    /// The game is in the endgame once there is at most this much non-pawn material
+    pub endgame_max_material: i32,
+}
+
+impl GamePhaseThresholds {
+    /// Roughly: no exchanges of pieces and at most half of the minor pieces developed for the
+    /// opening, and no more than a rook and a minor piece each for the endgame.
+    pub const DEFAULT: Self = Self {
+        opening_min_material: 6000,
+        opening_min_undeveloped: 4,
+        endgame_max_material: 1750,
+    };
+}
+
+impl Default for GamePhaseThresholds {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The `State::phase_score` of the starting position
+pub const MAX_PHASE_SCORE: i32 = 256;
+
 #[derive(Clone, Copy, Debug)]
 pub struct State {
     pub to_play: Color,
@@ -62,11 +251,15 @@ pub struct State {
     pub pinned: BitBoard,
     pub checkers: BitBoard,
     pub zobrist: ZobristHash,
+
+    /// Running material and piece-square totals, kept up to date as moves are applied
+    pub accumulator: EvalAccumulator,
 }
 
 impl State {
+    /// A state with nothing on the board, and white to play without any castling rights
     pub fn new_empty() -> Self {
-        Self {
+        let mut state = Self {
             to_play: Color::White,
             castle_rights: CastleRights::empty(),
             en_passant: None,
@@ -76,11 +269,61 @@ impl State {
             pinned: BitBoard::new_empty(),
             checkers: BitBoard::new_empty(),
             zobrist: ZobristHash::null(),
+            accumulator: EvalAccumulator::new_empty(),
+        };
+
+        // Even an empty board hashes the player to move and the (lack of) castling rights
+        state.zobrist = zobrist::calculate_entire_zobrist(&state);
+        state
+    }
+
+    /// Set up a position from the piece on each square, indexed by bitboard offset as in
+    /// `Board::from_array`, without going via a FEN string.
+    ///
+    /// The move counters are set as they would be at the start of a game. Fails if either player
+    /// doesn't have exactly one king, if there are pawns on the first or last ranks, or if the
+    /// player who isn't to move could have their king captured.
+    pub fn from_placement(
+        squares: &[Option<(Color, Piece)>; 64],
+        to_play: Color,
+        castle_rights: CastleRights,
+        en_passant: Option<BoardPos>,
+    ) -> Result<Self, PlacementError> {
+        let mut state = Self::new_empty();
+        for (offset, square) in squares.iter().enumerate() {
+            if let Some((color, piece)) = *square {
+                let pos = BoardPos::from_bitboard_offset(offset as u8);
+                if piece == Piece::Pawn && (pos.rank == Rank::R1 || pos.rank == Rank::R8) {
+                    return Err(PlacementError::PawnOnBackRank(pos));
+                }
+                state.add_piece(color, piece, pos);
+            }
+        }
+
+        for color in [Color::White, Color::Black] {
+            if state.board.color_piece_board(color, Piece::King).count() != 1 {
+                return Err(PlacementError::KingCount(color));
+            }
         }
+
+        if state.is_square_attacked(state.king_pos(!to_play), to_play) {
+            return Err(PlacementError::OpponentInCheck);
+        }
+
+        state.to_play = to_play;
+        state.castle_rights = castle_rights;
+        state.en_passant = en_passant;
+        state.fullmove_counter = 1;
+        state.recompute_all();
+
+        Ok(state)
     }
 
+    /// Place a piece on an empty square, keeping the zobrist hash and eval accumulator in step
     pub fn add_piece(&mut self, color: Color, piece: Piece, pos: BoardPos) {
         self.board.add_piece(pos, color, piece);
+        self.accumulator.add_piece(color, piece, pos);
+        self.zobrist ^= zobrist::piece_number(color, piece, pos);
     }
 
     pub fn king_pos(&self, color: Color) -> BoardPos {
@@ -94,19 +337,76 @@ impl State {
         self.checkers.any()
     }
 
-    pub fn recompute_pins_and_checks(&mut self) {
-        // A mask that selects all the pieces that are currently pinned
-        self.pinned = BitBoard::new_empty();
+    /// The pieces giving check to the king of the given color.
+    ///
+    /// For `self.to_play` this is the same as `self.checkers`.
+    pub fn checkers_of(&self, color: Color) -> BitBoard {
+        crate::movegen::attackers_of(self, self.king_pos(color), !color)
+    }
 
-        // A mask that selects all the enemy pieces that are currently giving check
-        self.checkers = BitBoard::new_empty();
+    /// Is the given position attacked by any piece of color `by`
+    pub fn is_square_attacked(&self, pos: BoardPos, by: Color) -> bool {
+        crate::movegen::attackers_of(self, pos, by).any()
+    }
 
-        let our_color = self.to_play;
-        let opp_color = !our_color;
+    /// The enemy pieces that are giving check to `self.to_play`
+    pub const fn checkers(&self) -> BitBoard {
+        self.checkers
+    }
 
-        let k_pos = self.king_pos(our_color);
-        let k_mask = BitBoard::single(k_pos);
-        let opp_color_mask = self.board.color_board(opp_color);
+    /// The pieces of `self.to_play` that are pinned to their king
+    pub const fn pinned(&self) -> BitBoard {
+        self.pinned
+    }
+
+    /// The enemy pieces pinning one of the pieces of `self.to_play` to its king
+    pub fn pinners(&self) -> BitBoard {
+        let k_pos = self.king_pos(self.to_play);
+        let union_board = self.board.all_union_board();
+        let mut pinners = BitBoard::new_empty();
+        for pos in self.sliders_aimed_at_king().iter_set() {
+            let between = masks::between(pos, k_pos) & union_board;
+            if between.count() == 1 && (between & self.pinned).any() {
+                pinners.set(pos);
+            }
+        }
+        pinners
+    }
+
+    /// The enemy pieces giving check to `self.to_play`, and the enemy pieces pinning one of its
+    /// pieces to its king, ie all the enemy pieces that restrict its legal moves.
+    pub fn checkers_and_pinners(&self) -> (BitBoard, BitBoard) {
+        (self.checkers, self.pinners())
+    }
+
+    /// The enemy piece pinning the piece on the given square to the king of `self.to_play`, or
+    /// None if that piece isn't pinned.
+    pub fn pinner_of(&self, pos: BoardPos) -> Option<BoardPos> {
+        if !self.pinned[pos] {
+            return None;
+        }
+
+        let k_pos = self.king_pos(self.to_play);
+        let union_board = self.board.all_union_board();
+        self.sliders_aimed_at_king()
+            .iter_set()
+            .find(|&pinner| masks::between(pinner, k_pos) & union_board == BitBoard::single(pos))
+    }
+
+    /// The squares that the pinned piece on the given square can move along without exposing its
+    /// king, from the square next to the king up to and including the pinning piece.
+    ///
+    /// None if the piece on the given square isn't pinned.
+    pub fn pin_ray(&self, pos: BoardPos) -> Option<BitBoard> {
+        let pinner = self.pinner_of(pos)?;
+        Some(masks::between(pinner, self.king_pos(self.to_play)).with_set(pinner))
+    }
+
+    /// The enemy sliding pieces that would attack the king of `self.to_play` if nothing was in
+    /// the way
+    fn sliders_aimed_at_king(&self) -> BitBoard {
+        let k_pos = self.king_pos(self.to_play);
+        let opp_color_mask = self.board.color_board(!self.to_play);
         let queens = self.board.piece_board(Piece::Queen);
 
         let pinner_bishops = self
@@ -123,7 +423,35 @@ impl State {
             .intersect_with(opp_color_mask)
             .intersect_with(masks::rook_rays(k_pos));
 
-        let all_pinners = pinner_bishops.union_with(pinner_rooks);
+        pinner_bishops.union_with(pinner_rooks)
+    }
+
+    /// Recompute everything that is derived from the rest of the state: the pins, the checkers,
+    /// the zobrist hash and the eval accumulator.
+    ///
+    /// Moves keep all of these up to date, but anything else that changes the state, eg setting
+    /// `to_play` or the castling rights, or placing pieces directly on `board`, leaves them stale.
+    /// This must be called after setting up a state by hand before it is used for movegen or
+    /// searched.
+    pub fn recompute_all(&mut self) {
+        self.recompute_pins_and_checks();
+        self.zobrist = zobrist::calculate_entire_zobrist(self);
+        self.accumulator = psqt::calculate_entire_accumulator(self);
+    }
+
+    pub fn recompute_pins_and_checks(&mut self) {
+        // A mask that selects all the pieces that are currently pinned
+        self.pinned = BitBoard::new_empty();
+
+        // A mask that selects all the enemy pieces that are currently giving check
+        self.checkers = BitBoard::new_empty();
+
+        let our_color = self.to_play;
+        let opp_color = !our_color;
+
+        let k_pos = self.king_pos(our_color);
+
+        let all_pinners = self.sliders_aimed_at_king();
 
         let union_board = self.board.all_union_board();
         for pos in all_pinners.iter_set() {
@@ -135,18 +463,14 @@ impl State {
             }
         }
 
-        for pawn in self
+        // An enemy pawn attacks our king exactly when it stands on a square that a pawn of our
+        // color on the king's square would attack
+        let checking_pawns = self
             .board
             .color_piece_board(opp_color, Piece::Pawn)
-            .iter_set()
-        {
-            let pawn_attacks = masks::pawn_attacks(opp_color, pawn)
-                .intersect_with(k_mask);
+            .intersect_with(masks::pawn_attacks(our_color, k_pos));
 
-            if pawn_attacks.any() {
-                self.checkers.set(pawn);
-            }
-        }
+        self.checkers.union_inplace(checking_pawns);
 
         let checking_knights = self
             .board
@@ -160,92 +484,39 @@ impl State {
     }
 
     fn apply_castling(&mut self, m: Move) {
-        let kingside = m.to.file == File::G;
-
-        let required_flag = if self.to_play == Color::Black {
-            if kingside {
-                CastleRights::BLACK_KINGSIDE
-            } else {
-                CastleRights::BLACK_QUEENSIDE
-            }
+        let side = if m.to.file == File::G {
+            CastleSide::Kingside
         } else {
-            if kingside {
-                CastleRights::WHITE_KINGSIDE
-            } else {
-                CastleRights::WHITE_QUEENSIDE
-            }
+            CastleSide::Queenside
         };
-        debug_assert!(self.castle_rights.contains(required_flag));
+        debug_assert!(self.castle_rights.get(self.to_play, side));
 
-        let from = match (self.to_play, kingside) {
-            (Color::White, true) => H1,
-            (Color::White, false) => A1,
-            (Color::Black, true) => H8,
-            (Color::Black, false) => A8,
-        };
-        let to = match (self.to_play, kingside) {
-            (Color::White, true) => F1,
-            (Color::White, false) => D1,
-            (Color::Black, true) => F8,
-            (Color::Black, false) => D8,
-        };
+        let (from, to) = side.rook_squares(self.to_play);
 
         let op = BitBoard::single(from).union_with(BitBoard::single(to));
         self.board.xor_inplace(self.to_play, Piece::Rook, op);
         self.zobrist ^= zobrist::piece_number(self.to_play, Piece::Rook, from);
         self.zobrist ^= zobrist::piece_number(self.to_play, Piece::Rook, to);
+        self.accumulator
+            .move_piece(self.to_play, Piece::Rook, from, to);
     }
 
-    fn update_castling_rights(&mut self, m: Move, piece: Piece, capture_piece: Option<Piece>) {
+    fn update_castling_rights(&mut self, m: Move, piece: Piece) {
         if piece == Piece::King {
             // Moving the king removes all castling rights
-            match self.to_play {
-                Color::White => {
-                    self.castle_rights.remove(CastleRights::ALL_WHITE);
-                }
-                Color::Black => {
-                    self.castle_rights.remove(CastleRights::ALL_BLACK);
-                }
-            }
-        }
-
-        if piece == Piece::Rook {
-            // Moving a rook removes castling rights on that side
-            match self.to_play {
-                Color::White => {
-                    if m.from == A1 {
-                        self.castle_rights.remove(CastleRights::WHITE_QUEENSIDE);
-                    } else if m.from == H1 {
-                        self.castle_rights.remove(CastleRights::WHITE_KINGSIDE);
-                    }
-                }
-                Color::Black => {
-                    if m.from == A8 {
-                        self.castle_rights.remove(CastleRights::BLACK_QUEENSIDE);
-                    } else if m.from == H8 {
-                        self.castle_rights.remove(CastleRights::BLACK_KINGSIDE);
-                    }
-                }
+            for &side in CastleSide::all() {
+                self.castle_rights
+                    .remove(CastleRights::flag(self.to_play, side));
             }
         }
 
-        if let Some(Piece::Rook) = capture_piece {
-            // Losing a rook means you can no longer castle on that side
-            match !self.to_play {
-                Color::White => {
-                    if m.to == A1 {
-                        self.castle_rights.remove(CastleRights::WHITE_QUEENSIDE);
-                    } else if m.to == H1 {
-                        self.castle_rights.remove(CastleRights::WHITE_KINGSIDE);
-                    }
-                }
-                Color::Black => {
-                    if m.to == A8 {
-                        self.castle_rights.remove(CastleRights::BLACK_QUEENSIDE);
-                    } else if m.to == H8 {
-                        self.castle_rights.remove(CastleRights::BLACK_KINGSIDE);
-                    }
-                }
+        // While a castling right is held, the corresponding rook must still be on its starting
+        // square. So any move from that square is moving the rook, and any move to that square is
+        // capturing it, either of which means there can be no more castling on that side.
+        for (color, side) in self.castle_rights.iter_set() {
+            let (rook_pos, _) = side.rook_squares(color);
+            if m.from == rook_pos || m.to == rook_pos {
+                self.castle_rights.remove(CastleRights::flag(color, side));
             }
         }
     }
@@ -255,10 +526,7 @@ impl State {
 
         fn sym(val: Option<(Color, Piece)>) -> char {
             if let Some((color, piece)) = val {
-                match color {
-                    Color::White => piece.to_char().to_ascii_uppercase(),
-                    Color::Black => piece.to_char(),
-                }
+                crate::piece::piece_to_fen_char(color, piece)
             } else {
                 ' '
             }
@@ -267,10 +535,76 @@ impl State {
         pretty_format(|pos| sym(self.board.get(pos)))
     }
 
+    /// Applies a move after checking that it is legal in this position.
+    ///
+    /// Unlike `apply_move`, this is suitable for moves from untrusted sources such as user input.
+    pub fn apply_move_checked(&self, m: Move) -> Result<Self, MoveError> {
+        let (color, piece) = self.board.get(m.from).ok_or(MoveError::NoPieceOnSource)?;
+        if color != self.to_play {
+            return Err(MoveError::WrongColor);
+        }
+
+        let promoting = piece == Piece::Pawn && (m.to.rank == Rank::R1 || m.to.rank == Rank::R8);
+        let valid_promotion = match m.promotion {
+            None => !promoting,
+            Some(Piece::Pawn) | Some(Piece::King) => false,
+            Some(_) => promoting,
+        };
+        if !valid_promotion {
+            return Err(MoveError::BadPromotion);
+        }
+
+        let is_legal = crate::movegen::legal_moves(self)
+            .iter()
+            .any(|legal| legal == m);
+        if !is_legal {
+            return Err(MoveError::NotLegal);
+        }
+
+        Ok(self.apply_move(m))
+    }
+
+    /// Applies each of the given moves in turn, panicking if any of them doesn't fit
+    pub fn apply_moves(&self, moves: &[Move]) -> Self {
+        moves.iter().fold(*self, |state, &m| state.apply_move(m))
+    }
+
+    /// Applies each of the given moves in turn, checking that each of them is legal.
+    ///
+    /// Stops at the first move that isn't legal, reporting its index in the sequence.
+    pub fn apply_moves_checked(&self, moves: &[Move]) -> Result<Self, MoveSequenceError> {
+        moves
+            .iter()
+            .enumerate()
+            .try_fold(*self, |state, (index, &m)| {
+                state
+                    .apply_move_checked(m)
+                    .map_err(|error| MoveSequenceError { index, error })
+            })
+    }
+
+    /// The legal moves of the piece on the given square, which are none if it isn't a piece
+    /// belonging to the player to move. See `movegen::legal::legal_moves_from`.
+    pub fn legal_moves_from(&self, source: BoardPos) -> MoveSetChunk {
+        crate::movegen::legal::legal_moves_from(self, source)
+    }
+
     /// Applies a move, panicking if the move doesn't fit.
     ///
     /// When panicking, may leave this object in an invalid state.
     pub fn apply_move(&self, m: Move) -> Self {
+        let mut next_state = self.apply_move_no_legality(m);
+        next_state.recompute_pins_and_checks();
+        next_state
+    }
+
+    /// Applies a move like `apply_move`, but without working out the pins and checks of the
+    /// resulting position, which is the most expensive part of applying a move.
+    ///
+    /// The returned state reports no pins or checkers, so it must not be used to generate moves or
+    /// to test for check until `recompute_pins_and_checks` has been called on it. This is only
+    /// useful to callers that look at the board without playing on from it, eg to display it.
+    pub fn apply_move_no_legality(&self, m: Move) -> Self {
         let mut next_state = *self;
 
         next_state.checkers = BitBoard::new_empty();
@@ -290,6 +624,9 @@ impl State {
         debug_assert_eq!(_color, our_color);
         next_state.zobrist ^= zobrist::piece_number(self.to_play, piece, m.from);
         next_state.zobrist ^= zobrist::piece_number(self.to_play, piece, m.to);
+        next_state
+            .accumulator
+            .move_piece(our_color, piece, m.from, m.to);
 
         let capture_piece = next_state.board.get(m.to).map(|(c, p)| {
             debug_assert_eq!(c, opp_color);
@@ -303,6 +640,9 @@ impl State {
                 .board
                 .xor_inplace(opp_color, capture_piece, to_bb);
             next_state.zobrist ^= zobrist::piece_number(!self.to_play, capture_piece, m.to);
+            next_state
+                .accumulator
+                .remove_piece(opp_color, capture_piece, m.to);
         }
 
         // let op = BitBoard::single(m.from).union_with(BitBoard::single(m.to));
@@ -316,14 +656,18 @@ impl State {
                 Color::Black => BoardPos::from_file_rank(m.to.file, Rank::R5),
             };
             next_state.board.clear(opp_color, Piece::Pawn, ep_pawn_pos);
+            next_state
+                .accumulator
+                .remove_piece(opp_color, Piece::Pawn, ep_pawn_pos);
         }
 
         if piece == Piece::King && (move_bb & masks::castling_moves_all() == move_bb) {
             next_state.apply_castling(m);
         }
-        next_state.update_castling_rights(m, piece, capture_piece);
-        next_state.zobrist ^= zobrist::castling_number(self.castle_rights);
-        next_state.zobrist ^= zobrist::castling_number(next_state.castle_rights);
+        next_state.update_castling_rights(m, piece);
+        for (color, side) in (self.castle_rights ^ next_state.castle_rights).iter_set() {
+            next_state.zobrist ^= zobrist::castle_right_number(color, side);
+        }
 
         // Update the en-passant capturable state
         if (piece == Piece::Pawn) && (move_bb & masks::double_pawn_moves(our_color) == move_bb) {
@@ -343,6 +687,10 @@ impl State {
             debug_assert!(m.to.rank == Rank::R1 || m.to.rank == Rank::R8);
             next_state.board.clear(our_color, Piece::Pawn, m.to);
             next_state.board.set(our_color, promotion, m.to);
+            next_state
+                .accumulator
+                .remove_piece(our_color, Piece::Pawn, m.to);
+            next_state.accumulator.add_piece(our_color, promotion, m.to);
         }
 
         if capture_piece.is_some() || piece == Piece::Pawn {
@@ -358,10 +706,153 @@ impl State {
         next_state.zobrist ^= zobrist::consts::ZOBRIST_WHITE_TURN;
         next_state.to_play = !next_state.to_play;
 
+        debug_assert_eq!(
+            next_state.accumulator,
+            psqt::calculate_entire_accumulator(&next_state)
+        );
+
+        next_state
+    }
+
+    /// Passes the turn to the other player without changing the board, as for null move pruning.
+    ///
+    /// Panics if the player to move is in check, as passing would leave their king in check.
+    pub fn make_null_move(&self) -> Self {
+        assert!(!self.in_check(), "Can't make a null move while in check");
+
+        let mut next_state = *self;
+
+        next_state.zobrist ^= zobrist::ep_number(self.en_passant);
+        next_state.en_passant = None;
+        next_state.zobrist ^= zobrist::ep_number(None);
+
+        next_state.halfmove_clock += 1;
+        if next_state.to_play == Color::Black {
+            next_state.fullmove_counter += 1;
+        }
+
+        next_state.zobrist ^= zobrist::consts::ZOBRIST_WHITE_TURN;
+        next_state.to_play = !next_state.to_play;
+
         next_state.recompute_pins_and_checks();
+
         next_state
     }
 
+    /// The piece that the given move would capture in this state, if any.
+    ///
+    /// For en passant captures this is the pawn that was passed, even though the destination
+    /// square is empty. Panics if there is no piece at the source of the move.
+    pub fn capture_victim(&self, m: Move) -> Option<Piece> {
+        crate::chessmove::move_kind(self, m).captured_piece()
+    }
+
+    /// Whether the given move would capture a piece in this state, including by en passant
+    pub fn is_capture_move(&self, m: Move) -> bool {
+        self.capture_victim(m).is_some()
+    }
+
+    /// The en passant square, but only if the player to move could actually capture onto it.
+    ///
+    /// `en_passant` is set after every double pawn push, whether or not there is a pawn in place
+    /// to take advantage of it.
+    pub fn effective_en_passant(&self) -> Option<BoardPos> {
+        let ep = self.en_passant?;
+        let capturable = crate::movegen::legal_moves(self)
+            .iter()
+            .any(|m| m.to == ep && self.board.piece_kind_at(m.from) == Some(Piece::Pawn));
+
+        if capturable {
+            Some(ep)
+        } else {
+            None
+        }
+    }
+
+    /// Are the two states the same position, as far as the remainder of the game is concerned?
+    ///
+    /// Compares the pieces, the player to move, castling rights, and effective en passant square,
+    /// but not the move counters.
+    pub fn same_board(&self, other: &State) -> bool {
+        self.board == other.board
+            && self.to_play == other.to_play
+            && self.castle_rights == other.castle_rights
+            && self.effective_en_passant() == other.effective_en_passant()
+    }
+
+    /// The number of pieces of either color on the board, including pawns and kings
+    pub fn total_piece_count(&self) -> u8 {
+        self.board.all_union_board().count()
+    }
+
+    /// The combined value in centipawns of every piece other than pawns and kings
+    pub fn non_pawn_material(&self) -> i32 {
+        let total =
+            self.accumulator.material(Color::White) + self.accumulator.material(Color::Black);
+        let pawns = self.board.piece_board(Piece::Pawn).count() as i32;
+        total - pawns * Piece::Pawn.value()
+    }
+
+    /// How much of the game remains before the endgame, from `MAX_PHASE_SCORE` with all the pieces
+    /// of the starting position on the board down to 0 with only pawns and kings. Counted from the
+    /// number of knights, bishops, rooks and queens of both colors, with extra material from
+    /// promotions capped at the maximum.
+    ///
+    /// This is kept up to date by the eval accumulator as moves are applied, so is cheap to query.
+    pub fn phase_score(&self) -> i32 {
+        let weight = self
+            .accumulator
+            .phase_weight()
+            .min(psqt::STARTING_PHASE_WEIGHT);
+        weight * MAX_PHASE_SCORE / psqt::STARTING_PHASE_WEIGHT
+    }
+
+    /// Classify the current position using the default thresholds
+    pub fn game_phase(&self) -> GamePhase {
+        self.game_phase_with(&GamePhaseThresholds::DEFAULT)
+    }
+
+    pub fn game_phase_with(&self, thresholds: &GamePhaseThresholds) -> GamePhase {
+        let material = self.non_pawn_material();
+        if material <= thresholds.endgame_max_material {
+            return GamePhase::Endgame;
+        }
+
+        let minor_starts = [
+            (Color::White, Piece::Knight, crate::squares![B1, G1]),
+            (Color::White, Piece::Bishop, crate::squares![C1, F1]),
+            (Color::Black, Piece::Knight, crate::squares![B8, G8]),
+            (Color::Black, Piece::Bishop, crate::squares![C8, F8]),
+        ];
+        let undeveloped: u8 = minor_starts
+            .iter()
+            .map(|&(color, piece, starts)| {
+                self.board
+                    .color_piece_board(color, piece)
+                    .intersect_with(starts)
+                    .count()
+            })
+            .sum();
+
+        if material >= thresholds.opening_min_material
+            && undeveloped >= thresholds.opening_min_undeveloped
+        {
+            GamePhase::Opening
+        } else {
+            GamePhase::Middlegame
+        }
+    }
+
+    /// Is there no sequence of legal moves that could lead to either player being checkmated, in
+    /// which case the game is drawn under the FIDE dead position rule.
+    ///
+    /// TODO: This only recognises positions that are dead because of the material on the board,
+    /// see `adjudicator::insufficient_material`. Positions where blocked pawns lock both kings out
+    /// of ever reaching anything, with no captures possible, are dead too but aren't yet detected.
+    pub fn is_dead_position(&self) -> bool {
+        crate::adjudicator::insufficient_material(self)
+    }
+
     pub fn game_result(&self) -> GameResult {
         let has_legal_moves = crate::movegen::legal_moves(self).any();
         match (self.in_check(), has_legal_moves) {
@@ -382,9 +873,13 @@ mod test {
     use crate::chessmove::Move;
     use crate::color::Color;
     use crate::coordinates::proptest_helpers::*;
-    use crate::io::fen::{format_fen, parse_fen};
+    use crate::io::fen::{fen_without_counters, format_fen, parse_fen};
     use crate::piece::Piece;
 
+    use crate::movegen::legal_moves;
+
+    use proptest::collection::vec;
+    use proptest::prelude::any;
     use proptest::strategy::{Just, Strategy};
     use proptest::{prop_oneof, proptest};
 
@@ -404,6 +899,91 @@ mod test {
     }
 
     proptest! {
+        #[test]
+        fn test_accumulator_matches_recompute(move_choices in vec(any::<usize>(), 0..80)) {
+            // Positions with castling, en-passant, and promotion opportunities
+            let fens = [
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            ];
+
+            for fen in fens {
+                let mut state = parse_fen(fen).unwrap();
+                assert_eq!(state.accumulator, psqt::calculate_entire_accumulator(&state));
+
+                for &choice in &move_choices {
+                    let moves = legal_moves(&state).iter().collect::<Vec<_>>();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    state = state.apply_move(moves[choice % moves.len()]);
+                    assert_eq!(state.accumulator, psqt::calculate_entire_accumulator(&state));
+                }
+            }
+        }
+
+        #[test]
+        fn test_pawn_checkers_match_pawn_attacks(
+            move_choices in vec(any::<usize>(), 0..80)
+        ) {
+            // Positions with en-passant and promotion opportunities, and kings near enemy pawns
+            let fens = [
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            ];
+
+            for fen in fens {
+                let mut state = parse_fen(fen).unwrap();
+
+                for &choice in &move_choices {
+                    let moves = legal_moves(&state).iter().collect::<Vec<_>>();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    state = state.apply_move(moves[choice % moves.len()]);
+
+                    let opp_color = !state.to_play;
+                    let k_mask = BitBoard::single(state.king_pos(state.to_play));
+                    let mut expected = BitBoard::new_empty();
+                    for pawn in state.board.color_piece_board(opp_color, Piece::Pawn).iter_set() {
+                        if masks::pawn_attacks(opp_color, pawn).intersect_with(k_mask).any() {
+                            expected.set(pawn);
+                        }
+                    }
+
+                    let pawn_checkers = state
+                        .checkers
+                        .intersect_with(state.board.piece_board(Piece::Pawn));
+                    assert_eq!(pawn_checkers, expected, "{}", format_fen(&state));
+                }
+            }
+        }
+
+        #[test]
+        fn test_in_check_matches_king_attacked(move_choices in vec(any::<usize>(), 0..80)) {
+            let mut state =
+                parse_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                    .unwrap();
+
+            for choice in move_choices {
+                let moves = legal_moves(&state).iter().collect::<Vec<_>>();
+                if moves.is_empty() {
+                    break;
+                }
+                state = state.apply_move(moves[choice % moves.len()]);
+
+                let king = state.king_pos(state.to_play);
+                assert_eq!(state.in_check(), state.is_square_attacked(king, !state.to_play));
+                assert_eq!(state.checkers_of(state.to_play), state.checkers);
+
+                // The side that just moved can never have been left in check
+                assert!(!state.checkers_of(!state.to_play).any());
+            }
+        }
+
         #[test]
         fn test_piece_num_roundtrips(piece in arb_piece()) {
             let num = piece.to_num();
@@ -418,9 +998,11 @@ mod test {
             pos in arb_boardpos()
         ) {
             let mut state = State::new_empty();
+            assert_eq!(state.zobrist, zobrist::calculate_entire_zobrist(&state));
 
             assert!(!state.board.color_board(color).any());
             state.add_piece(color, piece, pos);
+            assert_eq!(state.zobrist, zobrist::calculate_entire_zobrist(&state));
 
             let bb = state.board.color_piece_board(color, piece);
             assert_eq!(bb, state.board.color_board(color));
@@ -433,6 +1015,655 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_castle_rights_flags() {
+        assert_eq!(
+            CastleRights::flag(Color::White, CastleSide::Kingside),
+            CastleRights::WHITE_KINGSIDE
+        );
+        assert_eq!(
+            CastleRights::flag(Color::White, CastleSide::Queenside),
+            CastleRights::WHITE_QUEENSIDE
+        );
+        assert_eq!(
+            CastleRights::flag(Color::Black, CastleSide::Kingside),
+            CastleRights::BLACK_KINGSIDE
+        );
+        assert_eq!(
+            CastleRights::flag(Color::Black, CastleSide::Queenside),
+            CastleRights::BLACK_QUEENSIDE
+        );
+    }
+
+    #[test]
+    fn test_rook_captures_remove_castling_rights() {
+        let cases = [
+            // Capturing white's h1 rook takes away white's kingside castling
+            ("r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1", "g2h1", "Qkq"),
+            // Capturing black's a8 rook takes away black's queenside castling
+            ("r3k2r/1B6/8/8/8/8/8/R3K2R w KQkq - 0 1", "b7a8", "KQk"),
+            // Capturing white's a1 rook takes away white's queenside castling
+            ("r3k2r/8/8/8/8/8/1b6/R3K2R b KQkq - 0 1", "b2a1", "Kkq"),
+            // Capturing black's h8 rook takes away black's kingside castling
+            ("r3k2r/6B1/8/8/8/8/8/R3K2R w KQkq - 0 1", "g7h8", "KQq"),
+            // A rook that has already left its starting square has no rights left to lose
+            ("4k2r/r7/1B6/8/8/8/8/4K2R w Kk - 0 1", "b6a7", "Kk"),
+        ];
+
+        for (fen, lan, rights) in cases {
+            let state = parse_fen(fen).unwrap();
+            let next = state.apply_move(Move::from_long_algebraic(lan).unwrap());
+            assert_eq!(next.castle_rights.to_fen_string(), rights, "{}", fen);
+            assert_eq!(
+                next.zobrist,
+                zobrist::calculate_entire_zobrist(&next),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_castle_rights_iter_set() {
+        assert_eq!(CastleRights::empty().iter_set().count(), 0);
+
+        let all = CastleRights::all().iter_set().collect::<Vec<_>>();
+        assert_eq!(all, CastleSide::all_with_colors().collect::<Vec<_>>());
+        assert_eq!(all.len(), 4);
+
+        let rights = CastleRights::WHITE_QUEENSIDE | CastleRights::BLACK_KINGSIDE;
+        assert_eq!(
+            rights.iter_set().collect::<Vec<_>>(),
+            vec![
+                (Color::White, CastleSide::Queenside),
+                (Color::Black, CastleSide::Kingside),
+            ]
+        );
+
+        for (color, side) in CastleSide::all_with_colors() {
+            let single = CastleRights::flag(color, side);
+            assert_eq!(single.iter_set().collect::<Vec<_>>(), vec![(color, side)]);
+            assert_eq!(
+                zobrist::castling_number(single),
+                zobrist::castle_right_number(color, side)
+            );
+        }
+
+        let mut combined = zobrist::castling_number(CastleRights::WHITE_QUEENSIDE);
+        combined ^= zobrist::castling_number(CastleRights::BLACK_KINGSIDE);
+        assert_eq!(zobrist::castling_number(rights), combined);
+        assert_eq!(
+            zobrist::castling_number(CastleRights::empty()),
+            ZobristHash::null()
+        );
+    }
+
+    #[test]
+    fn test_in_check() {
+        // (FEN, is the side to move in check)
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                false,
+            ),
+            (
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                true,
+            ),
+            ("4k3/8/8/8/8/5n2/8/R3K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/R3K3 b - - 0 1", false),
+            ("4k3/8/8/1B6/8/8/8/4K3 b - - 0 1", true),
+            ("4k3/8/2n5/1B6/8/8/8/4K3 b - - 0 1", false),
+            ("4k3/3P4/8/8/8/8/8/4K3 b - - 0 1", true),
+        ];
+
+        for (fen, expected) in cases {
+            let state = parse_fen(fen).unwrap();
+            let king = state.king_pos(state.to_play);
+            assert_eq!(state.in_check(), expected, "{}", fen);
+            assert_eq!(
+                state.is_square_attacked(king, !state.to_play),
+                expected,
+                "{}",
+                fen
+            );
+            assert_eq!(state.checkers_of(state.to_play), state.checkers, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_game_phase() {
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                GamePhase::Opening,
+            ),
+            (
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 4",
+                GamePhase::Opening,
+            ),
+            (
+                "r1bq1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 9",
+                GamePhase::Middlegame,
+            ),
+            (
+                "r4rk1/pp3ppp/8/8/8/8/PP3PPP/R4RK1 w - - 0 20",
+                GamePhase::Middlegame,
+            ),
+            ("8/8/4k3/8/8/3K4/3P4/8 w - - 0 1", GamePhase::Endgame),
+            (
+                "6k1/5ppp/8/8/8/2N5/5PPP/3R2K1 w - - 0 30",
+                GamePhase::Endgame,
+            ),
+        ];
+
+        for (fen, expected) in cases {
+            assert_eq!(parse_fen(fen).unwrap().game_phase(), expected, "{}", fen);
+        }
+
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(start.total_piece_count(), 32);
+        assert_eq!(start.non_pawn_material(), 6900);
+
+        let never_endgame = GamePhaseThresholds {
+            endgame_max_material: -1,
+            ..GamePhaseThresholds::DEFAULT
+        };
+        let kp_vs_k = parse_fen("8/8/4k3/8/8/3K4/3P4/8 w - - 0 1").unwrap();
+        assert_eq!(kp_vs_k.total_piece_count(), 3);
+        assert_eq!(
+            kp_vs_k.game_phase_with(&never_endgame),
+            GamePhase::Middlegame
+        );
+    }
+
+    #[test]
+    fn test_phase_score_decreases_with_trades() {
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(start.phase_score(), MAX_PHASE_SCORE);
+
+        // A Ruy Lopez with the bishop traded for a knight, then an early trade of queens
+        let moves = [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "g8f6", "b5c6", "d7c6", "f3e5", "d8d4", "e5f3",
+            "d4e4", "d1e2", "e4e2", "e1e2",
+        ];
+        let mut state = start;
+        for lan in moves {
+            let m = Move::from_long_algebraic(lan).unwrap();
+            let next = state.apply_move(m);
+            match state.board.piece_kind_at(m.to) {
+                Some(piece) if piece != Piece::Pawn => {
+                    assert!(next.phase_score() < state.phase_score(), "{}", lan)
+                }
+                _ => assert_eq!(next.phase_score(), state.phase_score(), "{}", lan),
+            }
+            state = next;
+        }
+        assert_eq!(state.phase_score(), MAX_PHASE_SCORE * 14 / 24);
+
+        // Pawns don't count, and material from promotions doesn't go beyond the maximum
+        assert_eq!(
+            parse_fen("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1")
+                .unwrap()
+                .phase_score(),
+            0
+        );
+        assert_eq!(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/QPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                .unwrap()
+                .phase_score(),
+            MAX_PHASE_SCORE
+        );
+    }
+
+    #[test]
+    fn test_same_board_across_move_orders() {
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let play = |moves: &[&str]| {
+            moves.iter().fold(start, |state, lan| {
+                state.apply_move(Move::from_long_algebraic(lan).unwrap())
+            })
+        };
+
+        let knight_first = play(&["g1f3", "g8f6", "g2g3"]);
+        let pawn_first = play(&["g2g3", "g8f6", "g1f3"]);
+        assert!(knight_first.same_board(&pawn_first));
+        assert_ne!(format_fen(&knight_first), format_fen(&pawn_first));
+        assert_eq!(
+            fen_without_counters(&knight_first),
+            fen_without_counters(&pawn_first)
+        );
+
+        // An en passant square that can't be used doesn't make a position different
+        let double_push = play(&["e2e4"]);
+        let no_ep =
+            parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_eq!(double_push.en_passant, Some(E3));
+        assert!(double_push.same_board(&no_ep));
+
+        // ...but one that can be used does
+        let capturable = play(&["e2e4", "a7a6", "e4e5", "d7d5"]);
+        let forgotten = parse_fen(&format_fen(&capturable).replace(" d6 ", " - ")).unwrap();
+        assert_eq!(capturable.effective_en_passant(), Some(D6));
+        assert!(!capturable.same_board(&forgotten));
+
+        assert!(!start.same_board(&knight_first));
+    }
+
+    #[test]
+    fn test_apply_move_checked() {
+        let state = parse_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let check = |lan: &str| state.apply_move_checked(Move::from_long_algebraic(lan).unwrap());
+
+        assert_eq!(check("a2a3").err(), Some(MoveError::NoPieceOnSource));
+        assert_eq!(check("e8d8").err(), Some(MoveError::WrongColor));
+        assert_eq!(check("e1e3").err(), Some(MoveError::NotLegal));
+        assert_eq!(check("h1a1").err(), Some(MoveError::NotLegal));
+        assert_eq!(check("b7b8").err(), Some(MoveError::BadPromotion));
+        let king_promotion = Move {
+            from: B7,
+            to: B8,
+            promotion: Some(Piece::King),
+        };
+        assert_eq!(
+            state.apply_move_checked(king_promotion).err(),
+            Some(MoveError::BadPromotion)
+        );
+        assert_eq!(check("h1h2q").err(), Some(MoveError::BadPromotion));
+
+        for lan in ["e1g1", "h1h7", "b7b8q", "b7b8n"] {
+            let m = Move::from_long_algebraic(lan).unwrap();
+            assert_eq!(
+                check(lan).map(|s| format_fen(&s)),
+                Ok(format_fen(&state.apply_move(m))),
+                "{}",
+                lan
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_moves() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let moves = |lans: &[&str]| {
+            lans.iter()
+                .map(|lan| Move::from_long_algebraic(lan).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let legal = moves(&["e2e4", "e7e5", "g1f3", "b8c6"]);
+        let expected = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        assert_eq!(format_fen(&state.apply_moves(&legal)), expected);
+        assert_eq!(
+            state.apply_moves_checked(&legal).map(|s| format_fen(&s)),
+            Ok(expected.to_string())
+        );
+        assert_eq!(format_fen(&state.apply_moves(&[])), format_fen(&state));
+
+        // The knight on f3 blocks the pawn
+        let illegal = moves(&["e2e4", "e7e5", "g1f3", "d7d6", "f2f3", "g8f6"]);
+        assert_eq!(
+            state.apply_moves_checked(&illegal).err(),
+            Some(MoveSequenceError {
+                index: 4,
+                error: MoveError::NotLegal
+            })
+        );
+    }
+
+    #[test]
+    fn test_pin_ray() {
+        // The rook pins the knight along the file, and the bishop pins the pawn along the diagonal
+        let state = parse_fen("4r1k1/8/8/b7/8/8/3PN3/4K1N1 w - - 0 1").unwrap();
+        assert_eq!(state.pinned(), crate::squares![D2, E2]);
+        assert_eq!(state.checkers(), BitBoard::new_empty());
+
+        assert_eq!(state.pinner_of(E2), Some(E8));
+        assert_eq!(
+            state.pin_ray(E2),
+            Some(crate::squares![E2, E3, E4, E5, E6, E7, E8])
+        );
+
+        assert_eq!(state.pinner_of(D2), Some(A5));
+        assert_eq!(state.pin_ray(D2), Some(crate::squares![D2, C3, B4, A5]));
+
+        // Neither a free piece nor an empty square is pinned
+        assert_eq!(state.pin_ray(G1), None);
+        assert_eq!(state.pin_ray(E4), None);
+
+        // A knight can't move along any line, so once pinned it can't move at all
+        let knight_moves = legal_moves(&state).iter().filter(|m| m.from == E2).count();
+        assert_eq!(knight_moves, 0);
+
+        // With the knight gone, the rook gives check instead
+        let state = parse_fen("4r1k1/8/8/b7/8/8/3P4/4K1N1 w - - 0 1").unwrap();
+        assert_eq!(state.checkers(), BitBoard::single(E8));
+        assert_eq!(state.pinner_of(D2), Some(A5));
+    }
+
+    #[test]
+    fn test_is_dead_position() {
+        let dead = [
+            // King against king
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            // King and a single minor piece against king
+            "8/8/4k3/8/8/3K4/5N2/8 b - - 0 1",
+            "8/8/4k3/8/3b4/3K4/8/8 w - - 0 1",
+            // Bishops that are all on the same color square, whichever side they belong to
+            "8/8/4kb2/8/8/3KB3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KB3/8/6B1 w - - 0 1",
+        ];
+        for fen in dead {
+            assert!(parse_fen(fen).unwrap().is_dead_position(), "{}", fen);
+        }
+
+        let alive = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "8/8/4k3/8/8/3K4/3P4/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/3R4/8 w - - 0 1",
+            // Mate is possible with help, eg with the defending king in the corner
+            "8/8/4k1b1/8/8/3KB3/8/8 w - - 0 1",
+            "8/8/4kn2/8/8/3KN3/8/8 w - - 0 1",
+            "8/8/4k1b1/8/8/3KN3/8/8 w - - 0 1",
+        ];
+        for fen in alive {
+            assert!(!parse_fen(fen).unwrap().is_dead_position(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_capture_victim() {
+        let state =
+            parse_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/5N2/PPPP1PPP/RNBQKB1R w KQkq f6 0 1").unwrap();
+        let victim = |lan| state.capture_victim(Move::from_long_algebraic(lan).unwrap());
+
+        // En passant, where the victim isn't on the destination square
+        assert_eq!(victim("e5f6"), Some(Piece::Pawn));
+        assert!(state.board.get(F6).is_none());
+        assert!(state.is_capture_move(Move::from_long_algebraic("e5f6").unwrap()));
+
+        let state = parse_fen("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            state.capture_victim(Move::from_long_algebraic("e4d5").unwrap()),
+            Some(Piece::Queen)
+        );
+
+        for lan in ["e4e5", "e1d2"] {
+            let m = Move::from_long_algebraic(lan).unwrap();
+            assert_eq!(state.capture_victim(m), None, "{}", lan);
+            assert!(!state.is_capture_move(m), "{}", lan);
+        }
+    }
+
+    #[test]
+    fn test_legal_moves_from() {
+        let dests = |state: &State, source| {
+            state
+                .legal_moves_from(source)
+                .dest_set
+                .iter_set()
+                .collect::<Vec<_>>()
+        };
+
+        // The bishop on d2 is pinned, so can only move along the pin
+        let state = parse_fen("4k3/8/8/b7/8/8/3B1n2/4K2R w K - 0 1").unwrap();
+        assert_eq!(dests(&state, D2), vec![C3, B4, A5]);
+
+        // The king can castle or take the knight, but not step onto d1 which the knight attacks
+        assert_eq!(dests(&state, E1), vec![F1, G1, E2, F2]);
+
+        // Each promotion of a promoting pawn
+        let state = parse_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let promotions = state.legal_moves_from(A7).iter().collect::<Vec<_>>();
+        assert_eq!(promotions.len(), 8);
+        assert!(promotions.iter().all(|m| m.promotion.is_some()));
+
+        // Empty squares and the other player's pieces have no moves
+        assert!(!state.legal_moves_from(A1).any());
+        assert!(!state.legal_moves_from(B8).any());
+
+        // The same moves as the full move generator, including in check and double check
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "7k/3p4/8/K1P4r/8/8/8/8 w - - 0 1",
+            "8/1p3k2/8/2P5/2K5/8/8/8 b - - 0 1",
+            "4k3/8/8/8/8/5n2/8/r3K2R w K - 0 1",
+        ] {
+            let state = parse_fen(fen).unwrap();
+            let expected = legal_moves(&state).sorted_vec();
+            let mut actual = BoardPos::iter_all()
+                .flat_map(|pos| state.legal_moves_from(pos).iter())
+                .collect::<Vec<_>>();
+            actual.sort();
+            assert_eq!(actual, expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_apply_move_no_legality() {
+        // Some of the moves give check, and Qe4 pins the knight
+        let state = parse_fen("r3k3/4n3/8/8/1Q6/8/3N4/R3K2R w KQq - 0 1").unwrap();
+
+        let (mut gave_check, mut pinned) = (false, false);
+        for m in legal_moves(&state).iter() {
+            let full = state.apply_move(m);
+            gave_check |= full.in_check();
+            pinned |= full.pinned().any();
+
+            let mut fast = state.apply_move_no_legality(m);
+            assert!(!fast.in_check());
+            assert!(!fast.pinned().any());
+
+            fast.recompute_pins_and_checks();
+            assert_eq!(fast.checkers, full.checkers, "{}", m);
+            assert_eq!(fast.pinned, full.pinned, "{}", m);
+            assert_eq!(fast.zobrist, full.zobrist, "{}", m);
+            assert_eq!(format_fen(&fast), format_fen(&full), "{}", m);
+        }
+        assert!(gave_check && pinned);
+    }
+
+    #[test]
+    fn test_make_null_move() {
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+
+        let passed = state.make_null_move();
+        assert_eq!(passed.board, state.board);
+        assert_eq!(passed.to_play, Color::Black);
+        assert_ne!(passed.zobrist, state.zobrist);
+        assert_eq!(passed.zobrist, zobrist::calculate_entire_zobrist(&passed));
+
+        let passed_twice = passed.make_null_move();
+        assert_eq!(passed_twice.to_play, Color::White);
+        assert_eq!(passed_twice.zobrist, state.zobrist);
+
+        // The en passant square is lost by passing
+        let state =
+            parse_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1").unwrap();
+        let passed = state.make_null_move();
+        assert_eq!(passed.en_passant, None);
+        assert_eq!(passed.zobrist, zobrist::calculate_entire_zobrist(&passed));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_make_null_move_in_check() {
+        parse_fen("4k3/8/8/8/8/8/4q3/4K3 w - - 0 1")
+            .unwrap()
+            .make_null_move();
+    }
+
+    #[test]
+    fn test_new_empty_zobrist() {
+        let empty = State::new_empty();
+        assert_eq!(empty.zobrist, zobrist::calculate_entire_zobrist(&empty));
+
+        // The empty board with white to play is distinguished from the same with black to play
+        let mut black_to_play = empty;
+        black_to_play.to_play = Color::Black;
+        assert_ne!(
+            empty.zobrist,
+            zobrist::calculate_entire_zobrist(&black_to_play)
+        );
+
+        // Pieces added one at a time hash the same as the parsed position
+        let parsed = parse_fen("8/8/4k3/8/8/3K4/3P4/8 w - - 0 1").unwrap();
+        let mut built = State::new_empty();
+        for (pos, color, piece) in parsed.board.iter_pieces() {
+            built.add_piece(color, piece, pos);
+        }
+        assert_eq!(built.zobrist, parsed.zobrist);
+    }
+
+    #[test]
+    fn test_castle_rights_fen() {
+        let cases = [
+            (CastleRights::empty(), "-", "-"),
+            (CastleRights::WHITE_KINGSIDE, "K", "H"),
+            (
+                CastleRights::WHITE_QUEENSIDE | CastleRights::BLACK_KINGSIDE,
+                "Qk",
+                "Ah",
+            ),
+            (CastleRights::ALL_BLACK, "kq", "ha"),
+            (CastleRights::all(), "KQkq", "HAha"),
+        ];
+
+        for (rights, fen, shredder) in cases {
+            assert_eq!(rights.to_fen_string(), fen);
+            assert_eq!(rights.to_string(), fen);
+            assert_eq!(rights.to_shredder_fen_string(), shredder);
+            assert_eq!(CastleRights::from_fen_str(fen), Ok(rights));
+            assert_eq!(CastleRights::from_fen_str(shredder), Ok(rights));
+        }
+
+        // The letters can come in any order, and the two notations can be mixed
+        assert_eq!(CastleRights::from_fen_str("qKAh"), Ok(CastleRights::all()));
+
+        assert_eq!(
+            CastleRights::from_fen_str("KQx"),
+            Err(FenParseError::InvalidCastlingRightsChar('x'))
+        );
+        // A rook that doesn't start on the A or H files can never castle in standard chess
+        assert_eq!(
+            CastleRights::from_fen_str("B"),
+            Err(FenParseError::InvalidCastlingRightsChar('B'))
+        );
+    }
+
+    #[test]
+    fn test_checkers_and_pinners() {
+        // The bishop on b4 pins the knight on c3, the rook on e8 gives check, and the rook on h1
+        // is lined up with the king but blocked by one of its own pieces
+        let state = parse_fen("4r1k1/8/8/8/1b6/2N5/8/4K1nr w - - 0 1").unwrap();
+        assert_eq!(
+            state.checkers_and_pinners(),
+            (BitBoard::single(E8), BitBoard::single(B4))
+        );
+        assert_eq!(state.pinner_of(C3), Some(B4));
+
+        // Nothing pins or checks in the starting position
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(
+            state.checkers_and_pinners(),
+            (BitBoard::new_empty(), BitBoard::new_empty())
+        );
+    }
+
+    #[test]
+    fn test_recompute_all() {
+        let fen = "4k3/8/8/8/8/8/3P4/r3K3 w - - 0 1";
+        let parsed = parse_fen(fen).unwrap();
+
+        // Set up the same position by hand, bypassing the bookkeeping of `add_piece` for the pawn
+        let mut state = State::new_empty();
+        state.add_piece(Color::White, Piece::King, E1);
+        state.add_piece(Color::Black, Piece::King, E8);
+        state.add_piece(Color::Black, Piece::Rook, A1);
+        state.board.add_piece(D2, Color::White, Piece::Pawn);
+        state.fullmove_counter = 1;
+
+        // Until the derived state is recomputed the check from the rook goes unnoticed, so moves
+        // that leave the king in check are generated
+        assert_eq!(format_fen(&state), fen);
+        assert!(!state.in_check());
+        assert_ne!(
+            legal_moves(&state).sorted_vec(),
+            legal_moves(&parsed).sorted_vec()
+        );
+        assert_ne!(state.zobrist, parsed.zobrist);
+        assert_ne!(state.accumulator, parsed.accumulator);
+
+        state.recompute_all();
+        assert_eq!(state.checkers, BitBoard::single(A1));
+        assert_eq!(
+            legal_moves(&state).sorted_vec(),
+            legal_moves(&parsed).sorted_vec()
+        );
+        assert_eq!(state.zobrist, parsed.zobrist);
+        assert_eq!(state.accumulator, parsed.accumulator);
+    }
+
+    #[test]
+    fn test_from_placement() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        ];
+
+        for fen in fens {
+            let parsed = parse_fen(fen).unwrap();
+            let squares = parsed.board.to_array();
+            assert_eq!(Board::from_array(&squares), parsed.board, "{}", fen);
+
+            let placed = State::from_placement(
+                &squares,
+                parsed.to_play,
+                parsed.castle_rights,
+                parsed.en_passant,
+            )
+            .unwrap();
+            assert_eq!(format_fen(&placed), fen);
+            assert_eq!(placed.zobrist, parsed.zobrist, "{}", fen);
+            assert_eq!(placed.checkers, parsed.checkers, "{}", fen);
+            assert_eq!(placed.pinned, parsed.pinned, "{}", fen);
+            assert_eq!(placed.accumulator, parsed.accumulator, "{}", fen);
+        }
+
+        let place = |pieces: &[(BoardPos, Color, Piece)], to_play| {
+            let mut squares = [None; 64];
+            for &(pos, color, piece) in pieces {
+                squares[pos.to_bitboard_offset() as usize] = Some((color, piece));
+            }
+            State::from_placement(&squares, to_play, CastleRights::empty(), None).err()
+        };
+        let white_king = (D3, Color::White, Piece::King);
+        let black_king = (E6, Color::Black, Piece::King);
+        let extra_king = (G1, Color::Black, Piece::King);
+        let back_pawn = (D8, Color::White, Piece::Pawn);
+        let rook = (E1, Color::White, Piece::Rook);
+        assert_eq!(
+            place(&[black_king], Color::White),
+            Some(PlacementError::KingCount(Color::White))
+        );
+        assert_eq!(
+            place(&[white_king, black_king, extra_king], Color::White),
+            Some(PlacementError::KingCount(Color::Black))
+        );
+        assert_eq!(
+            place(&[white_king, black_king, back_pawn], Color::White),
+            Some(PlacementError::PawnOnBackRank(D8))
+        );
+        assert_eq!(
+            place(&[white_king, black_king, rook], Color::White),
+            Some(PlacementError::OpponentInCheck)
+        );
+        assert_eq!(place(&[white_king, black_king, rook], Color::Black), None);
+    }
+
     fn test_apply_move_helper(fen_start: &str, lan_move: &str, expected_fen_end: &str) {
         let state =
             parse_fen(fen_start).expect("Expected test case to have valid starting FEN string");
@@ -0,0 +1,143 @@
+//! Recognition of simple tactical motifs in a position, for tools that generate or explain
+//! puzzles rather than for the search.
+
+use crate::bitboard::masks;
+use crate::movegen::attackers_of;
+use crate::{BitBoard, BoardPos, Color, Piece, State};
+
+/// A rough ordering of how valuable each kind of piece is, for deciding whether an attack on a
+/// piece is a threat.
+const fn motif_value(piece: Piece) -> u8 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight | Piece::Bishop => 3,
+        Piece::Rook => 5,
+        Piece::Queen => 9,
+        Piece::King => u8::MAX,
+    }
+}
+
+/// Every pin on the pieces of the given color, as (pinner, pinned, target) squares.
+///
+/// The target behind the pinned piece is either the king, for an absolute pin, or a queen, for a
+/// relative pin. Queens pinning a piece to a queen are ignored, as the queen being pinned to could
+/// simply recapture. Absolute pins are listed first.
+pub fn find_pins(state: &State, color: Color) -> Vec<(BoardPos, BoardPos, BoardPos)> {
+    let board = &state.board;
+    let occupied = board.all_union_board();
+    let our_pieces = board.color_board(color);
+    let opp_pieces = board.color_board(!color);
+
+    let kings = board.color_piece_board(color, Piece::King);
+    let queens = board.color_piece_board(color, Piece::Queen);
+
+    let mut pins = Vec::new();
+    for target in kings.iter_set().chain(queens.iter_set()) {
+        let target_piece = board.piece_kind_at(target);
+
+        let mut rooks = board.piece_board(Piece::Rook);
+        let mut bishops = board.piece_board(Piece::Bishop);
+        if target_piece == Some(Piece::King) {
+            rooks.union_inplace(board.piece_board(Piece::Queen));
+            bishops.union_inplace(board.piece_board(Piece::Queen));
+        }
+
+        let pinners = rooks
+            .intersect_with(masks::rook_rays(target))
+            .union_with(bishops.intersect_with(masks::bishop_rays(target)))
+            .intersect_with(opp_pieces);
+
+        for pinner in pinners.iter_set() {
+            let between = masks::between(pinner, target).intersect_with(occupied);
+            if between.count() != 1 || !between.intersect_with(our_pieces).any() {
+                continue;
+            }
+
+            let pinned = between.first_set().unwrap();
+            if board.piece_kind_at(pinned) != Some(Piece::King) {
+                pins.push((pinner, pinned, target));
+            }
+        }
+    }
+
+    pins
+}
+
+/// Every fork of the pieces of the given color, as the forking piece and the pieces it forks.
+///
+/// A piece forks two or more enemy pieces when it attacks each of them and each is either the
+/// king or worth more than the forking piece, so that the attack is a threat to each regardless of
+/// whether they are defended.
+pub fn find_forks(state: &State, color: Color) -> Vec<(BoardPos, BitBoard)> {
+    let board = &state.board;
+
+    let value_at = |pos| motif_value(board.piece_kind_at(pos).unwrap());
+
+    // For each of the forking side's pieces, the enemy pieces that it threatens
+    let mut threatened = [BitBoard::new_empty(); 64];
+    for target in board.color_board(color).iter_set() {
+        for attacker in attackers_of(state, target, !color).iter_set() {
+            if value_at(target) > value_at(attacker) {
+                threatened[attacker.to_bitboard_offset() as usize].set(target);
+            }
+        }
+    }
+
+    board
+        .color_board(!color)
+        .iter_set()
+        .map(|forker| (forker, threatened[forker.to_bitboard_offset() as usize]))
+        .filter(|(_, targets)| targets.count() >= 2)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::coordinates::consts::*;
+    use crate::io::fen::parse_fen;
+
+    #[test]
+    fn test_find_pins() {
+        // The bishop on b4 pins the knight on c3 to the king, and the rook on e8 pins the bishop on
+        // e4 to the queen, but not to the king as the queen is also in the way
+        let state = parse_fen("4r1k1/8/8/8/1b2B3/2N5/4Q3/4K3 w - - 0 1").unwrap();
+
+        assert_eq!(
+            find_pins(&state, Color::White),
+            vec![(B4, C3, E1), (E8, E4, E2)]
+        );
+        assert_eq!(find_pins(&state, Color::Black), Vec::new());
+
+        // Absolute pins on the player to move are exactly the pins the state already tracks
+        let absolute = find_pins(&state, state.to_play)
+            .into_iter()
+            .filter(|&(_, _, target)| target == state.king_pos(state.to_play))
+            .collect::<Vec<_>>();
+        assert_eq!(absolute.len(), state.pinned().count() as usize);
+        for (pinner, pinned, _) in absolute {
+            assert_eq!(state.pinner_of(pinned), Some(pinner));
+        }
+
+        // A piece between the two queens isn't pinned, as our queen could just recapture
+        let state = parse_fen("3qk3/8/8/3N4/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert_eq!(find_pins(&state, Color::White), Vec::new());
+    }
+
+    #[test]
+    fn test_find_forks() {
+        // The knight on d6 forks the king and rook, but the bishop on a6 only threatens the rook
+        let state = parse_fen("2r1k3/8/B2N4/8/8/8/8/4K3 b - - 0 1").unwrap();
+
+        assert_eq!(
+            find_forks(&state, Color::Black),
+            vec![(D6, BitBoard::single(C8).with_set(E8))]
+        );
+        assert_eq!(find_forks(&state, Color::White), Vec::new());
+
+        // A queen attacking two pawns doesn't fork them, as neither is worth more than the queen
+        let state = parse_fen("4k3/8/8/2p1p3/3Q4/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(find_forks(&state, Color::Black), Vec::new());
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Serialize, Deserialize};
 
-use crate::state::{CastleRights, State};
+use crate::state::{CastleRights, CastleSide, State};
 use crate::{BoardPos, Color, Piece};
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
@@ -24,9 +24,19 @@ pub mod consts {
     include!(concat!(env!("OUT_DIR"), "/zobrist_gen.rs"));
 }
 
-pub const fn castling_number(castle_rights: CastleRights) -> ZobristHash {
-    let cr_num = castle_rights.bits() as usize;
-    consts::ZOBRIST_CASTLING[cr_num]
+/// The zobrist number for `color` being allowed to castle on `side`
+pub const fn castle_right_number(color: Color, side: CastleSide) -> ZobristHash {
+    let idx = CastleRights::flag(color, side).bits().trailing_zeros() as usize;
+    consts::ZOBRIST_CASTLING[idx]
+}
+
+/// The combined zobrist number of every right that is set in `castle_rights`
+pub fn castling_number(castle_rights: CastleRights) -> ZobristHash {
+    let mut num = ZobristHash::null();
+    for (color, side) in castle_rights.iter_set() {
+        num ^= castle_right_number(color, side);
+    }
+    num
 }
 
 pub const fn to_play_num(to_play: Color) -> ZobristHash {
@@ -0,0 +1,78 @@
+use std::io::{Cursor, Write};
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Sender};
+
+use pewter_core::io::uci::*;
+
+#[derive(Default)]
+struct NoOptions;
+
+impl UciOptions for NoOptions {
+    type SetOptionError = ();
+
+    fn all_options() -> Vec<OptionMessage> {
+        Vec::new()
+    }
+
+    fn set_value(&mut self, _option_name: &str, _value: &str) -> Result<(), Self::SetOptionError> {
+        Err(())
+    }
+}
+
+/// Forwards everything written to it over a channel, so that the test can observe the output
+/// while the interface still owns the writer.
+struct ChannelWriter(Sender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, e))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_uci_handshake_over_in_memory_io() {
+    let input = Cursor::new(b"uci\nisready\nquit\n".to_vec());
+    let (output_tx, output_rx) = unbounded();
+
+    let uci = UciInterface::<NoOptions>::with_io(input, ChannelWriter(output_tx))
+        .expect("Expected to be able to start the UCI interface");
+
+    // Play the part of the engine, responding to each command as it arrives
+    let timeout = Duration::from_secs(5);
+    loop {
+        let cmd = uci
+            .rx
+            .recv_timeout(timeout)
+            .expect("Expected a UCI command");
+        match cmd {
+            UciCommand::Uci => {
+                uci.tx
+                    .send(UciMessage::Id(EngineId::Name("pewter".to_string())))
+                    .unwrap();
+                uci.tx.send(UciMessage::UciOk).unwrap();
+            }
+            UciCommand::IsReady => uci.tx.send(UciMessage::ReadyOk).unwrap(),
+            UciCommand::Quit => break,
+            cmd => panic!("Unexpected command {:?}", cmd),
+        }
+    }
+
+    let mut output = String::new();
+    while !output.contains("readyok") {
+        let chunk = output_rx
+            .recv_timeout(timeout)
+            .expect("Expected the interface to write a response");
+        output.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines, ["id name pewter", "uciok", "readyok"]);
+}
@@ -0,0 +1,283 @@
+//! Exact knowledge of simple endgames, which the search would otherwise have to work out the hard
+//! way.
+
+use std::sync::OnceLock;
+
+use pewter_core::{BoardPos, Color, Piece, State};
+
+/// The game theoretic result of a position, from the perspective of the player to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wdl {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// The result of a king and pawn versus king position, or None if the position isn't one.
+///
+/// Looks the position up in a bitbase covering every KPvK position, which is generated on first use
+/// by retrograde analysis.
+pub fn kpk_result(state: &State) -> Option<Wdl> {
+    let board = &state.board;
+    if board.all_union_board().count() != 3 || board.piece_board(Piece::Pawn).count() != 1 {
+        return None;
+    }
+
+    let pawn = board
+        .piece_board(Piece::Pawn)
+        .first_set()
+        .expect("Expected exactly one pawn");
+    let (strong, _) = board
+        .get(pawn)
+        .expect("Expected a piece on the pawn square");
+
+    // The bitbase is stored with the pawn belonging to white, so flip the board vertically if the
+    // pawn is black's.
+    let normalize = |pos: BoardPos| match strong {
+        Color::White => pos.to_bitboard_offset(),
+        Color::Black => pos.to_bitboard_offset() ^ 56,
+    };
+    let strong_king = normalize(state.king_pos(strong));
+    let weak_king = normalize(state.king_pos(!strong));
+    let pawn = normalize(pawn);
+
+    let strong_to_move = state.to_play == strong;
+    let idx = index(strong_to_move, strong_king, weak_king, pawn);
+
+    Some(match (kpk_bitbase()[idx], strong_to_move) {
+        (Entry::Win, true) => Wdl::Win,
+        (Entry::Win, false) => Wdl::Loss,
+        _ => Wdl::Draw,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Entry {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+/// Indexed by whether white is to move, then white king, black king, and white pawn offsets
+const KPK_SIZE: usize = 2 * 64 * 64 * 64;
+
+fn index(white_to_move: bool, white_king: u8, black_king: u8, pawn: u8) -> usize {
+    let stm = if white_to_move { 0 } else { 1 };
+    ((stm * 64 + white_king as usize) * 64 + black_king as usize) * 64 + pawn as usize
+}
+
+fn kpk_bitbase() -> &'static [Entry] {
+    static BITBASE: OnceLock<Vec<Entry>> = OnceLock::new();
+    BITBASE.get_or_init(generate_kpk)
+}
+
+fn neighbors(sq: u8) -> impl Iterator<Item = u8> {
+    BoardPos::from_bitboard_offset(sq)
+        .neighbors()
+        .map(|pos| pos.to_bitboard_offset())
+}
+
+fn adjacent(a: u8, b: u8) -> bool {
+    let df = (a % 8) as i8 - (b % 8) as i8;
+    let dr = (a / 8) as i8 - (b / 8) as i8;
+    df.abs() <= 1 && dr.abs() <= 1
+}
+
+fn pawn_attacks(pawn: u8, sq: u8) -> bool {
+    let df = (sq % 8) as i8 - (pawn % 8) as i8;
+    sq / 8 == pawn / 8 + 1 && df.abs() == 1
+}
+
+/// The legal moves of the black king, other than capturing the pawn
+fn black_king_moves(white_king: u8, black_king: u8, pawn: u8) -> impl Iterator<Item = u8> {
+    neighbors(black_king)
+        .filter(move |&sq| sq != pawn && !adjacent(sq, white_king) && !pawn_attacks(pawn, sq))
+}
+
+/// Classify the positions that can be decided without looking at any other positions
+fn initial_entry(white_to_move: bool, white_king: u8, black_king: u8, pawn: u8) -> Entry {
+    let pawn_rank = pawn / 8;
+    if pawn_rank == 0
+        || pawn_rank == 7
+        || white_king == pawn
+        || black_king == pawn
+        || adjacent(white_king, black_king)
+    {
+        return Entry::Invalid;
+    }
+
+    if white_to_move {
+        if pawn_attacks(pawn, black_king) {
+            return Entry::Invalid;
+        }
+
+        // Promoting is a win whenever the new queen can't immediately be taken
+        let promotion = pawn + 8;
+        if pawn_rank == 6
+            && promotion != white_king
+            && promotion != black_king
+            && (!adjacent(black_king, promotion) || adjacent(white_king, promotion))
+        {
+            return Entry::Win;
+        }
+    } else {
+        if adjacent(black_king, pawn) && !adjacent(white_king, pawn) {
+            return Entry::Draw;
+        }
+
+        if black_king_moves(white_king, black_king, pawn)
+            .next()
+            .is_none()
+        {
+            return if pawn_attacks(pawn, black_king) {
+                Entry::Win
+            } else {
+                Entry::Draw
+            };
+        }
+    }
+
+    Entry::Unknown
+}
+
+fn generate_kpk() -> Vec<Entry> {
+    let mut db = vec![Entry::Invalid; KPK_SIZE];
+    for &white_to_move in &[true, false] {
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                for pawn in 0..64 {
+                    db[index(white_to_move, white_king, black_king, pawn)] =
+                        initial_entry(white_to_move, white_king, black_king, pawn);
+                }
+            }
+        }
+    }
+
+    // Repeatedly resolve unknown positions from the positions that they lead to, until nothing
+    // changes. Every won position is eventually proven to be a win, so anything left unknown is a
+    // draw.
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                for pawn in 8..56 {
+                    let idx = index(true, white_king, black_king, pawn);
+                    if db[idx] == Entry::Unknown {
+                        db[idx] = classify_white(&db, white_king, black_king, pawn);
+                        changed |= db[idx] != Entry::Unknown;
+                    }
+
+                    let idx = index(false, white_king, black_king, pawn);
+                    if db[idx] == Entry::Unknown {
+                        db[idx] = classify_black(&db, white_king, black_king, pawn);
+                        changed |= db[idx] != Entry::Unknown;
+                    }
+                }
+            }
+        }
+    }
+
+    for entry in db.iter_mut() {
+        if *entry == Entry::Unknown {
+            *entry = Entry::Draw;
+        }
+    }
+
+    db
+}
+
+/// White wins if any move wins, and draws if every move draws
+fn classify_white(db: &[Entry], white_king: u8, black_king: u8, pawn: u8) -> Entry {
+    let king_moves = neighbors(white_king)
+        .filter(|&sq| sq != pawn && !adjacent(sq, black_king))
+        .map(|sq| index(false, sq, black_king, pawn));
+
+    // Promotions are already accounted for by the initial classification
+    let single_push = pawn + 8;
+    let double_push = pawn + 16;
+    let push_blocked = |sq| sq == white_king || sq == black_king;
+    let mut pawn_moves = Vec::with_capacity(2);
+    if single_push / 8 < 7 && !push_blocked(single_push) {
+        pawn_moves.push(index(false, white_king, black_king, single_push));
+        if pawn / 8 == 1 && !push_blocked(double_push) {
+            pawn_moves.push(index(false, white_king, black_king, double_push));
+        }
+    }
+
+    let mut all_draw = true;
+    for child in king_moves.chain(pawn_moves) {
+        match db[child] {
+            Entry::Win => return Entry::Win,
+            Entry::Draw => (),
+            _ => all_draw = false,
+        }
+    }
+
+    if all_draw {
+        Entry::Draw
+    } else {
+        Entry::Unknown
+    }
+}
+
+/// Black draws if any move draws, and loses if every move loses
+fn classify_black(db: &[Entry], white_king: u8, black_king: u8, pawn: u8) -> Entry {
+    let mut all_win = true;
+    for sq in black_king_moves(white_king, black_king, pawn) {
+        match db[index(true, white_king, sq, pawn)] {
+            Entry::Draw => return Entry::Draw,
+            Entry::Win => (),
+            _ => all_win = false,
+        }
+    }
+
+    if all_win {
+        Entry::Win
+    } else {
+        Entry::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::fen::parse_fen;
+
+    #[test]
+    fn test_kpk_result() {
+        let cases = [
+            // King on the sixth rank in front of its pawn wins whoever is to move
+            ("3k4/8/3K4/3P4/8/8/8/8 w - - 0 1", Some(Wdl::Win)),
+            ("3k4/8/3K4/3P4/8/8/8/8 b - - 0 1", Some(Wdl::Loss)),
+            // The defending king in front of a rook pawn can't be driven away
+            ("7k/8/8/8/8/8/7P/7K w - - 0 1", Some(Wdl::Draw)),
+            ("k7/8/8/8/8/8/P7/K7 b - - 0 1", Some(Wdl::Draw)),
+            // The pawn is outside the square of the king, and runs through
+            ("8/8/8/k7/8/8/6P1/6K1 w - - 0 1", Some(Wdl::Win)),
+            // ...but is caught when the king is inside the square
+            ("8/8/8/8/4k3/8/7P/K7 b - - 0 1", Some(Wdl::Draw)),
+            // The pawn is lost immediately
+            ("8/8/8/8/8/3k4/3P4/7K b - - 0 1", Some(Wdl::Draw)),
+            // The king already stands on one of the pawn's key squares
+            ("3k4/8/3K4/8/3P4/8/8/8 b - - 0 1", Some(Wdl::Loss)),
+            // Opposition: whoever has to move gives way
+            ("8/3k4/8/3K4/3P4/8/8/8 w - - 0 1", Some(Wdl::Draw)),
+            ("8/3k4/8/3K4/3P4/8/8/8 b - - 0 1", Some(Wdl::Loss)),
+            // The same with black's pawn, so the board is flipped internally
+            ("8/8/8/3p4/3k4/8/3K4/8 w - - 0 1", Some(Wdl::Loss)),
+            ("8/8/8/3p4/3k4/8/3K4/8 b - - 0 1", Some(Wdl::Draw)),
+            // Not a KPvK position at all
+            ("3k4/8/3K4/3P4/3P4/8/8/8 w - - 0 1", None),
+            ("3k4/8/3K4/3N4/8/8/8/8 w - - 0 1", None),
+        ];
+
+        for (fen, expected) in cases {
+            let state = parse_fen(fen).unwrap();
+            assert_eq!(kpk_result(&state), expected, "{}", fen);
+        }
+    }
+}
@@ -1,14 +1,13 @@
-use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use anyhow::Result;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 
-use super::{EngineError, PerfInfo, SearchControls, Timings};
-use pewter_core::{Move, State};
+use super::{EngineConfig, EngineError, PerfInfo, SearchControls, Timings};
+use pewter_core::{zobrist::ZobristHash, Move, State};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 struct BeginSearchArgs {
     /// Ignore all time controls, and compute until the Stop command is received
     infinite: bool,
@@ -19,16 +18,33 @@ struct BeginSearchArgs {
     /// Automatically stop after searching this many nodes
     max_nodes: Option<u64>,
 
+    /// Search for a forced mate in this many moves instead of for the best move
+    mate: Option<u8>,
+
     /// The most up-to-date time control information for this search
     ///
     /// Completely ignored if an infinite search is requested
     timings: Option<Timings>,
+
+    /// Only consider these moves from the root, see `Engine::set_search_moves`
+    search_moves: Option<Vec<Move>>,
+}
+
+/// The conclusion of a search run by the engine server
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BestMove {
+    pub best_move: Move,
+
+    /// The reply that the engine expects, if it has one
+    pub ponder_move: Option<Move>,
 }
 
 /// Used internally in the engine server to give instructions to the main engine thread
 #[derive(Clone, Debug)]
 enum EngineCommand {
-    SetState(State),
+    SetState(State, Vec<ZobristHash>),
+    Configure(EngineConfig),
+    NewGame,
     BeginSearch(BeginSearchArgs),
     Exit,
 }
@@ -36,33 +52,65 @@ enum EngineCommand {
 pub struct EngineServer {
     cmd_tx: Sender<EngineCommand>,
     search_stopper: Arc<AtomicBool>,
+
+    /// Shared with every search, so that debug mode can be toggled while one is running
+    debug: Arc<AtomicBool>,
+
     pub perf_rx: Receiver<PerfInfo>,
-    pub best_move_rx: Receiver<Move>,
+    pub best_move_rx: Receiver<BestMove>,
 }
 
 impl EngineServer {
     pub fn startup() -> Result<Self> {
+        Self::startup_with_config(EngineConfig::default())
+    }
+
+    /// Start the engine thread, configuring the engine with the given config before it is given
+    /// any commands.
+    pub fn startup_with_config(config: EngineConfig) -> Result<Self> {
         let (cmd_tx, cmd_rx) = unbounded();
         let (perf_tx, perf_rx) = unbounded();
         let (best_move_tx, best_move_rx) = unbounded();
 
         let search_stopper = Arc::new(AtomicBool::new(false));
-        let search_stopper_clone = search_stopper.clone();
+        let debug = Arc::new(AtomicBool::new(false));
+        let controls = SearchControls {
+            stop: search_stopper.clone(),
+            perf_info: Some(perf_tx),
+            debug: debug.clone(),
+        };
 
         std::thread::Builder::new()
             .name("EngineServer main".to_string())
-            .spawn(|| engine_main_thread(cmd_rx, perf_tx, best_move_tx, search_stopper_clone))?;
+            .spawn(|| engine_main_thread(config, cmd_rx, controls, best_move_tx))?;
 
         Ok(Self {
             cmd_tx,
             perf_rx,
             best_move_rx,
             search_stopper,
+            debug,
         })
     }
 
-    pub fn set_state(&mut self, new_state: State) -> Result<()> {
-        self.cmd_tx.send(EngineCommand::SetState(new_state))?;
+    /// Set the position to search from, along with the hashes of the positions played before it
+    /// in this game, see `Engine::set_game_history`
+    pub fn set_state(&mut self, new_state: State, game_history: Vec<ZobristHash>) -> Result<()> {
+        self.cmd_tx
+            .send(EngineCommand::SetState(new_state, game_history))?;
+        Ok(())
+    }
+
+    /// The following positions are from a different game, so nothing learned from searching the
+    /// earlier ones applies, see `Engine::new_game`
+    pub fn new_game(&mut self) -> Result<()> {
+        self.cmd_tx.send(EngineCommand::NewGame)?;
+        Ok(())
+    }
+
+    /// Reconfigure the engine, taking effect from the next search
+    pub fn configure(&mut self, config: EngineConfig) -> Result<()> {
+        self.cmd_tx.send(EngineCommand::Configure(config))?;
         Ok(())
     }
 
@@ -71,13 +119,17 @@ impl EngineServer {
         infinite: bool,
         max_depth: Option<u8>,
         max_nodes: Option<u64>,
+        mate: Option<u8>,
         timings: Option<Timings>,
+        search_moves: Option<Vec<Move>>,
     ) -> Result<()> {
         let args = BeginSearchArgs {
             infinite,
             max_depth,
             max_nodes,
+            mate,
             timings,
+            search_moves,
         };
 
         self.search_stopper.store(false, Ordering::Relaxed);
@@ -91,6 +143,13 @@ impl EngineServer {
         self.search_stopper.store(true, Ordering::Relaxed);
         Ok(())
     }
+
+    /// Enable or disable debug mode, in which searches send notes on what they're doing as
+    /// `PerfInfo::info_string`s. This takes effect immediately, even for a search in progress.
+    pub fn set_debug(&mut self, debug: bool) -> Result<()> {
+        self.debug.store(debug, Ordering::Relaxed);
+        Ok(())
+    }
 }
 
 impl Drop for EngineServer {
@@ -101,12 +160,12 @@ impl Drop for EngineServer {
 }
 
 fn engine_main_thread(
+    config: EngineConfig,
     cmd_rx: Receiver<EngineCommand>,
-    perf_tx: Sender<PerfInfo>,
-    best_move_tx: Sender<Move>,
-    search_stopper: Arc<AtomicBool>,
+    controls: SearchControls,
+    best_move_tx: Sender<BestMove>,
 ) -> Result<()> {
-    let r = engine_main_thread_inner(cmd_rx, perf_tx, best_move_tx, search_stopper);
+    let r = engine_main_thread_inner(config, cmd_rx, controls, best_move_tx);
 
     if let Err(e) = r.as_ref() {
         tracing::error!("Engine main thread exiting because: {:?}", e);
@@ -118,48 +177,38 @@ fn engine_main_thread(
 }
 
 fn engine_main_thread_inner(
+    config: EngineConfig,
     cmd_rx: Receiver<EngineCommand>,
-    perf_tx: Sender<PerfInfo>,
-    best_move_tx: Sender<Move>,
-    search_stopper: Arc<AtomicBool>,
+    controls: SearchControls,
+    best_move_tx: Sender<BestMove>,
 ) -> Result<()> {
     let mut engine = super::Engine::new();
-
-    // TODO: Options/configuration system rather than hardcoded DB path
-    let db_path = Path::new("./opening_db.dat");
-    let workdir = std::env::current_dir().unwrap();
-    let workdir = workdir.to_string_lossy();
-    if db_path.exists() {
-        tracing::debug!(
-            "Loading opening db from {} in {}",
-            db_path.to_string_lossy(),
-            workdir
-        );
-        engine.load_opening_db(Path::new("./opening_db.dat"))?;
-    } else {
-        tracing::debug!(
-            "Couldn't find opening db at {} in {}, proceeding without",
-            db_path.to_string_lossy(),
-            workdir
-        );
-    }
+    engine.configure(config)?;
 
     for cmd in cmd_rx {
         match cmd {
-            EngineCommand::SetState(state) => engine.set_board_state(state),
+            EngineCommand::SetState(state, game_history) => {
+                engine.set_board_state(state);
+                engine.set_game_history(game_history);
+            }
+            EngineCommand::Configure(config) => engine.configure(config)?,
+            EngineCommand::NewGame => engine.new_game(),
             EngineCommand::BeginSearch(args) => {
-                let controls = SearchControls {
-                    stop: search_stopper.clone(),
-                    perf_info: Some(perf_tx.clone()),
+                let controls = controls.clone();
+                engine.set_search_moves(args.search_moves);
+                let result = match args.mate {
+                    Some(moves) => engine.search_mate(moves, controls),
+                    None => engine.search_best_move(
+                        args.infinite,
+                        args.max_depth,
+                        args.max_nodes,
+                        args.timings,
+                        true,
+                        controls,
+                    ),
                 };
 
-                let best_move = match engine.search_best_move(
-                    args.infinite,
-                    args.max_depth,
-                    args.max_nodes,
-                    args.timings,
-                    controls,
-                ) {
+                let best_move = match result {
                     Ok(m) => m,
                     Err(EngineError::EarlyStop) => {
                         tracing::warn!("Search stop was requested before the first move was found");
@@ -168,7 +217,10 @@ fn engine_main_thread_inner(
                     other => other?,
                 };
 
-                best_move_tx.send(best_move)?;
+                best_move_tx.send(BestMove {
+                    best_move,
+                    ponder_move: engine.ponder_move(),
+                })?;
             }
             EngineCommand::Exit => break,
         }
@@ -176,3 +228,86 @@ fn engine_main_thread_inner(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::fen::parse_fen;
+    use std::time::Duration;
+
+    #[test]
+    fn test_best_move_has_ponder_move() {
+        let mut server = EngineServer::startup_with_config(EngineConfig {
+            book_path: None,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        server
+            .set_state(
+                parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                    .unwrap(),
+                Vec::new(),
+            )
+            .unwrap();
+        server
+            .begin_search(false, Some(4), None, None, None, None)
+            .unwrap();
+
+        let best = server
+            .best_move_rx
+            .recv_timeout(Duration::from_secs(60))
+            .expect("Expected the search to finish");
+        let pv = server
+            .perf_rx
+            .try_iter()
+            .filter_map(|perf| perf.root_report)
+            .last()
+            .expect("Expected the search to report on the root")
+            .pv;
+
+        assert!(pv.len() >= 2);
+        assert_eq!(best.best_move, pv[0]);
+        assert_eq!(best.ponder_move, Some(pv[1]));
+    }
+
+    #[test]
+    fn test_debug_mode_sends_info_strings() {
+        let info_strings = |debug| {
+            let mut server = EngineServer::startup_with_config(EngineConfig {
+                book_path: None,
+                ..EngineConfig::default()
+            })
+            .unwrap();
+            server.set_debug(debug).unwrap();
+            server
+                .set_state(
+                    parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                        .unwrap(),
+                    Vec::new(),
+                )
+                .unwrap();
+            server
+                .begin_search(false, Some(3), None, None, None, None)
+                .unwrap();
+
+            server
+                .best_move_rx
+                .recv_timeout(Duration::from_secs(60))
+                .expect("Expected the search to finish");
+            server
+                .perf_rx
+                .try_iter()
+                .filter_map(|perf| perf.info_string)
+                .collect::<Vec<_>>()
+        };
+
+        let notes = info_strings(true);
+        assert!(
+            notes.iter().any(|note| note.contains("max_depth")),
+            "Expected the stop reason among {:?}",
+            notes
+        );
+        assert_eq!(info_strings(false), Vec::<String>::new());
+    }
+}
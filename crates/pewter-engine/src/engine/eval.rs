@@ -1,183 +1,96 @@
-use pewter_core::{Color, Piece, State, BoardPos};
+use pewter_core::{bitboard::masks, state::MAX_PHASE_SCORE, BitBoard, Color, File, Piece, State};
 
 pub type Evaluation = i32;
 
 pub mod consts {
-    use pewter_core::{Piece, BoardPos};
     use super::Evaluation;
+    use pewter_core::Piece;
 
     pub const POS_INFINITY: Evaluation = Evaluation::MAX - 1024;
     pub const NEG_INFINITY: Evaluation = -POS_INFINITY;
 
+    // The search negates its window at every ply, which can only be done safely if negating an
+    // evaluation between the infinities gives another one.
+    const _: () = assert!(NEG_INFINITY == -POS_INFINITY);
+
     /// The score if the current player has been mated
     pub const MATE: Evaluation = NEG_INFINITY / 2;
     
     pub const DRAW: Evaluation = 0;
 
-    /// The material value of each piece, in centipawns
-    pub const fn piece_value(piece: Piece) -> Evaluation {
-        match piece {
-            Piece::Pawn => 100,
-            Piece::Rook => 525,
-            Piece::Knight => 350,
-            Piece::Bishop => 350,
-            Piece::King => 0,
-            Piece::Queen => 1000,
-        }
-    }
+    pub use pewter_core::psqt::piece_square_table;
 
-    pub const STARTING_MATERIAL: Evaluation =
-        piece_value(Piece::Pawn) * 8 +
-        piece_value(Piece::Rook) * 2 +
-        piece_value(Piece::Knight) * 2 +
-        piece_value(Piece::Bishop) * 2 +
-        piece_value(Piece::Queen);
-
-    const PAWN_SQUARE_TABLE: [Evaluation; 64] = [
-        0,  0,  0,  0,  0,  0,  0,  0,
-        50, 50, 50, 50, 50, 50, 50, 50,
-        10, 10, 20, 30, 30, 20, 10, 10,
-         5,  5, 10, 25, 25, 10,  5,  5,
-         0,  0,  0, 20, 20,  0,  0,  0,
-         5, -5,-10,  0,  0,-10, -5,  5,
-         5, 10, 10,-20,-20, 10, 10,  5,
-         0,  0,  0,  0,  0,  0,  0,  0
-    ];
-    
-    const KNIGHT_SQUARE_TABLE: [Evaluation; 64] = [
-        -50,-40,-30,-30,-30,-30,-40,-50,
-        -40,-20,  0,  0,  0,  0,-20,-40,
-        -30,  0, 10, 15, 15, 10,  0,-30,
-        -30,  5, 15, 20, 20, 15,  5,-30,
-        -30,  0, 15, 20, 20, 15,  0,-30,
-        -30,  5, 10, 15, 15, 10,  5,-30,
-        -40,-20,  0,  5,  5,  0,-20,-40,
-        -50,-40,-30,-30,-30,-30,-40,-50, 
-    ];
-    
-    const BISHOP_SQUARE_TABLE: [Evaluation; 64] = [
-        -20,-10,-10,-10,-10,-10,-10,-20,
-        -10,  0,  0,  0,  0,  0,  0,-10,
-        -10,  0,  5, 10, 10,  5,  0,-10,
-        -10,  5,  5, 10, 10,  5,  5,-10,
-        -10,  0, 10, 10, 10, 10,  0,-10,
-        -10, 10, 10, 10, 10, 10, 10,-10,
-        -10,  5,  0,  0,  0,  0,  5,-10,
-        -20,-10,-10,-10,-10,-10,-10,-20,
-    ];
-    
-    const ROOK_SQUARE_TABLE: [Evaluation; 64] = [
-        0,  0,  0,  0,  0,  0,  0,  0,
-        5, 10, 10, 10, 10, 10, 10,  5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-        0,  0,  0,  5,  5,  0,  0,  0
-    ];
-    
-    const QUEEN_SQUARE_TABLE: [Evaluation; 64] = [
-        -20,-10,-10, -5, -5,-10,-10,-20,
-        -10,  0,  0,  0,  0,  0,  0,-10,
-        -10,  0,  5,  5,  5,  5,  0,-10,
-         -5,  0,  5,  5,  5,  5,  0, -5,
-          0,  0,  5,  5,  5,  5,  0, -5,
-        -10,  5,  5,  5,  5,  5,  0,-10,
-        -10,  0,  5,  0,  0,  0,  0,-10,
-        -20,-10,-10, -5, -5,-10,-10,-20
-    ];
-    
-    const NULL_SQUARE_TABLE: [Evaluation; 64] = [
-       0, 0, 0, 0, 0, 0, 0, 0,  
-       0, 0, 0, 0, 0, 0, 0, 0,  
-       0, 0, 0, 0, 0, 0, 0, 0,  
-       0, 0, 0, 0, 0, 0, 0, 0,  
-       0, 0, 0, 0, 0, 0, 0, 0,  
-       0, 0, 0, 0, 0, 0, 0, 0,  
-       0, 0, 0, 0, 0, 0, 0, 0,  
-       0, 0, 0, 0, 0, 0, 0, 0,  
-    ];
-    
-    const CENTER_MANHATTEN_DISTANCE: [u8; 64] = [
-        6, 5, 4, 3, 3, 4, 5, 6,
-        5, 4, 3, 2, 2, 3, 4, 5,
-        4, 3, 2, 1, 1, 2, 3, 4,
-        3, 2, 1, 0, 0, 1, 2, 3,
-        3, 2, 1, 0, 0, 1, 2, 3,
-        4, 3, 2, 1, 1, 2, 3, 4,
-        5, 4, 3, 2, 2, 3, 4, 5,
-        6, 5, 4, 3, 3, 4, 5, 6,
-    ];
-    
-    pub const fn piece_square_table(piece: Piece) -> [Evaluation; 64] {
-        match piece {
-            Piece::Pawn => PAWN_SQUARE_TABLE,
-            Piece::Knight => KNIGHT_SQUARE_TABLE,
-            Piece::Bishop => BISHOP_SQUARE_TABLE,
-            Piece::Rook => ROOK_SQUARE_TABLE,
-            Piece::Queen => QUEEN_SQUARE_TABLE,
-            _ => NULL_SQUARE_TABLE
-        }
-    }
-    
-    pub const fn center_manhatten_distance(pos: BoardPos) -> Evaluation {
-        CENTER_MANHATTEN_DISTANCE[pos.to_bitboard_offset() as usize] as Evaluation
-    }
+    pub const STARTING_MATERIAL: Evaluation = Piece::Pawn.value() * 8
+        + Piece::Rook.value() * 2
+        + Piece::Knight.value() * 2
+        + Piece::Bishop.value() * 2
+        + Piece::Queen.value();
+
+    /// Bonus for each rook on a file with no pawns of either color
+    pub const ROOK_OPEN_FILE_BONUS: Evaluation = 25;
+
+    /// Bonus for each rook on a file with only enemy pawns
+    pub const ROOK_HALF_OPEN_FILE_BONUS: Evaluation = 10;
+}
+
+/// Clamp an evaluation to lie between the infinities, so that it can be safely negated and
+/// compared against any search window
+pub fn clamp(eval: Evaluation) -> Evaluation {
+    eval.clamp(consts::NEG_INFINITY, consts::POS_INFINITY)
+}
+
+/// The sum of two evaluations, saturating at the infinities instead of overflowing
+pub fn saturating_add(a: Evaluation, b: Evaluation) -> Evaluation {
+    clamp(a.saturating_add(b))
+}
+
+/// The difference of two evaluations, saturating at the infinities instead of overflowing
+pub fn saturating_sub(a: Evaluation, b: Evaluation) -> Evaluation {
+    clamp(a.saturating_sub(b))
 }
 
 /// The total value of material in centipawns for the given color
 fn material_value(state: &State, color: Color) -> Evaluation {
-    Piece::all()
-        .map(|p| {
-            let value = consts::piece_value(p);
-            let bb = state.board.color_piece_board(color, p);
-            (bb.count() as Evaluation) * value
-        })
-        .iter()
-        .sum()
+    state.accumulator.material(color)
 }
 
-fn piece_square_value_single(color: Color, piece: Piece, pos: BoardPos) -> Evaluation {
-    let table = consts::piece_square_table(piece);
-    let index = match color {
-        Color::White => pos.to_bitboard_offset(),
-        Color::Black => 63 - pos.to_bitboard_offset(),
-    };
-    
-    table[index as usize]
+fn piece_square_value(state: &State, color: Color) -> Evaluation {
+    state.accumulator.piece_square(color)
 }
 
-fn piece_square_value(state: &State, color: Color) -> Evaluation {
-    Piece::all()
+/// How far into the endgame the game is, between 0 and 1, judged by the material other than pawns
+/// left on the board. The same for both players.
+pub fn endgame_phase(state: &State) -> f32 {
+    1f32 - state.phase_score() as f32 / MAX_PHASE_SCORE as f32
+}
+
+/// The union of every file that contains none of the given pawns
+fn files_without(pawns: BitBoard) -> BitBoard {
+    File::all()
         .iter()
-        .map(|&piece| {
-            let bb = state.board.color_piece_board(color, piece);
-            bb.iter_set()
-                .map(|pos| piece_square_value_single(color, piece, pos))
-                .sum::<Evaluation>()
-        })
-        .sum()
-}
-
-fn endgame_weight(state: &State, color: Color, mat: Evaluation) -> f32 {
-    let pv = consts::piece_value(Piece::Pawn);
-    let pawn_count = state.board.color_piece_board(color, Piece::Pawn).count() as i32;
-    let eg_mat = mat - pawn_count * pv;
-
-    let reference_eg_mat = consts::piece_value(Piece::Rook) * 2
-        + consts::piece_value(Piece::Bishop)
-        + consts::piece_value(Piece::Knight);
-    
-    // No end-game material is "maximally end-game"
-    // More than the reference end-game material is "minimally end-game"
-    
-    let x = eg_mat as f32 / reference_eg_mat as f32;
-    if x > 1f32 {
-        1f32
-    } else {
-        1f32 - x
-    }
+        .map(|&file| masks::file(file))
+        .filter(|file| !file.intersect_with(pawns).any())
+        .fold(BitBoard::new_empty(), |acc, file| acc.union_with(file))
+}
+
+/// Every square on a file with no pawns of either color
+pub fn open_files(state: &State) -> BitBoard {
+    files_without(state.board.piece_board(Piece::Pawn))
+}
+
+/// Every square on a file with no pawns of the given color. Includes open files.
+pub fn half_open_files(state: &State, color: Color) -> BitBoard {
+    files_without(state.board.color_piece_board(color, Piece::Pawn))
+}
+
+/// Rooks are much more active on files that aren't blocked by their own pawns
+fn rook_file_bonus(state: &State, color: Color) -> Evaluation {
+    let rooks = state.board.color_piece_board(color, Piece::Rook);
+    let open = open_files(state);
+    let half_open = half_open_files(state, color).intersect_with(open.inverse());
+
+    rooks.intersect_with(open).count() as Evaluation * consts::ROOK_OPEN_FILE_BONUS
+        + rooks.intersect_with(half_open).count() as Evaluation * consts::ROOK_HALF_OPEN_FILE_BONUS
 }
 
 /// In the endgame, it is beneficial to push the opponent king to the edges of the board.
@@ -185,14 +98,14 @@ fn endgame_weight(state: &State, color: Color, mat: Evaluation) -> f32 {
 /// This method returns more positive evaluation the closer the opponents king is to the sides, but
 /// only if in the endgame.
 fn push_opp_king_to_sides(state: &State, color: Color, weight: f32, our_mat: Evaluation, opp_mat: Evaluation) -> Evaluation {
-    if our_mat < (opp_mat + consts::piece_value(Piece::Pawn) * 2) {
+    if our_mat < (opp_mat + Piece::Pawn.value() * 2) {
         return 0;
     }
     
     let opp_king_pos = state.board.king_pos(!color)
         .expect("There is no opponent king");
 
-    let score = consts::center_manhatten_distance(opp_king_pos) * 10;
+    let score = opp_king_pos.center_distance() as Evaluation * 10;
 
     (score as f32 * weight) as Evaluation
 }
@@ -206,35 +119,208 @@ fn nonlinear_material_diff(our_mat: Evaluation, opp_mat: Evaluation) -> Evaluati
     ((diff as f32 / total as f32) * 100.0) as Evaluation
 }
 
+/// The terms that make up one player's side of the evaluation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SideBreakdown {
+    /// The total value of the player's material
+    pub material: Evaluation,
+
+    /// Bonus for being ahead in material, which grows as material comes off the board. Only ever
+    /// given to the player to move.
+    pub material_diff: Evaluation,
+
+    /// Bonus for having a pair of bishops
+    pub bishop_pair: Evaluation,
+
+    /// The summed piece-square table values of the player's pieces
+    pub piece_square: Evaluation,
+
+    /// Bonus for rooks on open and half-open files
+    pub rook_files: Evaluation,
+
+    /// Bonus in the endgame for pushing the opponent's king towards the edge of the board
+    pub king_push: Evaluation,
+}
+
+impl SideBreakdown {
+    pub fn total(&self) -> Evaluation {
+        self.material
+            + self.material_diff
+            + self.bishop_pair
+            + self.piece_square
+            + self.rook_files
+            + self.king_push
+    }
+}
+
+/// Every term of the evaluation for both players, see `evaluate_explained`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvalBreakdown {
+    /// The terms for the player to move
+    pub ours: SideBreakdown,
+
+    /// The terms for the opponent of the player to move
+    pub theirs: SideBreakdown,
+}
+
+impl EvalBreakdown {
+    /// The final evaluation, from the perspective of the player to move
+    pub fn total(&self) -> Evaluation {
+        self.ours.total() - self.theirs.total()
+    }
+}
+
 /// Total evaluation of the given state, from the perspective of the current player.
 pub fn evaluate(state: &State) -> Evaluation {
-    let mut our_score = 0;
-    let mut opp_score = 0;
+    evaluate_explained(state).total()
+}
+
+/// The evaluation of the given state split into its individual terms for each player, for
+/// debugging and tuning. The terms sum to `evaluate(state)`.
+pub fn evaluate_explained(state: &State) -> EvalBreakdown {
+    let mut ours = SideBreakdown::default();
+    let mut theirs = SideBreakdown::default();
 
     let our_mat = material_value(state, state.to_play);
     let opp_mat = material_value(state, !state.to_play);
     
-    our_score += our_mat;
-    opp_score += opp_mat;
+    ours.material = our_mat;
+    theirs.material = opp_mat;
     
-    our_score += nonlinear_material_diff(our_mat, opp_mat);
+    ours.material_diff = nonlinear_material_diff(our_mat, opp_mat);
     
     // Having a pair of bishops is more than twice as good as having a single bishop
     if state.board.color_piece_board(state.to_play, Piece::Bishop).count() > 1 {
-        our_score += 100;
+        ours.bishop_pair = 100;
     }
     if state.board.color_piece_board(!state.to_play, Piece::Bishop).count() > 1 {
-        opp_score += 100;
+        theirs.bishop_pair = 100;
     }
 
-    our_score += piece_square_value(state, state.to_play);
-    opp_score += piece_square_value(state, !state.to_play);
+    ours.piece_square = piece_square_value(state, state.to_play);
+    theirs.piece_square = piece_square_value(state, !state.to_play);
+
+    ours.rook_files = rook_file_bonus(state, state.to_play);
+    theirs.rook_files = rook_file_bonus(state, !state.to_play);
     
-    let our_eg_weight = endgame_weight(state, state.to_play, opp_mat);
-    let opp_eg_weight = endgame_weight(state, !state.to_play, opp_mat);
+    let phase = endgame_phase(state);
     
-    our_score += push_opp_king_to_sides(state, state.to_play, our_eg_weight, our_mat, opp_mat);
-    opp_score += push_opp_king_to_sides(state, !state.to_play, opp_eg_weight, opp_mat, our_mat);
+    ours.king_push = push_opp_king_to_sides(state, state.to_play, phase, our_mat, opp_mat);
+    theirs.king_push = push_opp_king_to_sides(state, !state.to_play, phase, opp_mat, our_mat);
+
+    EvalBreakdown { ours, theirs }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::fen::parse_fen;
 
-    our_score - opp_score
+    #[test]
+    fn test_open_files() {
+        let state = parse_fen("4k3/pppp2pp/8/8/8/8/PPPP1PPP/4RK2 w - - 0 1").unwrap();
+
+        let e_and_f = masks::file(File::E).union_with(masks::file(File::F));
+        assert_eq!(open_files(&state), masks::file(File::E));
+        assert_eq!(half_open_files(&state, Color::White), masks::file(File::E));
+        assert_eq!(half_open_files(&state, Color::Black), e_and_f);
+    }
+
+    #[test]
+    fn test_evaluate_explained() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/pppp1ppp/8/8/8/8/PPPP1PPP/4RK2 b - - 0 1",
+            "8/1k6/8/8/8/8/8/3QK3 w - - 0 1",
+        ];
+
+        for fen in fens {
+            let state = parse_fen(fen).unwrap();
+            let breakdown = evaluate_explained(&state);
+            assert_eq!(breakdown.total(), evaluate(&state), "{}", fen);
+            assert_eq!(breakdown.theirs.material_diff, 0, "{}", fen);
+        }
+
+        // The lone king is pushed towards the edge, but the other side has nothing to push with
+        let state = parse_fen("8/1k6/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let breakdown = evaluate_explained(&state);
+        assert!(breakdown.ours.king_push > 0);
+        assert_eq!(breakdown.theirs.king_push, 0);
+        assert_eq!(breakdown.theirs.material, 0);
+    }
+
+    #[test]
+    fn test_endgame_phase() {
+        let phase = |fen| endgame_phase(&parse_fen(fen).unwrap());
+
+        assert_eq!(
+            phase("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            0.0
+        );
+        assert_eq!(phase("8/1k6/8/8/8/8/8/4K3 w - - 0 1"), 1.0);
+
+        // Pawns don't count towards the phase
+        assert_eq!(phase("8/1k6/8/8/8/8/4P3/4K3 w - - 0 1"), 1.0);
+
+        // The phase is shared, so doesn't depend on who has the material or who is to move
+        let queen = phase("8/1k6/8/8/8/8/8/3QK3 w - - 0 1");
+        assert!(queen > 0.0 && queen < 1.0);
+        assert_eq!(phase("3qk3/8/8/8/8/8/1K6/8 w - - 0 1"), queen);
+        assert_eq!(phase("8/1k6/8/8/8/8/8/3QK3 b - - 0 1"), queen);
+    }
+
+    #[test]
+    fn test_saturating_arithmetic() {
+        use consts::{NEG_INFINITY, POS_INFINITY};
+
+        assert_eq!(saturating_add(POS_INFINITY, 1), POS_INFINITY);
+        assert_eq!(saturating_add(POS_INFINITY, Evaluation::MAX), POS_INFINITY);
+        assert_eq!(saturating_add(NEG_INFINITY, -1), NEG_INFINITY);
+        assert_eq!(saturating_sub(NEG_INFINITY, Evaluation::MAX), NEG_INFINITY);
+        assert_eq!(saturating_sub(POS_INFINITY, Evaluation::MIN), POS_INFINITY);
+        assert_eq!(saturating_add(100, -30), 70);
+
+        for eval in [
+            Evaluation::MIN,
+            NEG_INFINITY,
+            0,
+            POS_INFINITY,
+            Evaluation::MAX,
+        ] {
+            let clamped = clamp(eval);
+            assert!((NEG_INFINITY..=POS_INFINITY).contains(&clamped));
+            assert_eq!(-(-clamped), clamped);
+        }
+    }
+
+    #[test]
+    fn test_rook_file_bonus() {
+        let cases = [
+            (
+                "4k3/pppp1ppp/8/8/8/8/PPPP1PPP/4RK2 w - - 0 1",
+                consts::ROOK_OPEN_FILE_BONUS,
+            ),
+            (
+                "4k3/pppppppp/8/8/8/8/PPPP1PPP/4RK2 w - - 0 1",
+                consts::ROOK_HALF_OPEN_FILE_BONUS,
+            ),
+            ("4k3/pppppppp/8/8/8/8/PPPPPPPP/4RK2 w - - 0 1", 0),
+        ];
+
+        for (fen, expected) in cases {
+            let state = parse_fen(fen).unwrap();
+            assert_eq!(rook_file_bonus(&state, Color::White), expected, "{}", fen);
+            assert_eq!(rook_file_bonus(&state, Color::Black), 0, "{}", fen);
+        }
+
+        // Moving the rook onto a closed file along the back rank loses exactly the bonus
+        let open = parse_fen("4k3/pppp1ppp/8/8/8/8/PPPP1PPP/4R1K1 w - - 0 1").unwrap();
+        let closed = parse_fen("4k3/pppp1ppp/8/8/8/8/PPPP1PPP/3R2K1 w - - 0 1").unwrap();
+        assert_eq!(
+            evaluate(&open) - evaluate(&closed),
+            consts::ROOK_OPEN_FILE_BONUS
+        );
+    }
 }
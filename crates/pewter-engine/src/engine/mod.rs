@@ -1,22 +1,33 @@
-use std::{path::Path, time::Duration};
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use pewter_core::{Move, State};
+use pewter_core::movegen::legal_moves;
+use pewter_core::{zobrist::ZobristHash, Move, State};
 
 use anyhow::Result;
-use crossbeam_channel::SendError;
-use rand::{seq::SliceRandom, thread_rng};
+use crossbeam_channel::{unbounded, Receiver, SendError};
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
 use thiserror::Error;
 
+pub mod bench;
+pub mod endgame;
 pub mod engine_server;
 pub mod eval;
 pub mod opening_db;
+pub mod score;
+pub mod see;
+pub mod skill;
 pub mod transposition;
 pub mod search;
 pub mod ordering;
 
 pub use engine_server::EngineServer;
 use eval::Evaluation;
-use search::{Searcher, SearchControls};
+use search::{SearchControls, SearchInfo, Searcher, MAX_SEARCH_DEPTH};
+use skill::Skill;
 
 use opening_db::OpeningDb;
 
@@ -38,6 +49,74 @@ pub struct Timings {
     pub move_time: Option<Duration>,
 }
 
+/// The conditions under which a search should conclude
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    /// Ignore all other limits, and search until explicitly stopped
+    pub infinite: bool,
+
+    /// Stop after searching this deep
+    pub max_depth: Option<u8>,
+
+    /// Stop after searching this many nodes
+    pub max_nodes: Option<u64>,
+
+    /// Time control information used to decide how long to search for
+    pub timings: Option<Timings>,
+}
+
+/// Everything about the engine's behaviour that can be configured by its host
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EngineConfig {
+    /// The depth to search to when a search isn't given an explicit depth limit, nor any timings
+    /// for the time manager to limit it by instead
+    pub default_depth: u8,
+
+    /// The size of the transposition table in megabytes. Changing it clears the table.
+    pub hash_size_mb: usize,
+
+    /// How much the engine should avoid draws, in centipawns. See `Searcher::set_contempt`.
+    pub contempt: Evaluation,
+
+    /// Where to load the opening book from
+    pub book_path: Option<PathBuf>,
+
+    /// Whether to play moves from the opening book when there are any
+    pub own_book: bool,
+
+    /// When true, the engine is being used to analyse positions rather than to play a game, so
+    /// search results are preferred over book moves and accuracy over speed.
+    pub analyse_mode: bool,
+
+    /// How strongly the engine should play, from 0 up to `skill::MAX_SKILL_LEVEL` for full
+    /// strength.
+    pub skill_level: u8,
+
+    /// The seed for every random choice the engine makes, eg between book moves or when playing
+    /// below full strength, or None to seed from the system's entropy.
+    pub seed: Option<u64>,
+
+    /// Time to hold back from each move's time allowance, to allow for delays between the engine
+    /// and the clock.
+    pub move_overhead: Duration,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            default_depth: 10,
+            hash_size_mb: transposition::DEFAULT_HASH_SIZE_MB,
+            contempt: 0,
+            book_path: Some(PathBuf::from("./opening_db.dat")),
+            own_book: true,
+            analyse_mode: false,
+            skill_level: skill::MAX_SKILL_LEVEL,
+            seed: None,
+            move_overhead: Duration::from_millis(10),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 /// Assorted information about the recent mechanical performance of the engine
 pub struct PerfInfo {
@@ -55,6 +134,56 @@ pub struct PerfInfo {
 
     /// This many positions found in the shredder endgame databases
     pub shredder_hits: u64,
+
+    /// What the search concluded about the position being searched, only present on the last
+    /// message of each search.
+    pub root_report: Option<RootReport>,
+
+    /// The root move that was about to be searched, along with its number in the order the root
+    /// moves are searched in, counting from 1.
+    pub current_move: Option<(Move, u16)>,
+
+    /// A human readable note on what the engine is doing, eg why it stopped searching. These are
+    /// only sent in debug mode, see `SearchControls::debug`.
+    pub info_string: Option<String>,
+}
+
+/// The outcome that a search expects from the position it searched, with best play from both
+/// sides
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RootVerdict {
+    /// The search didn't find anything conclusive
+    Undecided,
+
+    /// The player to move can force mate
+    Won,
+
+    /// Nothing either player does can change the result from a draw
+    Drawn,
+
+    /// The player to move will be mated whatever they do
+    Lost,
+}
+
+/// A draw that the player to move could claim instead of making a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawClaim {
+    FiftyMove,
+    ThreefoldRepetition,
+}
+
+/// What a search concluded about the position at its root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootReport {
+    pub verdict: RootVerdict,
+
+    /// The score of the best move, from the perspective of the player to move
+    pub score: Evaluation,
+
+    /// The best line found, starting with the best move
+    pub pv: Vec<Move>,
+
+    pub draw_claim: Option<DrawClaim>,
 }
 
 #[derive(Clone, Error, Debug)]
@@ -81,17 +210,150 @@ impl<T> From<SendError<T>> for EngineError {
     }
 }
 
+/// An in-progress analysis running on a background thread, created by `Engine::analyze`.
+///
+/// Yields one `SearchInfo` per completed search iteration. Dropping this handle stops the search.
+pub struct Analysis {
+    info_rx: Receiver<SearchInfo>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Iterator for Analysis {
+    type Item = SearchInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.info_rx.recv().ok()
+    }
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
 #[derive(Clone)]
 pub struct Engine {
     board_state: Option<State>,
     opening_db: Option<OpeningDb>,
+    config: EngineConfig,
+
+    /// When true, the engine always makes the same choices given the same inputs
+    deterministic: bool,
+
+    /// The source of every random choice the engine makes, see `EngineConfig::seed`
+    rng: SmallRng,
+
+    /// The reply expected to the move found by the last search, see `Engine::ponder_move`
+    ponder_move: Option<Move>,
+
+    /// Kept between searches so that what it learns about the game carries over between moves
+    searcher: Searcher,
+
+    /// When set, searches only consider these moves from the root, see `set_search_moves`
+    search_moves: Option<Vec<Move>>,
+
+    /// The hashes of the positions played before the board state, see `set_game_history`
+    game_history: Vec<ZobristHash>,
+}
+
+fn engine_rng(config: &EngineConfig) -> SmallRng {
+    match config.seed {
+        Some(seed) => SmallRng::seed_from_u64(seed),
+        None => SmallRng::from_entropy(),
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
+        let config = EngineConfig::default();
         Self {
             board_state: None,
             opening_db: None,
+            deterministic: false,
+            rng: engine_rng(&config),
+            ponder_move: None,
+            searcher: Searcher::new(SearchControls::default()),
+            search_moves: None,
+            game_history: Vec::new(),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Replace the engine's configuration, (re)loading the opening book if one is configured.
+    ///
+    /// A configured book that doesn't exist or can't be loaded, eg because it's corrupt or from an
+    /// incompatible version, is skipped with a warning, leaving the engine without a book.
+    pub fn configure(&mut self, config: EngineConfig) -> Result<()> {
+        self.opening_db = None;
+
+        match &config.book_path {
+            Some(path) if config.own_book && path.exists() => {
+                tracing::debug!("Loading opening db from {}", path.to_string_lossy());
+                if let Err(e) = self.load_opening_db(path) {
+                    tracing::warn!(
+                        "Couldn't load opening db from {}, proceeding without: {}",
+                        path.to_string_lossy(),
+                        e
+                    );
+                }
+            }
+            Some(path) if config.own_book => {
+                tracing::debug!(
+                    "Couldn't find opening db at {}, proceeding without",
+                    path.to_string_lossy()
+                );
+            }
+            _ => (),
+        }
+
+        self.searcher.set_hash_size_mb(config.hash_size_mb);
+        self.rng = engine_rng(&config);
+        self.searcher.set_seed(self.rng.gen());
+        self.config = config;
+        Ok(())
+    }
+
+    /// Enable or disable deterministic mode.
+    ///
+    /// In deterministic mode the most popular book move is always played rather than a random one,
+    /// and searches ignore the clock so that they are bounded only by depth and explicit stop
+    /// requests. Searching the same state to the same depth then always gives the same result.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Restrict the following searches to only consider the given moves from the root, as for the
+    /// UCI "searchmoves" option, or lift the restriction with None.
+    ///
+    /// Moves that aren't legal in the position being searched are ignored with a warning, and if
+    /// none of them are legal every move is searched.
+    pub fn set_search_moves(&mut self, search_moves: Option<Vec<Move>>) {
+        self.search_moves = search_moves;
+    }
+
+    /// The legal moves of the given state that searches are restricted to, if there's a
+    /// restriction, warning about each of the requested moves that isn't legal.
+    fn root_moves(&self, state: &State) -> Option<Vec<Move>> {
+        let search_moves = self.search_moves.as_ref()?;
+        let legal = legal_moves(state);
+
+        let (allowed, illegal): (Vec<Move>, Vec<Move>) = search_moves
+            .iter()
+            .partition(|&&m| legal.iter().any(|l| l == m));
+        for m in illegal {
+            tracing::warn!("Ignoring searchmoves entry {} as it isn't legal", m);
+        }
+
+        if allowed.is_empty() {
+            tracing::warn!("None of the searchmoves entries are legal, searching every move");
+            None
+        } else {
+            Some(allowed)
         }
     }
 
@@ -101,37 +363,741 @@ impl Engine {
         Ok(())
     }
 
+    /// Set the position to search from, forgetting the game history of the previous position
     pub fn set_board_state(&mut self, new_state: State) {
         self.board_state = Some(new_state);
+        self.set_game_history(Vec::new());
     }
 
+    /// Set the hashes of the positions played before the board state, oldest first, so that
+    /// searches can see repetitions of them and report draws that could be claimed.
+    pub fn set_game_history(&mut self, game_history: Vec<ZobristHash>) {
+        self.searcher.set_game_history(game_history.clone());
+        self.game_history = game_history;
+    }
+
+    /// Find the best move in the current state, playing from the opening book when possible.
+    ///
+    /// With `use_book` false the book is skipped for just this search without being unloaded, eg
+    /// to see what the engine itself thinks of a book position.
     pub fn search_best_move(
         &mut self,
         infinite: bool,
         max_depth: Option<u8>,
-        _max_nodes: Option<u64>,
+        max_nodes: Option<u64>,
         timings: Option<Timings>,
+        use_book: bool,
         controls: SearchControls,
     ) -> Result<Move, EngineError> {
         let state = &self.board_state.ok_or(EngineError::NoState)?;
+        self.ponder_move = None;
+        let root_moves = self.root_moves(state);
 
         // Check for opening DB hits first
-        if let Some(db) = &self.opening_db {
-            let book_move = match db.query(state) {
-                [] => None,
-                [r] => Some(r.m),
-                [multiple @ ..] => Some(multiple.choose(&mut thread_rng()).unwrap().m),
+        let use_book = use_book && self.config.own_book && !self.config.analyse_mode;
+        if let Some(db) = self.opening_db.as_ref().filter(|_| use_book) {
+            let results = db
+                .query(state)
+                .iter()
+                .filter(|r| match &root_moves {
+                    Some(moves) => moves.contains(&r.m),
+                    None => true,
+                })
+                .collect::<Vec<_>>();
+            let book_move = if self.deterministic {
+                // Ties are broken by the ordering of the moves themselves
+                results
+                    .iter()
+                    .max_by_key(|r| (r.total_count(), Reverse(r.m)))
+                    .map(|r| r.m)
+            } else {
+                results.choose(&mut self.rng).map(|r| r.m)
             };
 
             if let Some(book_move) = book_move {
                 tracing::info!("Responding with book move: {}", book_move);
+                let note = format!(
+                    "Playing book move {} of {} candidates",
+                    book_move,
+                    results.len()
+                );
+                self.with_controls(controls, |searcher| searcher.debug_info(note))?;
                 return Ok(book_move);
             }
         }
-        
+
+        let max_depth = self.depth_limit(max_depth, timings.is_some());
         let timings = timings.unwrap_or(Timings::default());
-        
-        let mut searcher = Searcher::new(controls);
-        searcher.search(state, max_depth.unwrap_or(10), timings, infinite)
+
+        self.searcher.set_time_managed(!self.deterministic);
+        self.searcher
+            .set_futility_pruning(!self.config.analyse_mode);
+        self.searcher.set_move_overhead(self.config.move_overhead);
+        self.searcher.set_contempt(self.config.contempt);
+        self.searcher.set_root_moves(root_moves);
+        self.searcher.set_max_nodes(max_nodes.filter(|_| !infinite));
+
+        let skill = Skill::new(self.config.skill_level);
+        if !skill.is_full_strength() {
+            let depth = skill.search_depth().min(max_depth);
+            let ranked = self.with_controls(controls, |searcher| {
+                searcher.rank_root_moves(state, depth, timings, infinite)
+            })?;
+            return skill
+                .choose_move(&ranked, &mut self.rng)
+                .ok_or(EngineError::NoMoves);
+        }
+
+        let best_move = self.with_controls(controls, |searcher| {
+            searcher.search(state, max_depth, timings, infinite)
+        })?;
+        self.ponder_move = self.searcher.ponder_move();
+        Ok(best_move)
     }
-}
\ No newline at end of file
+
+    /// Run a search with the given controls, which are released again afterwards so that the
+    /// caller's channels aren't held open between searches.
+    fn with_controls<T>(
+        &mut self,
+        controls: SearchControls,
+        search: impl FnOnce(&mut Searcher) -> T,
+    ) -> T {
+        self.searcher.set_controls(controls);
+        let result = search(&mut self.searcher);
+        self.searcher.set_controls(SearchControls::default());
+        result
+    }
+
+    /// Forget everything learned while searching earlier positions, as for the UCI "ucinewgame"
+    /// command.
+    ///
+    /// Otherwise the transposition table is kept between searches, as positions from the search
+    /// of one move are likely to come up again in the search of the next.
+    pub fn new_game(&mut self) {
+        self.searcher.new_game();
+        self.ponder_move = None;
+        self.game_history.clear();
+    }
+
+    /// The depth a search should be limited to given the depth it was asked for, if any.
+    ///
+    /// Without an explicit depth, a search with timings is left for the time manager to stop, so
+    /// only falls back to the configured default depth when there's no time management.
+    fn depth_limit(&self, max_depth: Option<u8>, has_timings: bool) -> u8 {
+        match max_depth {
+            Some(max_depth) => max_depth,
+            None if has_timings && !self.deterministic => MAX_SEARCH_DEPTH,
+            None => self.config.default_depth,
+        }
+    }
+
+    /// The opponent's expected reply to the move returned by the last call to `search_best_move`
+    /// or `search_mate`, for the GUI to ponder on.
+    ///
+    /// This is None when the move came from the book or was chosen below full strength, as
+    /// there's no line of play to take it from.
+    pub fn ponder_move(&self) -> Option<Move> {
+        self.ponder_move
+    }
+
+    /// Search the current state for a forced mate in at most the given number of moves, as for the
+    /// UCI "go mate" command. See `Searcher::search_mate`.
+    ///
+    /// The opening DB and skill level are not consulted, as the point is to find the mate.
+    pub fn search_mate(
+        &mut self,
+        moves: u8,
+        controls: SearchControls,
+    ) -> Result<Move, EngineError> {
+        let state = &self.board_state.ok_or(EngineError::NoState)?;
+        self.searcher.set_root_moves(self.root_moves(state));
+        self.searcher.set_max_nodes(None);
+        let result = self.with_controls(controls, |searcher| searcher.search_mate(state, moves));
+        self.ponder_move = self.searcher.ponder_move();
+        result
+    }
+
+    /// Begin searching the given state in the background, streaming the results of each
+    /// iteration of the search until one of the limits is reached.
+    ///
+    /// Unlike `search_best_move`, the opening DB is not consulted. The search is seeded from the
+    /// engine's own random choices, in the same way as the main searcher.
+    pub fn analyze(&mut self, state: &State, limits: SearchLimits) -> Result<Analysis> {
+        let (info_tx, info_rx) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let controls = SearchControls {
+            stop: stop.clone(),
+            perf_info: None,
+            ..SearchControls::default()
+        };
+
+        let state = *state;
+        let deterministic = self.deterministic;
+        let analyse_mode = self.config.analyse_mode;
+        let move_overhead = self.config.move_overhead;
+        let contempt = self.config.contempt;
+        let hash_size_mb = self.config.hash_size_mb;
+        let seed = self.rng.gen();
+        let root_moves = self.root_moves(&state);
+        let game_history = self.game_history.clone();
+        let max_depth = self.depth_limit(limits.max_depth, limits.timings.is_some());
+        std::thread::Builder::new()
+            .name("Engine analysis".to_string())
+            .spawn(move || {
+                let mut searcher = Searcher::new(controls);
+                searcher.set_hash_size_mb(hash_size_mb);
+                searcher.set_time_managed(!deterministic);
+                searcher.set_futility_pruning(!analyse_mode);
+                searcher.set_move_overhead(move_overhead);
+                searcher.set_contempt(contempt);
+                searcher.set_root_moves(root_moves);
+                searcher.set_game_history(game_history);
+                searcher.set_max_nodes(limits.max_nodes.filter(|_| !limits.infinite));
+                searcher.set_seed(seed);
+                let res = searcher.search_with_callback(
+                    &state,
+                    max_depth,
+                    limits.timings.unwrap_or_default(),
+                    limits.infinite,
+                    // The receiver hanging up just means nobody is listening anymore
+                    |info| drop(info_tx.send(info)),
+                );
+
+                if let Err(e) = res {
+                    tracing::debug!("Analysis search finished early: {}", e);
+                }
+            })?;
+
+        Ok(Analysis { info_rx, stop })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::{fen::parse_fen, pgn::parse_multi_pgn};
+
+    #[test]
+    fn test_analyze_streams_each_depth() {
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let limits = SearchLimits {
+            max_depth: Some(4),
+            ..SearchLimits::default()
+        };
+
+        let mut engine = Engine::new();
+        let infos = engine
+            .analyze(&state, limits)
+            .expect("Expected to be able to start an analysis")
+            .collect::<Vec<_>>();
+
+        assert!(!infos.is_empty());
+        assert!(infos.windows(2).all(|w| w[0].depth < w[1].depth));
+
+        engine.set_board_state(state);
+        let best_move = engine
+            .search_best_move(
+                false,
+                limits.max_depth,
+                None,
+                None,
+                true,
+                SearchControls {
+                    stop: Arc::new(AtomicBool::new(false)),
+                    perf_info: None,
+                    ..SearchControls::default()
+                },
+            )
+            .expect("Expected the search to find a move");
+
+        assert_eq!(infos.last().unwrap().pv[0], best_move);
+    }
+
+    #[test]
+    fn test_deterministic_search_is_reproducible() {
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let limits = SearchLimits {
+            max_depth: Some(4),
+            ..SearchLimits::default()
+        };
+
+        let mut engine = Engine::new();
+        engine.set_deterministic(true);
+
+        let mut run = || {
+            engine
+                .analyze(&state, limits)
+                .expect("Expected to be able to start an analysis")
+                .map(|info| (info.depth, info.pv, info.nodes))
+                .collect::<Vec<_>>()
+        };
+
+        let first = run();
+        let second = run();
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_deterministic_book_move() {
+        let pgn = "[Event \"a\"]\n\n1.e4 e5 1-0\n\n[Event \"b\"]\n\n1.d4 d5 0-1\n\n\
+                   [Event \"c\"]\n\n1.d4 Nf6 1/2-1/2\n";
+        let mut db = OpeningDb::new_empty();
+        for game in parse_multi_pgn(pgn).unwrap() {
+            db.add_game(&game.expect("Expected test PGN to be valid"), None);
+        }
+
+        let mut engine = Engine::new();
+        engine.opening_db = Some(db);
+        engine.set_deterministic(true);
+        engine.set_board_state(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        );
+
+        for _ in 0..10 {
+            let m = engine
+                .search_best_move(
+                    false,
+                    Some(2),
+                    None,
+                    None,
+                    true,
+                    SearchControls {
+                        stop: Arc::new(AtomicBool::new(false)),
+                        perf_info: None,
+                        ..SearchControls::default()
+                    },
+                )
+                .unwrap();
+            assert_eq!(m.format_long_algebraic(), "d2d4");
+        }
+    }
+
+    #[test]
+    fn test_seeded_engines_make_the_same_choices() {
+        let pgn = "[Event \"a\"]\n\n1.e4 e5 1-0\n\n[Event \"b\"]\n\n1.d4 d5 0-1\n\n\
+                   [Event \"c\"]\n\n1.c4 Nf6 1/2-1/2\n";
+        let book_moves = |seed| {
+            let mut db = OpeningDb::new_empty();
+            for game in parse_multi_pgn(pgn).unwrap() {
+                db.add_game(&game.expect("Expected test PGN to be valid"), None);
+            }
+
+            let mut engine = Engine::new();
+            engine
+                .configure(EngineConfig {
+                    book_path: None,
+                    seed: Some(seed),
+                    ..EngineConfig::default()
+                })
+                .unwrap();
+            engine.opening_db = Some(db);
+            engine.set_board_state(
+                parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+            );
+
+            (0..20)
+                .map(|_| {
+                    engine
+                        .search_best_move(
+                            false,
+                            Some(2),
+                            None,
+                            None,
+                            true,
+                            SearchControls::default(),
+                        )
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let moves = book_moves(7);
+        assert_eq!(moves, book_moves(7));
+
+        // The choices really are random, rather than always the same book move
+        assert!(moves.iter().any(|&m| m != moves[0]));
+    }
+
+    #[test]
+    fn test_configure_book_path() {
+        let pgn = "[Event \"a\"]\n\n1.c4 e5 1-0\n";
+        let mut db = OpeningDb::new_empty();
+        for game in parse_multi_pgn(pgn).unwrap() {
+            db.add_game(&game.expect("Expected test PGN to be valid"), None);
+        }
+
+        let book_path =
+            std::env::temp_dir().join(format!("pewter_test_book_{}.dat", std::process::id()));
+        std::fs::write(&book_path, db.serialize().unwrap()).unwrap();
+
+        let mut engine = Engine::new();
+        engine.set_board_state(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        );
+        let best_move = |engine: &mut Engine| {
+            engine
+                .search_best_move(
+                    false,
+                    Some(2),
+                    None,
+                    None,
+                    true,
+                    SearchControls {
+                        stop: Arc::new(AtomicBool::new(false)),
+                        perf_info: None,
+                        ..SearchControls::default()
+                    },
+                )
+                .unwrap()
+                .format_long_algebraic()
+        };
+
+        let config = EngineConfig {
+            book_path: Some(book_path.clone()),
+            ..EngineConfig::default()
+        };
+        engine.configure(config.clone()).unwrap();
+        std::fs::remove_file(&book_path).unwrap();
+        assert!(engine.opening_db.is_some());
+        assert_eq!(best_move(&mut engine), "c2c4");
+
+        // Turning off the book keeps it from being consulted
+        engine
+            .configure(EngineConfig {
+                own_book: false,
+                ..config.clone()
+            })
+            .unwrap();
+        assert!(engine.opening_db.is_none());
+
+        // Now that the file is gone, the book is skipped rather than failing
+        engine.configure(config).unwrap();
+        assert!(engine.opening_db.is_none());
+        assert_eq!(engine.config().book_path, Some(book_path));
+    }
+
+    #[test]
+    fn test_configure_corrupt_book() {
+        let pgn = "[Event \"a\"]\n\n1.c4 e5 1-0\n";
+        let mut db = OpeningDb::new_empty();
+        for game in parse_multi_pgn(pgn).unwrap() {
+            db.add_game(&game.expect("Expected test PGN to be valid"), None);
+        }
+        let data = db.serialize().unwrap();
+
+        let book_path =
+            std::env::temp_dir().join(format!("pewter_corrupt_book_{}.dat", std::process::id()));
+        let config = EngineConfig {
+            book_path: Some(book_path.clone()),
+            ..EngineConfig::default()
+        };
+
+        let mut engine = Engine::new();
+        engine.set_board_state(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        );
+
+        for contents in [&data[..data.len() / 2], b"not an opening db"] {
+            std::fs::write(&book_path, contents).unwrap();
+            engine.configure(config.clone()).unwrap();
+            assert!(engine.opening_db.is_none());
+
+            // Without the book, the engine still searches for its moves
+            let best_move = engine.search_best_move(
+                false,
+                Some(2),
+                None,
+                None,
+                true,
+                SearchControls::default(),
+            );
+            assert!(best_move.is_ok());
+        }
+
+        std::fs::remove_file(&book_path).unwrap();
+    }
+
+    #[test]
+    fn test_own_book_disabled_searches_book_positions() {
+        let pgn = "[Event \"a\"]\n\n1.h4 e5 1-0\n";
+        let mut db = OpeningDb::new_empty();
+        for game in parse_multi_pgn(pgn).unwrap() {
+            db.add_game(&game.expect("Expected test PGN to be valid"), None);
+        }
+
+        let mut engine = Engine::new();
+        engine.set_board_state(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        );
+        let best_move = |engine: &mut Engine| {
+            engine
+                .search_best_move(
+                    false,
+                    Some(3),
+                    None,
+                    None,
+                    true,
+                    SearchControls {
+                        stop: Arc::new(AtomicBool::new(false)),
+                        perf_info: None,
+                        ..SearchControls::default()
+                    },
+                )
+                .unwrap()
+                .format_long_algebraic()
+        };
+
+        engine.opening_db = Some(db);
+        assert_eq!(best_move(&mut engine), "h2h4");
+
+        // The book stays loaded, but isn't consulted
+        engine.config.own_book = false;
+        assert_ne!(best_move(&mut engine), "h2h4");
+
+        engine.config.own_book = true;
+        engine.config.analyse_mode = true;
+        assert_ne!(best_move(&mut engine), "h2h4");
+    }
+
+    #[test]
+    fn test_search_without_book() {
+        let pgn = "[Event \"a\"]\n\n1.a3 e5 1-0\n";
+        let mut db = OpeningDb::new_empty();
+        for game in parse_multi_pgn(pgn).unwrap() {
+            db.add_game(&game.expect("Expected test PGN to be valid"), None);
+        }
+
+        let mut engine = Engine::new();
+        engine.opening_db = Some(db);
+        engine.set_deterministic(true);
+        engine.set_board_state(
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap(),
+        );
+        let best_move = |engine: &mut Engine, use_book| {
+            engine
+                .search_best_move(
+                    false,
+                    Some(3),
+                    None,
+                    None,
+                    use_book,
+                    SearchControls {
+                        stop: Arc::new(AtomicBool::new(false)),
+                        perf_info: None,
+                        ..SearchControls::default()
+                    },
+                )
+                .unwrap()
+                .format_long_algebraic()
+        };
+
+        assert_eq!(best_move(&mut engine, true), "a2a3");
+        assert_ne!(best_move(&mut engine, false), "a2a3");
+
+        // Skipping the book for one search leaves it in place for the next
+        assert!(engine.opening_db.is_some());
+        assert_eq!(best_move(&mut engine, true), "a2a3");
+    }
+
+    #[test]
+    fn test_transposition_table_persists_between_moves() {
+        let mut engine = Engine::new();
+        engine.set_deterministic(true);
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let search = |engine: &mut Engine, state| {
+            engine.set_board_state(state);
+            engine
+                .search_best_move(false, Some(4), None, None, false, SearchControls::default())
+                .unwrap()
+        };
+
+        assert_eq!(engine.searcher.transposition_load(), 0.0);
+        let m = search(&mut engine, state);
+        let reply = engine
+            .ponder_move()
+            .expect("Expected a reply to the best move");
+
+        // The search of the next move starts with everything learned from the first
+        let next = state.apply_move(m).apply_move(reply);
+        let load_before = engine.searcher.transposition_load();
+        assert!(load_before > 0.0);
+        search(&mut engine, next);
+        assert!(engine.searcher.transposition_load() > load_before);
+
+        engine.new_game();
+        assert_eq!(engine.searcher.transposition_load(), 0.0);
+    }
+
+    #[test]
+    fn test_configure_hash_size() {
+        let mut engine = Engine::new();
+        engine.set_deterministic(true);
+        engine.set_board_state(
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap(),
+        );
+        let config = EngineConfig {
+            book_path: None,
+            hash_size_mb: 1,
+            ..EngineConfig::default()
+        };
+        engine.configure(config.clone()).unwrap();
+        engine
+            .search_best_move(false, Some(4), None, None, false, SearchControls::default())
+            .unwrap();
+
+        // The load is relative to the configured size, which a 1MB table fills up quickly
+        let load = engine.searcher.transposition_load();
+        assert!(load > 0.0 && load <= 1.0, "{}", load);
+
+        // Reconfiguring other options keeps the table, while resizing it starts a new one
+        engine
+            .configure(EngineConfig {
+                contempt: 10,
+                ..config.clone()
+            })
+            .unwrap();
+        assert_eq!(engine.searcher.transposition_load(), load);
+        engine
+            .configure(EngineConfig {
+                hash_size_mb: 2,
+                ..config
+            })
+            .unwrap();
+        assert_eq!(engine.searcher.transposition_load(), 0.0);
+    }
+
+    #[test]
+    fn test_search_moves_restricts_root() {
+        let mut engine = Engine::new();
+        engine.set_deterministic(true);
+        engine.set_board_state(parse_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap());
+        let mut search = |search_moves: &[&str]| {
+            let search_moves = search_moves
+                .iter()
+                .map(|m| Move::from_long_algebraic(m).unwrap())
+                .collect();
+            engine.set_search_moves(Some(search_moves));
+            engine
+                .search_best_move(false, Some(4), None, None, false, SearchControls::default())
+                .unwrap()
+                .format_long_algebraic()
+        };
+
+        // Restricted to a king move, even though the rook could take the hanging queen. The
+        // illegal entry is ignored.
+        assert_eq!(search(&["e1f1", "a2a4"]), "e1f1");
+        assert!(["e1f1", "e1f2"].contains(&search(&["e1f1", "e1f2"]).as_str()));
+
+        // With no legal entries at all every move is searched
+        assert_eq!(search(&["a2a4"]), "d2d5");
+        assert_eq!(search(&[]), "d2d5");
+
+        // The restricted searches didn't leave anything misleading in the transposition table
+        engine.set_search_moves(None);
+        let best_move = engine
+            .search_best_move(false, Some(4), None, None, false, SearchControls::default())
+            .unwrap();
+        assert_eq!(best_move.format_long_algebraic(), "d2d5");
+    }
+
+    #[test]
+    fn test_time_managed_search_ignores_default_depth() {
+        let mut engine = Engine::new();
+        engine
+            .configure(EngineConfig {
+                default_depth: 2,
+                book_path: None,
+                ..EngineConfig::default()
+            })
+            .unwrap();
+
+        // Searches with timings but no depth are left for the time manager to stop
+        assert_eq!(engine.depth_limit(None, true), MAX_SEARCH_DEPTH);
+        assert_eq!(engine.depth_limit(Some(5), true), 5);
+
+        // Without timings, or without time management, the default depth applies instead
+        assert_eq!(engine.depth_limit(None, false), 2);
+        engine.set_deterministic(true);
+        assert_eq!(engine.depth_limit(None, true), 2);
+
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let mut run = |limits| {
+            engine
+                .analyze(&state, limits)
+                .expect("Expected to be able to start an analysis")
+                .map(|info| (info.depth, info.pv, info.nodes))
+                .collect::<Vec<_>>()
+        };
+        let timed = run(SearchLimits {
+            timings: Some(Timings::default()),
+            ..SearchLimits::default()
+        });
+        let fixed_depth = run(SearchLimits {
+            max_depth: Some(2),
+            ..SearchLimits::default()
+        });
+        assert!(!timed.is_empty());
+        assert_eq!(timed, fixed_depth);
+    }
+
+    #[test]
+    fn test_skill_level() {
+        // Rxd5 wins the queen for free
+        let state = parse_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let best = Move::from_long_algebraic("d2d5").unwrap();
+
+        let moves_at_level = |skill_level: u8| {
+            let mut engine = Engine::new();
+            engine.set_deterministic(true);
+            engine
+                .configure(EngineConfig {
+                    book_path: None,
+                    skill_level,
+                    seed: Some(42),
+                    ..EngineConfig::default()
+                })
+                .unwrap();
+            engine.set_board_state(state);
+
+            (0..20)
+                .map(|_| {
+                    engine
+                        .search_best_move(
+                            false,
+                            Some(3),
+                            None,
+                            None,
+                            true,
+                            SearchControls {
+                                stop: Arc::new(AtomicBool::new(false)),
+                                perf_info: None,
+                                ..SearchControls::default()
+                            },
+                        )
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(moves_at_level(skill::MAX_SKILL_LEVEL)
+            .iter()
+            .all(|&m| m == best));
+
+        let weakest = moves_at_level(0);
+        let mistakes = weakest.iter().filter(|&&m| m != best).count();
+        assert!(
+            mistakes >= 10,
+            "Only {} mistakes at skill level 0",
+            mistakes
+        );
+
+        // The same seed makes the same mistakes
+        assert_eq!(moves_at_level(0), weakest);
+    }
+}
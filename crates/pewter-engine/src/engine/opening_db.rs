@@ -1,14 +1,43 @@
 use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
-use pewter_core::{io::pgn::Game, state::GameResult, Color, Move, State, zobrist::ZobristHash};
+use pewter_core::{
+    io::pgn::{parse_single_pgn, Game},
+    state::GameResult,
+    zobrist::{self, ZobristHash},
+    Color, Move, State,
+};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The version of the serialized format, written as its first byte, to be incremented whenever a
+/// change to the format means that earlier files can't be read.
+///
+/// Version 2 keys positions by `position_key` rather than the raw zobrist hash. Version 3 hashes
+/// each castling right separately, rather than each combination of rights.
+pub const FORMAT_VERSION: u8 = 3;
+
+/// The key that a position is stored under in the DB.
+///
+/// The zobrist hash of a state includes its en passant square after every double pawn push, so
+/// the same position reached by different move orders can hash differently, eg `1. d4 d5 2. c4`
+/// and `1. c4 d5 2. d4`. The book only keeps the en passant square if it could actually be
+/// captured onto, so that such transpositions share an entry.
+pub fn position_key(state: &State) -> ZobristHash {
+    let mut key = state.zobrist;
+    if state.en_passant.is_some() {
+        key ^= zobrist::ep_number(state.en_passant);
+        key ^= zobrist::ep_number(state.effective_en_passant());
+    }
+
+    key
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OpeningDb(HashMap<ZobristHash, Vec<DbResult>>);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DbResult {
     /// The potential move
     pub m: Move,
@@ -27,6 +56,56 @@ impl DbResult {
     pub fn total_count(&self) -> u32 {
         self.wins + self.draws + self.losses
     }
+
+    /// The results that follow from this move, from the perspective of the player making it
+    pub fn wdl(&self) -> WdlCounts {
+        WdlCounts {
+            wins: self.wins,
+            draws: self.draws,
+            losses: self.losses,
+        }
+    }
+}
+
+/// Numbers of games won, drawn, and lost, from the perspective of one of the players.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WdlCounts {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl WdlCounts {
+    pub fn total_count(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The same games from the perspective of the other player
+    pub fn flipped(self) -> Self {
+        Self {
+            wins: self.losses,
+            draws: self.draws,
+            losses: self.wins,
+        }
+    }
+}
+
+impl std::ops::Add for WdlCounts {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            wins: self.wins + other.wins,
+            draws: self.draws + other.draws,
+            losses: self.losses + other.losses,
+        }
+    }
+}
+
+impl std::iter::Sum for WdlCounts {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |a, b| a + b)
+    }
 }
 
 impl OpeningDb {
@@ -34,20 +113,17 @@ impl OpeningDb {
         Self(HashMap::new())
     }
 
-    pub fn add_game(&mut self, game: &Game) {
-        let mut state = game.initial;
+    /// Record the moves of the given game, stopping after the first `max_ply` plies if given
+    pub fn add_game(&mut self, game: &Game, max_ply: Option<usize>) {
+        let max_ply = max_ply.unwrap_or(game.moves.len());
+        for (state, m) in game.positions().take(max_ply) {
+            let existing_set = self.0.entry(position_key(&state)).or_insert(Vec::new());
 
-        // Be a little defensive
-        state.zobrist = pewter_core::zobrist::calculate_entire_zobrist(&state);
-
-        for m in &game.moves {
-            let existing_set = self.0.entry(state.zobrist).or_insert(Vec::new());
-
-            let result = match existing_set.iter().position(|r| r.m == *m) {
+            let result = match existing_set.iter().position(|r| r.m == m) {
                 Some(idx) => existing_set.get_mut(idx).unwrap(),
                 None => {
                     existing_set.push(DbResult {
-                        m: *m,
+                        m,
                         wins: 0,
                         draws: 0,
                         losses: 0,
@@ -67,9 +143,68 @@ impl OpeningDb {
                 (_, GameResult::Draw) => result.draws += 1,
                 (_, GameResult::Ongoing) => panic!("Can't add an ongoing game to the opening DB"),
             }
+        }
+    }
+
+    /// Build a new DB from the games of a multi-game PGN, see `add_pgn_reader`
+    pub fn from_pgn_reader<R: BufRead>(
+        reader: R,
+        min_count: u32,
+        max_ply: Option<usize>,
+    ) -> Result<Self> {
+        let mut db = Self::new_empty();
+        db.add_pgn_reader(reader, min_count, max_ply)?;
+        Ok(db)
+    }
+
+    /// Add every game of a multi-game PGN to this DB, reading it one game at a time so that the
+    /// whole PGN never has to be held in memory. Games that fail to parse are skipped, and only
+    /// the first `max_ply` plies of each game are recorded if given.
+    ///
+    /// Afterwards, moves that occur fewer than `min_count` times are removed, along with any
+    /// positions that are left without moves. Returns the number of games that were added.
+    pub fn add_pgn_reader<R: BufRead>(
+        &mut self,
+        reader: R,
+        min_count: u32,
+        max_ply: Option<usize>,
+    ) -> Result<usize> {
+        let mut added = 0;
+        let mut add_pgn = |pgn: &str| {
+            if let Ok(game) = parse_single_pgn(pgn) {
+                self.add_game(&game, max_ply);
+                added += 1;
+            }
+        };
+
+        // The same game boundaries as `parse_multi_pgn`, where a new game starts at the first tag
+        // line following some movetext.
+        let mut pgn = String::new();
+        let mut in_tags = false;
+        for line in reader.lines() {
+            let line = line?;
+            if line.starts_with('[') {
+                if !in_tags && !pgn.is_empty() {
+                    add_pgn(&pgn);
+                    pgn.clear();
+                }
+                in_tags = true;
+            } else {
+                in_tags = false;
+            }
+
+            pgn.push_str(&line);
+            pgn.push('\n');
+        }
 
-            state = state.apply_move(*m);
+        if !pgn.is_empty() {
+            add_pgn(&pgn);
         }
+
+        self.filter_moves(|r| r.total_count() >= min_count);
+        self.prune(0);
+
+        Ok(added)
     }
 
     /// Remove all moves for which the given function returns false
@@ -130,22 +265,293 @@ impl OpeningDb {
         }
     }
 
+    /// Remove every position that can't be reached from `initial` by playing at most `max_ply`
+    /// book moves, eg to cut down a DB that was built without a ply limit.
+    ///
+    /// Positions that were recorded deeper in some game but can also be reached within the limit
+    /// by another move order are kept.
+    pub fn prune_beyond_ply(&mut self, initial: &State, max_ply: usize) {
+        let mut reachable = HashSet::new();
+        let mut frontier = vec![*initial];
+        for _ply in 0..max_ply {
+            let mut next_frontier = Vec::new();
+            for state in frontier {
+                if !reachable.insert(position_key(&state)) {
+                    continue;
+                }
+
+                for r in self.query(&state) {
+                    next_frontier.push(state.apply_move(r.m));
+                }
+            }
+            frontier = next_frontier;
+        }
+
+        self.0.retain(|k, _v| reachable.contains(k));
+    }
+
+    /// The database as `FORMAT_VERSION`, followed by the compressed database
     pub fn serialize(&self) -> Result<Vec<u8>> {
         let dat = serde_cbor::to_vec(self)?;
-        let compressed_dat = zstd::encode_all(&dat[..], 5)?;
-        Ok(compressed_dat)
+        let mut out = vec![FORMAT_VERSION];
+        out.extend(zstd::encode_all(&dat[..], 5)?);
+        Ok(out)
     }
 
+    /// Read a database written by `serialize`, failing if it was written in a different version
+    /// of the format
     pub fn deserialize(data: &[u8]) -> Result<Self> {
+        let (&version, data) = data
+            .split_first()
+            .ok_or_else(|| anyhow!("Opening db is empty"))?;
+        if version != FORMAT_VERSION {
+            return Err(anyhow!(
+                "Opening db is format version {}, but only version {} is supported",
+                version,
+                FORMAT_VERSION
+            ));
+        }
+
         let decompressed_data = zstd::decode_all(data)?;
         let db = serde_cbor::from_slice(&decompressed_data)?;
         Ok(db)
     }
 
     pub fn query(&self, state: &State) -> &[DbResult] {
-        match self.0.get(&state.zobrist) {
+        match self.0.get(&position_key(state)) {
             Some(r) => r,
             None => &[],
         }
     }
+
+    /// The combined results of every book move in the given state, from the perspective of the
+    /// player to move
+    pub fn aggregate(&self, state: &State) -> WdlCounts {
+        self.query(state).iter().map(DbResult::wdl).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::pgn::parse_multi_pgn;
+
+    const MULTI_PGN: &str = r#"[Event "One"]
+[Result "1-0"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+
+[Event "Two"]
+[Result "0-1"]
+
+1. f3 e5 2. g4 Qh4# 0-1
+
+[Event "Three"]
+[Result "1/2-1/2"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 a6
+4. Ba4 Nf6 1/2-1/2
+
+[Event "Four"]
+[Result "1-0"]
+
+1. e4 c5 2. Nf3 d6 1-0
+"#;
+
+    #[test]
+    fn test_pgn_reader_matches_batch_build() {
+        for min_count in 0..=3 {
+            let mut batch = OpeningDb::new_empty();
+            for game in parse_multi_pgn(MULTI_PGN).unwrap() {
+                batch.add_game(&game.unwrap(), None);
+            }
+            batch.filter_moves(|r| r.total_count() >= min_count);
+            batch.prune(0);
+
+            let mut streamed = OpeningDb::new_empty();
+            let added = streamed
+                .add_pgn_reader(MULTI_PGN.as_bytes(), min_count, None)
+                .unwrap();
+            assert_eq!(added, 4);
+            assert_eq!(streamed, batch, "min_count {}", min_count);
+        }
+
+        // 1. e4 was played in three of the games
+        let start = pewter_core::io::fen::parse_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        )
+        .unwrap();
+        let db = OpeningDb::from_pgn_reader(MULTI_PGN.as_bytes(), 2, None).unwrap();
+        let results = db.query(&start);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].m, Move::from_long_algebraic("e2e4").unwrap());
+        assert_eq!(
+            (results[0].wins, results[0].draws, results[0].losses),
+            (2, 1, 0)
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut db = OpeningDb::new_empty();
+        for game in parse_multi_pgn(MULTI_PGN).unwrap() {
+            db.add_game(&game.unwrap(), None);
+        }
+
+        let start = pewter_core::io::fen::parse_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        )
+        .unwrap();
+        let e4 = Move::from_long_algebraic("e2e4").unwrap();
+        let f3 = Move::from_long_algebraic("f2f3").unwrap();
+
+        // White won two games and drew one after 1. e4, and lost the game after 1. f3
+        let total = db.aggregate(&start);
+        assert_eq!(
+            total,
+            WdlCounts {
+                wins: 2,
+                draws: 1,
+                losses: 1
+            }
+        );
+        assert_eq!(total, db.query(&start).iter().map(|r| r.wdl()).sum());
+
+        let record = |m| db.query(&start).iter().find(|r| r.m == m).unwrap().wdl();
+        assert_eq!(total, record(e4) + record(f3));
+
+        // After 1. e4 it's black to move, so the same games are seen from the other side
+        let after_e4 = start.apply_move(e4);
+        assert_eq!(db.aggregate(&after_e4), record(e4).flipped());
+        assert_eq!(
+            db.aggregate(&after_e4),
+            WdlCounts {
+                wins: 0,
+                draws: 1,
+                losses: 2
+            }
+        );
+
+        // Positions that aren't in the book have no results
+        let a6 = Move::from_long_algebraic("a7a6").unwrap();
+        assert_eq!(db.aggregate(&after_e4.apply_move(a6)), WdlCounts::default());
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let mut db = OpeningDb::new_empty();
+        for game in parse_multi_pgn(MULTI_PGN).unwrap() {
+            db.add_game(&game.unwrap(), None);
+        }
+
+        let data = db.serialize().unwrap();
+        assert_eq!(data[0], FORMAT_VERSION);
+        assert_eq!(OpeningDb::deserialize(&data).unwrap(), db);
+
+        // Other versions are rejected outright, rather than being read as garbage
+        let mut other_version = data.clone();
+        other_version[0] = FORMAT_VERSION + 1;
+        let err = OpeningDb::deserialize(&other_version).unwrap_err();
+        assert!(err.to_string().contains("version"), "{}", err);
+
+        assert!(OpeningDb::deserialize(&[]).is_err());
+        assert!(OpeningDb::deserialize(&data[..data.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_transpositions_share_entries() {
+        let pgn = r#"[Event "One"]
+[Result "1-0"]
+
+1. d4 Nf6 2. c4 e6 3. Nc3 1-0
+
+[Event "Two"]
+[Result "0-1"]
+
+1. c4 e6 2. d4 Nf6 3. Nc3 0-1
+
+[Event "Three"]
+[Result "1/2-1/2"]
+
+1. d4 d5 2. c4 dxc4 1/2-1/2
+
+[Event "Four"]
+[Result "1-0"]
+
+1. c4 d5 2. d4 dxc4 1-0
+"#;
+        let db = OpeningDb::from_pgn_reader(pgn.as_bytes(), 0, None).unwrap();
+        let games = parse_multi_pgn(pgn)
+            .unwrap()
+            .into_iter()
+            .map(|g| g.unwrap())
+            .collect::<Vec<_>>();
+        let after = |game: &Game, ply: usize| game.initial.apply_moves(&game.moves[..ply]);
+
+        // Both move orders reach the same position before 3. Nc3, and share a single record of it
+        let nc3 = Move::from_long_algebraic("b1c3").unwrap();
+        for game in &games[..2] {
+            let results = db.query(&after(game, 4));
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].m, nc3);
+            assert_eq!(
+                (results[0].wins, results[0].draws, results[0].losses),
+                (1, 0, 1)
+            );
+        }
+
+        // The last move was a double pawn push in each of these, leaving different en passant
+        // squares behind that neither side could actually capture onto
+        let (three, four) = (after(&games[2], 3), after(&games[3], 3));
+        assert_ne!(three.en_passant, four.en_passant);
+        assert!(three.same_board(&four));
+        assert_eq!(position_key(&three), position_key(&four));
+        assert_eq!(db.query(&three), db.query(&four));
+        assert_eq!(db.aggregate(&three).total_count(), 2);
+
+        // A capturable en passant square still distinguishes positions that are otherwise the same
+        let ep = pewter_core::io::fen::parse_fen(
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        )
+        .unwrap();
+        let no_ep = pewter_core::io::fen::parse_fen(
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3",
+        )
+        .unwrap();
+        assert_ne!(position_key(&ep), position_key(&no_ep));
+    }
+
+    #[test]
+    fn test_max_ply() {
+        let pgn = "[Event \"Ruy Lopez\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 \
+                   6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 1/2-1/2\n";
+        let game = pewter_core::io::pgn::parse_single_pgn(pgn).unwrap();
+        assert_eq!(game.moves.len(), 20);
+
+        let max_ply = 10;
+        let mut limited = OpeningDb::new_empty();
+        limited.add_game(&game, Some(max_ply));
+
+        let mut pruned = OpeningDb::new_empty();
+        pruned.add_game(&game, None);
+        pruned.prune_beyond_ply(&game.initial, max_ply);
+
+        // The position before each of the first ten moves is recorded, and nothing after that
+        let mut state = game.initial;
+        for (ply, &m) in game.moves.iter().enumerate() {
+            for db in [&limited, &pruned] {
+                let results = db.query(&state);
+                if ply < max_ply {
+                    assert_eq!(results.len(), 1, "ply {}", ply);
+                    assert_eq!(results[0].m, m, "ply {}", ply);
+                } else {
+                    assert!(results.is_empty(), "ply {}", ply);
+                }
+            }
+            state = state.apply_move(m);
+        }
+
+        assert_eq!(limited, pruned);
+    }
 }
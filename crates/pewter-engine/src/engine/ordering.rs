@@ -1,6 +1,6 @@
 use std::cmp::Reverse;
 
-use pewter_core::{Move, State, Piece};
+use pewter_core::{chessmove::move_kind, Move, MoveKind, Piece, State};
 
 use super::{eval::{Evaluation, self}, transposition::TranspositionTable};
 
@@ -11,17 +11,19 @@ fn predicted_score(state: &State, m: Move, hash_move: Option<Move>) -> Evaluatio
         .expect("Move doesn't target a piece")
         .1;
 
-    if let Some((_color, capture_piece)) = state.board.get(m.to) {
+    let kind = move_kind(state, m);
+
+    if let Some(capture_piece) = kind.captured_piece() {
         // Capturing a high value piece with a low value piece is best
-        score += eval::consts::piece_value(capture_piece) - eval::consts::piece_value(piece);
+        score += capture_piece.value() - piece.value();
 
         // Capturing anything is better than capturing nothing, so add enough to make sure that the
         // score is still higher.
-        score += eval::consts::piece_value(Piece::Queen) + 10;
+        score += Piece::Queen.value() + 10;
     }
     
-    if let Some(promotion) = m.promotion {
-        score += eval::consts::piece_value(promotion);
+    if let MoveKind::Promotion(promotion) | MoveKind::PromotionCapture(_, promotion) = kind {
+        score += promotion.value();
     }
     
     if hash_move == Some(m) {
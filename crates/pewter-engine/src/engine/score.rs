@@ -0,0 +1,294 @@
+//! A structured view of evaluations, which keeps the details of how mates are encoded into an
+//! `Evaluation` in one place.
+
+use std::cmp::Ordering;
+
+use pewter_core::io::uci::InfoScore;
+
+use super::eval::{self, consts::MATE};
+use super::Evaluation;
+
+/// Evaluations within this distance of `MATE` (or its negation) are mate scores, with the
+/// distance being the number of plies until the mate.
+const MAX_MATE_PLIES: Evaluation = 1024;
+
+/// A score from the perspective of the player to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// An ordinary evaluation, in centipawns
+    Cp(i32),
+
+    /// The player to move can force mate in this many moves (not plies). Zero or negative if the
+    /// player to move is getting mated, with zero meaning that they already have been.
+    Mate(i32),
+}
+
+impl Score {
+    /// The evaluation of a position where the player to move will be mated after the given number
+    /// of plies, so that the search prefers the quickest mates and the slowest defeats.
+    pub const fn mated_in_plies(plies: u8) -> Evaluation {
+        MATE + plies as Evaluation
+    }
+
+    /// Convert an evaluation of a position `ply_from_root` plies from the root of the search into
+    /// the evaluation to keep in the transposition table.
+    ///
+    /// Mate scores count plies from the root, but the same position can turn up at any ply, so in
+    /// the table they count plies from the position itself instead. Other evaluations are
+    /// unchanged.
+    pub fn to_table(eval: Evaluation, ply_from_root: u8) -> Evaluation {
+        let plies = ply_from_root as Evaluation;
+        if Self::is_mated(eval) {
+            eval - plies
+        } else if Self::is_mated(eval.saturating_neg()) {
+            eval + plies
+        } else {
+            eval
+        }
+    }
+
+    /// The inverse of `to_table`, for an evaluation read from the transposition table at the given
+    /// ply.
+    pub fn from_table(eval: Evaluation, ply_from_root: u8) -> Evaluation {
+        let plies = ply_from_root as Evaluation;
+        if Self::is_mated(eval) {
+            eval + plies
+        } else if Self::is_mated(eval.saturating_neg()) {
+            eval - plies
+        } else {
+            eval
+        }
+    }
+
+    /// Whether the evaluation is close enough to `MATE` to be the player to move getting mated.
+    ///
+    /// Unlike `from_evaluation`, a little past `MATE` counts too, as bounds on mate scores that
+    /// are converted by `to_table` can end up there.
+    fn is_mated(eval: Evaluation) -> bool {
+        eval.saturating_sub(MATE).saturating_abs() < MAX_MATE_PLIES
+    }
+
+    pub fn from_evaluation(eval: Evaluation) -> Self {
+        // Saturating, as evaluations close to the infinities are far from any mate score anyway
+        let mated_plies = eval.saturating_sub(MATE);
+        let mating_plies = eval.saturating_neg().saturating_sub(MATE);
+        if (0..MAX_MATE_PLIES).contains(&mated_plies) {
+            Score::Mate(-(mated_plies / 2))
+        } else if (0..MAX_MATE_PLIES).contains(&mating_plies) {
+            Score::Mate((mating_plies + 1) / 2)
+        } else {
+            Score::Cp(eval)
+        }
+    }
+
+    /// The evaluation of this score, which is always between the infinities.
+    ///
+    /// Mates too far away to be represented are brought as close as the encoding allows.
+    pub fn to_evaluation(self) -> Evaluation {
+        let max_moves = MAX_MATE_PLIES / 2;
+        match self {
+            Score::Cp(cp) => eval::clamp(cp),
+            Score::Mate(moves) if moves > 0 => {
+                -Self::mated_in_plies(0) - (moves.min(max_moves) * 2 - 1)
+            }
+            Score::Mate(moves) => Self::mated_in_plies(0) - moves.max(1 - max_moves) * 2,
+        }
+    }
+
+    /// Whether this is a mate score for either player
+    pub fn is_mate(self) -> bool {
+        matches!(self, Score::Mate(_))
+    }
+
+    /// A key that sorts in the same order as the scores themselves
+    fn sort_key(self) -> (u8, i32) {
+        match self {
+            // Lasting longer before being mated is better
+            Score::Mate(moves) if moves <= 0 => (0, -moves),
+            Score::Cp(cp) => (1, cp),
+            // Mating sooner is better
+            Score::Mate(moves) => (2, -moves),
+        }
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<Score> for InfoScore {
+    fn from(score: Score) -> Self {
+        let (centipawns, mate) = match score {
+            Score::Cp(cp) => (cp, None),
+            Score::Mate(moves) => (0, Some(moves)),
+        };
+
+        InfoScore {
+            centipawns,
+            mate,
+            lowerbound: false,
+            upperbound: false,
+        }
+    }
+}
+
+impl From<&InfoScore> for Score {
+    fn from(score: &InfoScore) -> Self {
+        match score.mate {
+            Some(moves) => Score::Mate(moves),
+            None => Score::Cp(score.centipawns),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::uci::{format_message, parse_message, InfoMessage, UciMessage};
+
+    #[test]
+    fn test_score_ordering() {
+        let ascending = [
+            Score::Mate(0),
+            Score::Mate(-1),
+            Score::Mate(-5),
+            Score::Cp(-100_000),
+            Score::Cp(-30),
+            Score::Cp(0),
+            Score::Cp(30),
+            Score::Cp(100_000),
+            Score::Mate(5),
+            Score::Mate(1),
+        ];
+
+        for pair in ascending.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+            assert!(
+                pair[0].to_evaluation() < pair[1].to_evaluation(),
+                "{:?} < {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluation_round_trip() {
+        // Being mated on the board, and mating with the next move
+        assert_eq!(
+            Score::from_evaluation(Score::mated_in_plies(0)),
+            Score::Mate(0)
+        );
+        assert_eq!(
+            Score::from_evaluation(-Score::mated_in_plies(1)),
+            Score::Mate(1)
+        );
+
+        // Mated by the opponent's third move, and mating with our own third move
+        assert_eq!(
+            Score::from_evaluation(Score::mated_in_plies(6)),
+            Score::Mate(-3)
+        );
+        assert_eq!(
+            Score::from_evaluation(-Score::mated_in_plies(5)),
+            Score::Mate(3)
+        );
+
+        for score in [-250, 0, 17, 100_000].map(Score::Cp) {
+            assert_eq!(Score::from_evaluation(score.to_evaluation()), score);
+        }
+
+        for moves in -100..=100 {
+            let score = Score::Mate(moves);
+            assert_eq!(Score::from_evaluation(score.to_evaluation()), score);
+        }
+    }
+
+    #[test]
+    fn test_table_evaluations() {
+        // A mate three plies below a node four plies from the root is stored as three plies from
+        // that node, and read back as eight plies from the root when met again at ply five
+        let mated = Score::mated_in_plies(7);
+        assert_eq!(Score::to_table(mated, 4), Score::mated_in_plies(3));
+        assert_eq!(
+            Score::from_table(Score::to_table(mated, 4), 5),
+            Score::mated_in_plies(8)
+        );
+        assert_eq!(
+            Score::from_table(Score::to_table(-mated, 4), 5),
+            -Score::mated_in_plies(8)
+        );
+
+        for ply in [0, 1, 10] {
+            for eval in [
+                Score::mated_in_plies(0),
+                -Score::mated_in_plies(1),
+                -250,
+                0,
+                100_000,
+                eval::consts::NEG_INFINITY,
+                eval::consts::POS_INFINITY,
+            ] {
+                assert_eq!(Score::from_table(Score::to_table(eval, ply), ply), eval);
+            }
+
+            // Ordinary evaluations are the same wherever they are found
+            assert_eq!(Score::to_table(-250, ply), -250);
+        }
+    }
+
+    #[test]
+    fn test_extreme_evaluations() {
+        use eval::consts::{NEG_INFINITY, POS_INFINITY};
+
+        for eval in [Evaluation::MIN, NEG_INFINITY, POS_INFINITY, Evaluation::MAX] {
+            assert!(!Score::from_evaluation(eval).is_mate(), "{}", eval);
+        }
+
+        // Mates given over UCI can be arbitrarily far away
+        assert_eq!(
+            Score::from_evaluation(Score::Mate(i32::MAX).to_evaluation()),
+            Score::Mate(MAX_MATE_PLIES / 2)
+        );
+        assert_eq!(
+            Score::from_evaluation(Score::Mate(i32::MIN).to_evaluation()),
+            Score::Mate(1 - MAX_MATE_PLIES / 2)
+        );
+        assert_eq!(Score::Cp(i32::MAX).to_evaluation(), POS_INFINITY);
+        assert_eq!(Score::Cp(i32::MIN).to_evaluation(), NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_uci_round_trip() {
+        for score in [
+            Score::Cp(-35),
+            Score::Cp(0),
+            Score::Cp(120),
+            Score::Mate(-2),
+            Score::Mate(4),
+        ] {
+            let msg = UciMessage::Info(InfoMessage {
+                score: Some(score.into()),
+                ..InfoMessage::default()
+            });
+
+            let parsed = match parse_message(&format_message(msg)) {
+                Ok(UciMessage::Info(InfoMessage {
+                    score: Some(parsed),
+                    ..
+                })) => parsed,
+                other => panic!("Expected an info message with a score, got {:?}", other),
+            };
+            assert_eq!(Score::from(&parsed), score);
+        }
+    }
+}
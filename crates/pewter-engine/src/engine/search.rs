@@ -1,3 +1,4 @@
+use std::cmp::Reverse;
 use std::fmt::Write;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -6,18 +7,28 @@ use std::time::{Duration, Instant};
 use crossbeam_channel::Sender;
 
 use crate::engine::ordering::order_moves;
-use pewter_core::{movegen::legal_moves, Color, Move, State};
-
+use pewter_core::chessmove::move_kind;
+use pewter_core::{
+    adjudicator::insufficient_material, movegen::legal_moves, state::MAX_PHASE_SCORE,
+    zobrist::ZobristHash, Color, Move, MoveKind, State,
+};
+
+use super::endgame::{self, Wdl};
+use super::score::Score;
 use super::transposition::{NodeType, TranspositionTable};
-use super::{eval, EngineError, Evaluation, PerfInfo, Timings};
+use super::{eval, DrawClaim, EngineError, Evaluation, PerfInfo, RootReport, RootVerdict, Timings};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct SearchControls {
     /// Periodically ready by every search thread. The search will be terminated when this is true.
     pub stop: Arc<AtomicBool>,
 
     /// Outlet for periodic performance events during the search.
     pub perf_info: Option<Sender<PerfInfo>>,
+
+    /// Read by the search each time it has something to report. While this is true, the reports
+    /// are sent to `perf_info`, see `Searcher::debug_info`.
+    pub debug: Arc<AtomicBool>,
 }
 
 #[derive(Clone, Debug)]
@@ -31,6 +42,16 @@ impl MoveChain {
         MoveChainIter { curr: Some(self) }
     }
 
+    /// Build a chain from a non-empty sequence of moves
+    fn from_moves(moves: &[Move]) -> Option<Self> {
+        let (last, rest) = moves.split_last()?;
+        let mut chain = MoveChain::Terminal(*last);
+        for &m in rest.iter().rev() {
+            chain = MoveChain::NonTerminal(m, Box::new(chain));
+        }
+        Some(chain)
+    }
+
     fn first(&self) -> Move {
         match self {
             MoveChain::Terminal(m) => *m,
@@ -82,6 +103,26 @@ impl Variation {
     }
 }
 
+/// A summary of a single completed iteration of the iterative deepening search
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    /// The depth that this iteration searched to
+    pub depth: u8,
+
+    /// The evaluation of the principal variation, from the perspective of the player to move
+    pub score: Evaluation,
+
+    /// The best line found by this iteration
+    pub pv: Vec<Move>,
+
+    /// The number of nodes visited since the start of the search
+    pub nodes: u64,
+
+    /// The time elapsed since the start of the search
+    pub time: Duration,
+}
+
+#[derive(Clone)]
 pub struct Searcher {
     controls: SearchControls,
 
@@ -94,70 +135,356 @@ pub struct Searcher {
     /// The number of visited nodes that weren't transposition table hits
     nodes_searched: u64,
 
+    /// The number of nodes visited by the quiescence search
+    quiescence_nodes: u64,
+
+    /// When set, searches stop once they have visited this many nodes, see `set_max_nodes`
+    max_nodes: Option<u64>,
+
     t_table: TranspositionTable,
 
     principal_variation: Option<Variation>,
+
+    /// When false, searches ignore the clock entirely and are bounded only by depth and the stop
+    /// signal.
+    time_managed: bool,
+
+    /// When true, quiet moves that can't plausibly raise alpha are skipped near the leaves
+    futility_pruning: bool,
+
+    /// When true, positions where the side to move is in check are searched one ply deeper
+    check_extensions: bool,
+
+    /// Time held back from every move's time allowance, see `time_allowance`
+    move_overhead: Duration,
+
+    /// When set, the search is looking for a forced mate in at most this many moves, and stops as
+    /// soon as one is found.
+    mate_target: Option<u8>,
+
+    /// The maximum number of captures the quiescence search plays out before falling back to the
+    /// static evaluation
+    max_quiescence_ply: u8,
+
+    /// The hashes of the positions played in the game before the root of the search, oldest first
+    game_history: Vec<ZobristHash>,
+
+    /// When set, only these moves are searched from the root, see `set_root_moves`
+    root_moves: Option<Vec<Move>>,
+
+    /// How much worse than even a draw is for the player to move at the root, see `draw_score`
+    contempt: Evaluation,
+
+    /// The player at the root and the contempt that the draw scores in the transposition table
+    /// were worked out for, or None if they were worked out without contempt.
+    table_contempt: Option<(Color, Evaluation)>,
+
+    /// The hashes of every position before the one currently being searched, including the game
+    /// history, oldest first
+    history: Vec<ZobristHash>,
 }
 
 struct SearchResult {
     eval: Evaluation,
     pv: Option<Variation>,
+
+    /// True if the evaluation depends on the moves that led to this node, and not just on the
+    /// position itself, because somewhere below it a position was scored as a repetition draw.
+    path_dependent: bool,
+
+    /// True if the search was stopped before every move of this node, or of some node below it,
+    /// had been searched, so that the evaluation isn't a bound on the real value.
+    cut_short: bool,
 }
 
 impl SearchResult {
     fn just_eval(eval: Evaluation) -> Self {
-        Self { eval, pv: None }
+        Self {
+            eval,
+            pv: None,
+            path_dependent: false,
+            cut_short: false,
+        }
     }
 }
 
+/// The futility pruning margins, indexed by the number of plies remaining before the quiescence
+/// search.
+const FUTILITY_MARGINS: [Evaluation; 2] = [300, 500];
+
+/// The maximum number of check extensions along any single line, so that long sequences of checks
+/// can't blow up the size of the search.
+const MAX_CHECK_EXTENSIONS: u8 = 4;
+
+/// The deepest that iterative deepening will go, for searches which are only meant to be limited
+/// by time.
+pub const MAX_SEARCH_DEPTH: u8 = 64;
+
+/// The shallowest iteration to report each root move of as it's searched. Shallower iterations
+/// finish too quickly for the reports to be of any use.
+const MIN_CURRENT_MOVE_DEPTH: u8 = 4;
+
+/// The default maximum depth of the quiescence search, which is deep enough to resolve all but
+/// the most pathological capture sequences.
+pub const DEFAULT_MAX_QUIESCENCE_PLY: u8 = 8;
+
+/// How long a time managed search should spend on the next move.
+///
+/// Less of the remaining time is spent on each move earlier in the game, as there are more moves
+/// still to play. The move overhead is held back from the allowance, so that delays between the
+/// engine choosing its move and the clock being stopped, eg in the GUI or over the network, don't
+/// lose on time.
+pub(crate) fn time_allowance(
+    timings: &Timings,
+    state: &State,
+    move_overhead: Duration,
+) -> Duration {
+    let remaining = match state.to_play {
+        Color::White => timings.white_remaining,
+        Color::Black => timings.black_remaining,
+    }
+    .unwrap_or(Duration::from_secs(60));
+    let this_move = timings.move_time.unwrap_or(Duration::from_millis(250));
+
+    // Between a twentieth of the remaining time in the opening and a tenth in the endgame
+    let moves_to_go = 10 + 10 * state.phase_score() / MAX_PHASE_SCORE;
+    std::cmp::min(remaining / moves_to_go as u32, this_move).saturating_sub(move_overhead)
+}
+
 impl Searcher {
+    /// A searcher with a transposition table of `DEFAULT_HASH_SIZE_MB`, see `set_hash_size_mb`
     pub fn new(controls: SearchControls) -> Self {
         Self {
             controls,
             nodes_searched: 0,
+            quiescence_nodes: 0,
+            max_nodes: None,
             last_search_start: Instant::now(),
             last_perf_info: Instant::now(),
             t_table: TranspositionTable::new_empty(),
             principal_variation: None,
+            time_managed: true,
+            futility_pruning: true,
+            check_extensions: true,
+            move_overhead: Duration::ZERO,
+            mate_target: None,
+            max_quiescence_ply: DEFAULT_MAX_QUIESCENCE_PLY,
+            game_history: Vec::new(),
+            root_moves: None,
+            contempt: 0,
+            table_contempt: None,
+            history: Vec::new(),
+        }
+    }
+
+    /// Enable or disable all time based limits on the search.
+    ///
+    /// Disabling time management makes fixed depth searches fully reproducible.
+    pub fn set_time_managed(&mut self, time_managed: bool) {
+        self.time_managed = time_managed;
+    }
+
+    /// Enable or disable futility pruning of quiet moves close to the leaves of the search
+    pub fn set_futility_pruning(&mut self, futility_pruning: bool) {
+        self.futility_pruning = futility_pruning;
+    }
+
+    /// Enable or disable extending the search by a ply whenever the side to move is in check
+    pub fn set_check_extensions(&mut self, check_extensions: bool) {
+        self.check_extensions = check_extensions;
+    }
+
+    /// Stop the following searches once they have visited the given number of nodes, as for the
+    /// UCI "go nodes" command, or lift the limit with None.
+    ///
+    /// The search still finds a move if the limit is reached part way through its first iteration.
+    pub fn set_max_nodes(&mut self, max_nodes: Option<u64>) {
+        self.max_nodes = max_nodes;
+    }
+
+    /// Whether the search has visited as many nodes as it's allowed to
+    fn node_limit_reached(&self) -> bool {
+        self.max_nodes
+            .is_some_and(|max_nodes| self.nodes_searched >= max_nodes)
+    }
+
+    /// Set how much time to hold back from each move to allow for communication delays
+    pub fn set_move_overhead(&mut self, move_overhead: Duration) {
+        self.move_overhead = move_overhead;
+    }
+
+    /// Set the maximum number of captures that the quiescence search plays out from the leaves of
+    /// the main search
+    pub fn set_max_quiescence_ply(&mut self, max_quiescence_ply: u8) {
+        self.max_quiescence_ply = max_quiescence_ply;
+    }
+
+    /// Set the hashes of the positions played before the position that will be searched, oldest
+    /// first, so that the search can see repetitions of them.
+    pub fn set_game_history(&mut self, game_history: Vec<ZobristHash>) {
+        self.game_history = game_history;
+    }
+
+    /// Restrict the following searches to only consider the given moves from the root, as for the
+    /// UCI "searchmoves" option, or lift the restriction with None.
+    ///
+    /// The moves are searched as given, so they must all be legal in the searched position and
+    /// there must be at least one of them, see `Engine::set_search_moves`.
+    pub fn set_root_moves(&mut self, root_moves: Option<Vec<Move>>) {
+        self.root_moves = root_moves;
+    }
+
+    /// The moves to search from the root state, either the restricted ones or every legal move
+    fn allowed_root_moves(&self, state: &State) -> Vec<Move> {
+        match &self.root_moves {
+            Some(allowed) => allowed.clone(),
+            None => legal_moves(state).iter().collect(),
         }
     }
 
+    /// Set how much the player to move at the root should avoid draws, in centipawns. Negative
+    /// contempt makes draws more attractive than an even position instead.
+    pub fn set_contempt(&mut self, contempt: Evaluation) {
+        self.contempt = contempt;
+    }
+
+    /// The score of a drawn position the given number of plies from the root, from the perspective
+    /// of the player to move there.
+    ///
+    /// Contempt counts against the player to move at the root, so it counts for their opponent,
+    /// who is to move at every odd ply. Within a search the side to move at any position is the
+    /// same wherever it turns up, but the transposition table outlives the search, and these scores
+    /// are flipped for a search with the other player at the root. See `check_table_contempt`.
+    fn draw_score(&self, ply_from_root: u8) -> Evaluation {
+        match ply_from_root % 2 {
+            0 => eval::consts::DRAW - self.contempt,
+            _ => eval::consts::DRAW + self.contempt,
+        }
+    }
+
+    /// Clear the transposition table if the draw scores in it were worked out for a different
+    /// player at the root of the search, or with a different contempt, than a search of the given
+    /// root state would use.
+    fn check_table_contempt(&mut self, root: &State) {
+        let table_contempt = match self.contempt {
+            0 => None,
+            contempt => Some((root.to_play, contempt)),
+        };
+
+        if self.table_contempt != table_contempt {
+            self.t_table.clear();
+            self.table_contempt = table_contempt;
+        }
+    }
+
+    /// Replace the transposition table with an empty one of roughly the given size, unless it's
+    /// already that size.
+    pub fn set_hash_size_mb(&mut self, size_mb: usize) {
+        if self.t_table.max_entries() != TranspositionTable::entries_for_size_mb(size_mb) {
+            self.t_table = TranspositionTable::with_size_mb(size_mb);
+        }
+    }
+
+    /// Seed the random choices made by the search, see `TranspositionTable::set_seed`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.t_table.set_seed(seed);
+    }
+
+    /// Replace the stop signal and performance channel used by the following searches
+    pub fn set_controls(&mut self, controls: SearchControls) {
+        self.controls = controls;
+    }
+
+    /// Forget everything learned from earlier searches, for when they were of a different game
+    pub fn new_game(&mut self) {
+        self.t_table.clear();
+        self.principal_variation = None;
+        self.game_history.clear();
+    }
+
+    /// How full the transposition table is, between 0 and 1. The table is kept between searches,
+    /// so this is only zero before the first search of each game.
+    pub fn transposition_load(&self) -> f32 {
+        self.t_table.load()
+    }
+
+    /// The number of nodes visited by the last search
+    pub fn nodes_searched(&self) -> u64 {
+        self.nodes_searched
+    }
+
+    /// The reply that the last search expects to its best move, ie the second move of its principal
+    /// variation, if the variation is that long.
+    pub fn ponder_move(&self) -> Option<Move> {
+        self.principal_variation
+            .as_ref()
+            .and_then(|pv| pv.moves.iter().nth(1))
+    }
+
+    /// The number of nodes visited by the quiescence search of the last search
+    pub fn quiescence_nodes_searched(&self) -> u64 {
+        self.quiescence_nodes
+    }
+
     pub fn search(
         &mut self,
         state: &State,
         max_depth: u8,
         timings: Timings,
         infinite: bool,
+    ) -> Result<Move, EngineError> {
+        self.search_with_callback(state, max_depth, timings, infinite, |_| ())
+    }
+
+    /// As `search`, but calls `on_iteration` after each completed iteration of iterative deepening
+    pub fn search_with_callback(
+        &mut self,
+        state: &State,
+        max_depth: u8,
+        timings: Timings,
+        infinite: bool,
+        mut on_iteration: impl FnMut(SearchInfo),
     ) -> Result<Move, EngineError> {
         self.last_search_start = Instant::now();
         self.last_perf_info = Instant::now();
+        self.nodes_searched = 0;
+        self.quiescence_nodes = 0;
         self.principal_variation = None;
-
-        let time_heuristic = {
-            let remaining = match state.to_play {
-                Color::White => timings.white_remaining,
-                Color::Black => timings.black_remaining,
-            }
-            .unwrap_or(Duration::from_secs(60));
-            let this_move = timings.move_time.unwrap_or(Duration::from_millis(250));
-
-            std::cmp::min(remaining / 10, this_move)
-        };
+        self.history.clone_from(&self.game_history);
+        self.check_table_contempt(state);
+
+        let time_heuristic = time_allowance(&timings, state, self.move_overhead);
+        if !infinite && self.time_managed {
+            self.debug_info(format!(
+                "Allocated {}ms to this move",
+                time_heuristic.as_millis()
+            ))?;
+        }
 
         let mut last_pv = None;
         for depth in 1.. {
             if !infinite && depth >= max_depth {
-                tracing::debug!("Stopping search because reached max_depth of {max_depth}");
+                self.debug_info(format!(
+                    "Stopping search because reached max_depth of {max_depth}"
+                ))?;
                 break;
             }
 
-            if !infinite && self.last_search_start.elapsed() > time_heuristic {
-                tracing::debug!("Stopping search because of time heuristic");
+            if !infinite && self.time_managed && self.last_search_start.elapsed() > time_heuristic {
+                self.debug_info("Stopping search because of time heuristic".to_string())?;
                 break;
             }
 
             if self.controls.stop.load(Ordering::Relaxed) {
-                tracing::debug!("Stopping search because stop signal recieved");
+                self.debug_info("Stopping search because stop signal received".to_string())?;
+                break;
+            }
+
+            if depth > 1 && self.node_limit_reached() {
+                self.debug_info(format!(
+                    "Stopping search because searched {} nodes",
+                    self.nodes_searched
+                ))?;
                 break;
             }
 
@@ -166,10 +493,12 @@ impl Searcher {
                 state,
                 0,
                 depth,
+                0,
                 eval::consts::NEG_INFINITY,
                 eval::consts::POS_INFINITY,
             )?;
 
+            let cut_short = result.cut_short;
             last_pv = result.pv;
 
             let last_pv = last_pv
@@ -177,9 +506,47 @@ impl Searcher {
                 .expect("Search concluded without a principal variation");
 
             tracing::info!("Searched depth {}, pv {}", depth, last_pv.format());
+
+            // An iteration cut short by a stop request or the node limit may not have considered
+            // every root move
+            if !cut_short && !self.controls.stop.load(Ordering::Relaxed) {
+                on_iteration(SearchInfo {
+                    depth,
+                    score: last_pv.eval,
+                    pv: last_pv.moves.iter().collect(),
+                    nodes: self.nodes_searched,
+                    time: self.last_search_start.elapsed(),
+                });
+            }
+
+            // Each iteration only finds mates at least as quick as the last, so there is nothing
+            // left to look for once one is found.
+            if let (Some(target), Score::Mate(moves)) =
+                (self.mate_target, Score::from_evaluation(last_pv.eval))
+            {
+                if moves > 0 && moves <= target as i32 {
+                    self.debug_info(format!("Stopping search because found mate in {moves}"))?;
+                    break;
+                }
+            }
         }
 
-        self.emit_perf_msg()?;
+        self.principal_variation = last_pv.clone();
+
+        let stats = self.t_table.stats();
+        self.debug_info(format!(
+            "Transposition table has {} entries ({:.1}% full), {} hits and {} misses",
+            stats.entries,
+            stats.load * 100.0,
+            stats.hits,
+            stats.misses
+        ))?;
+
+        let root_report = last_pv.as_ref().map(|pv| self.root_report(state, pv));
+        if let Some(report) = &root_report {
+            tracing::info!("Root report: {:?}", report);
+        }
+        self.emit_perf_msg(root_report, None)?;
 
         if self.controls.stop.load(Ordering::Relaxed) {
             Err(EngineError::EarlyStop)
@@ -190,63 +557,325 @@ impl Searcher {
         }
     }
 
+    /// Search for a forced mate in at most the given number of moves, ignoring the clock.
+    ///
+    /// Returns the first move of the quickest mate found, or the best move found if there is no
+    /// such mate. Futility pruning is disabled for the duration, as it could hide quiet mating
+    /// moves.
+    pub fn search_mate(&mut self, state: &State, moves: u8) -> Result<Move, EngineError> {
+        // The position after the mating move is 2N - 1 plies from the root, and has to be searched
+        // in full to see that it is mate.
+        let max_depth = moves.saturating_mul(2).saturating_sub(1).max(1);
+
+        let time_managed = std::mem::replace(&mut self.time_managed, false);
+        let futility_pruning = std::mem::replace(&mut self.futility_pruning, false);
+        self.mate_target = Some(moves);
+
+        // Iterative deepening stops one short of the given depth
+        let result = self.search(
+            state,
+            max_depth.saturating_add(1),
+            Timings::default(),
+            false,
+        );
+
+        self.time_managed = time_managed;
+        self.futility_pruning = futility_pruning;
+        self.mate_target = None;
+
+        result
+    }
+
+    /// Summarize what a search of the given root state concluded, given its principal variation
+    fn root_report(&self, state: &State, pv: &Variation) -> RootReport {
+        let score = pv.eval;
+        let verdict =
+            if insufficient_material(state) || endgame::kpk_result(state) == Some(Wdl::Draw) {
+                RootVerdict::Drawn
+            } else {
+                match Score::from_evaluation(score) {
+                    Score::Mate(moves) if moves > 0 => RootVerdict::Won,
+                    Score::Mate(_) => RootVerdict::Lost,
+                    Score::Cp(_) => RootVerdict::Undecided,
+                }
+            };
+
+        // The history counts the earlier occurrences of the root, so two of them means that the
+        // root is the third.
+        let lookback = (state.halfmove_clock as usize).min(self.game_history.len());
+        let earlier_occurrences = self.game_history[self.game_history.len() - lookback..]
+            .iter()
+            .filter(|&&h| h == state.zobrist)
+            .count();
+        let draw_claim = if state.halfmove_clock >= 100 {
+            Some(DrawClaim::FiftyMove)
+        } else if earlier_occurrences >= 2 {
+            Some(DrawClaim::ThreefoldRepetition)
+        } else {
+            None
+        };
+
+        RootReport {
+            verdict,
+            score,
+            pv: pv.moves.iter().collect(),
+            draw_claim,
+        }
+    }
+
+    /// Search every legal move from the given state to the given depth, returning each move along
+    /// with its score, from best to worst.
+    ///
+    /// Unlike `search`, every move gets an exact score rather than just being proven worse than the
+    /// best, which makes this much more expensive for the same depth.
+    ///
+    /// The stop signal and time allowance are checked between root moves, and once either runs out
+    /// only the moves ranked so far are returned, which always includes at least one. An infinite
+    /// search ignores the clock, and holds on to its ranking until the stop signal arrives.
+    pub fn rank_root_moves(
+        &mut self,
+        state: &State,
+        depth: u8,
+        timings: Timings,
+        infinite: bool,
+    ) -> Result<Vec<(Move, Evaluation)>, EngineError> {
+        self.last_search_start = Instant::now();
+        self.last_perf_info = Instant::now();
+        self.nodes_searched = 0;
+        self.quiescence_nodes = 0;
+        self.history.clone_from(&self.game_history);
+        self.history.push(state.zobrist);
+        self.check_table_contempt(state);
+
+        let time_heuristic = time_allowance(&timings, state, self.move_overhead);
+
+        let mut ranked = Vec::new();
+        for m in self.allowed_root_moves(state) {
+            if !ranked.is_empty() {
+                if self.controls.stop.load(Ordering::Relaxed) {
+                    self.debug_info("Stopping ranking because stop signal received".to_string())?;
+                    break;
+                }
+
+                if self.node_limit_reached() {
+                    self.debug_info(format!(
+                        "Stopping ranking because searched {} nodes",
+                        self.nodes_searched
+                    ))?;
+                    break;
+                }
+
+                if !infinite
+                    && self.time_managed
+                    && self.last_search_start.elapsed() > time_heuristic
+                {
+                    self.debug_info("Stopping ranking because of time heuristic".to_string())?;
+                    break;
+                }
+            }
+
+            let result = self.search_moves(
+                &state.apply_move(m),
+                1,
+                depth,
+                0,
+                eval::consts::NEG_INFINITY,
+                eval::consts::POS_INFINITY,
+            )?;
+            ranked.push((m, -result.eval));
+        }
+
+        // Ties are broken by the ordering of the moves themselves, to keep this reproducible
+        ranked.sort_by_key(|&(m, score)| (Reverse(score), m));
+
+        // The host of an infinite search doesn't expect an answer until it asks for one
+        while infinite && !self.controls.stop.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        Ok(ranked)
+    }
+
     fn search_moves(
         &mut self,
         state: &State,
         ply_from_root: u8,
         max_depth: u8,
+        extensions: u8,
         mut alpha: Evaluation,
         beta: Evaluation,
     ) -> Result<SearchResult, EngineError> {
         self.nodes_searched += 1;
 
+        // Checks are forcing, so searching them deeper is cheap and often uncovers mates that
+        // would otherwise sit just past the horizon. The quiescence search doesn't consider check
+        // evasions, so this also keeps positions in check out of it.
+        let (max_depth, extensions) =
+            if self.check_extensions && extensions < MAX_CHECK_EXTENSIONS && state.in_check() {
+                (max_depth + 1, extensions + 1)
+            } else {
+                (max_depth, extensions)
+            };
+
+        // Repeating a position can't achieve anything that wasn't achievable the first time it was
+        // reached, so score it as a draw. The score only holds along this particular line, so it
+        // must be kept out of the transposition table.
+        if ply_from_root > 0 && self.is_repetition(state) {
+            return Ok(SearchResult {
+                eval: self.draw_score(ply_from_root),
+                pv: None,
+                path_dependent: true,
+                cut_short: false,
+            });
+        }
+
+        // No amount of searching can find a mate in a dead position
+        if ply_from_root > 0 && insufficient_material(state) {
+            return Ok(SearchResult::just_eval(self.draw_score(ply_from_root)));
+        }
+
+        // Winning a drawn king and pawn ending can look easy to the static evaluation, so cut the
+        // search short as soon as one is reached. Won endings are still searched, as the search
+        // needs to find the moves that make progress.
+        if ply_from_root > 0 && endgame::kpk_result(state) == Some(Wdl::Draw) {
+            return Ok(SearchResult::just_eval(self.draw_score(ply_from_root)));
+        }
+
         if ply_from_root > max_depth {
-            let quiesce_score = self.quiescence_search(state, alpha, beta);
+            let quiesce_score = self.quiescence_search(state, ply_from_root, 0, alpha, beta);
             return Ok(SearchResult::just_eval(quiesce_score));
         }
 
         let depth_remaining = max_depth - ply_from_root;
 
+        // The result of a root restricted to some of its moves isn't the value of the position, so
+        // mustn't be taken from or put in the transposition table.
+        let restricted_root = ply_from_root == 0 && self.root_moves.is_some();
+
         // First, check the transposition table in case we've been here before
-        if let Some(tt) = self.t_table.probe(state, depth_remaining, alpha, beta) {
+        let tt_hit = if restricted_root {
+            None
+        } else {
+            self.t_table.probe(
+                state,
+                depth_remaining,
+                Score::to_table(alpha, ply_from_root),
+                Score::to_table(beta, ply_from_root),
+            )
+        };
+        if let Some(mut tt) = tt_hit {
+            tt.node_value = Score::from_table(tt.node_value, ply_from_root);
+
+            // Only Exact entries know their best line, bounds just prove that this node doesn't
+            // matter to the parent.
+            let pv = match tt.node_type {
+                NodeType::Exact => {
+                    let moves = self
+                        .t_table
+                        .principal_variation(state, depth_remaining as usize + 1);
+                    MoveChain::from_moves(&moves).map(|moves| Variation {
+                        moves,
+                        eval: tt.node_value,
+                    })
+                }
+                _ => None,
+            };
+
             return Ok(SearchResult {
                 eval: tt.node_value,
-
-                // TODO: Store+export PV in transposition table for Exact nodes
-                pv: None,
+                pv,
+                path_dependent: false,
+                cut_short: false,
             });
         }
 
-        let mut moves = legal_moves(state).iter().collect::<Vec<Move>>();
+        let mut moves = match ply_from_root {
+            0 => self.allowed_root_moves(state),
+            _ => legal_moves(state).iter().collect::<Vec<Move>>(),
+        };
 
         order_moves(state, &mut moves, &self.t_table);
 
         if moves.len() == 0 {
             if state.in_check() {
-                return Ok(SearchResult::just_eval(eval::consts::MATE));
+                return Ok(SearchResult::just_eval(Score::mated_in_plies(
+                    ply_from_root,
+                )));
             } else {
-                return Ok(SearchResult::just_eval(eval::consts::DRAW));
+                return Ok(SearchResult::just_eval(self.draw_score(ply_from_root)));
             }
         }
 
+        // If even a generous positional gain couldn't lift the static evaluation up to alpha, quiet
+        // moves are very unlikely to be worth searching. Captures, promotions, and checks can swing
+        // the evaluation by much more than the margin so are always searched.
+        let futile = self.futility_pruning
+            && ply_from_root > 0
+            && !state.in_check()
+            && FUTILITY_MARGINS
+                .get(depth_remaining as usize)
+                .map(|&margin| eval::saturating_add(eval::evaluate(state), margin) <= alpha)
+                .unwrap_or(false);
+
         let mut best_move = None;
         let mut node_type = NodeType::UpperBound;
         let mut pv = None;
+        let mut path_dependent = restricted_root;
+        let mut cut_short = false;
+
+        let move_count = moves.len();
+        for (move_idx, m) in moves.into_iter().enumerate() {
+            if ply_from_root == 0 && max_depth >= MIN_CURRENT_MOVE_DEPTH {
+                self.emit_perf_msg(None, Some((m, move_idx as u16 + 1)))?;
+            }
+
+            let is_quiet = matches!(
+                move_kind(state, m),
+                MoveKind::Quiet | MoveKind::DoublePawnPush | MoveKind::Castle(_)
+            );
 
-        for m in moves {
             let new_state = state.apply_move(m);
-            let result =
-                self.search_moves(&new_state, ply_from_root + 1, max_depth, -beta, -alpha)?;
+            if futile && is_quiet && !new_state.in_check() {
+                continue;
+            }
+
+            self.history.push(state.zobrist);
+            let result = self.search_moves(
+                &new_state,
+                ply_from_root + 1,
+                max_depth,
+                extensions,
+                -beta,
+                -alpha,
+            );
+            self.history.pop();
+            let result = result?;
 
             let score = -result.eval;
+            path_dependent |= result.path_dependent;
+            cut_short |= result.cut_short;
 
             // The move was too good, so the opponent wont allow this position to be reached in the
             // first place
             if score >= beta {
                 // TODO: Should the inserted node value be `score` rather than `beta`?
-                self.t_table
-                    .insert(state, depth_remaining, beta, NodeType::LowerBound, None);
-                return Ok(SearchResult::just_eval(beta));
+                // The refuting move is likely to refute this position again, so keep it around to
+                // be tried first next time.
+                if !path_dependent && !cut_short {
+                    self.t_table.insert(
+                        state,
+                        depth_remaining,
+                        Score::to_table(beta, ply_from_root),
+                        NodeType::LowerBound,
+                        Some(m),
+                    );
+                }
+                return Ok(SearchResult {
+                    eval: beta,
+                    pv: None,
+                    path_dependent,
+                    cut_short,
+                });
             }
 
             if score > alpha {
@@ -265,41 +894,83 @@ impl Searcher {
 
             self.maybe_emit_perf_msg(ply_from_root, max_depth)?;
             if self.should_stop(ply_from_root, max_depth) {
+                cut_short |= move_idx + 1 < move_count;
                 break;
             }
         }
 
-        self.t_table
-            .insert(state, depth_remaining, alpha, node_type, best_move);
+        // The moves that weren't searched could have been better, so a node that was cut short
+        // mustn't be stored as if it had been searched in full. The transposition table is kept
+        // between searches, so such an entry would mislead the next search of the same position.
+        if !path_dependent && !cut_short {
+            self.t_table.insert(
+                state,
+                depth_remaining,
+                Score::to_table(alpha, ply_from_root),
+                node_type,
+                best_move,
+            );
+        }
 
-        Ok(SearchResult { eval: alpha, pv })
+        Ok(SearchResult {
+            eval: alpha,
+            pv,
+            path_dependent,
+            cut_short,
+        })
     }
 
+    /// True if the given position has already occurred since the last irreversible move
+    fn is_repetition(&self, state: &State) -> bool {
+        let lookback = (state.halfmove_clock as usize).min(self.history.len());
+        self.history[self.history.len() - lookback..].contains(&state.zobrist)
+    }
+
+    /// Play out the captures available from a leaf of the main search, `qply` plies past the leaf
+    /// and `ply_from_root` plies from the root.
     fn quiescence_search(
         &mut self,
         state: &State,
+        ply_from_root: u8,
+        qply: u8,
         alpha: Evaluation,
         beta: Evaluation,
     ) -> Evaluation {
+        self.quiescence_nodes += 1;
+
+        // The static evaluation rewards pushing a lone king into a corner, but has no idea of
+        // whether it has been stalemated there. Lone kings have so few moves that it's cheap to
+        // check explicitly.
+        if state.board.color_board(state.to_play).count() == 1 && !legal_moves(state).any() {
+            return if state.in_check() {
+                Score::mated_in_plies(ply_from_root)
+            } else {
+                self.draw_score(ply_from_root)
+            };
+        }
+
         let root_eval = eval::evaluate(state);
         if root_eval >= beta {
             return beta;
         }
         let mut alpha = std::cmp::max(alpha, root_eval);
 
-        fn move_is_capture(state: &State, m: Move) -> bool {
-            state.board.color_board(!state.to_play).get(m.to)
+        // Bound the size of the quiescence tree in positions with long chains of captures, settling
+        // for the static evaluation once the cap is reached
+        if qply >= self.max_quiescence_ply {
+            return alpha;
         }
 
         let mut moves = legal_moves(state)
             .iter()
-            .filter(|m| move_is_capture(state, *m))
+            .filter(|m| m.is_capture(state))
             .collect::<Vec<Move>>();
         order_moves(state, &mut moves, &self.t_table);
 
         for m in moves {
             let new_state = state.apply_move(m);
-            let score = -self.quiescence_search(&new_state, -beta, -alpha);
+            let score =
+                -self.quiescence_search(&new_state, ply_from_root + 1, qply + 1, -beta, -alpha);
             if score >= beta {
                 return beta;
             }
@@ -312,9 +983,11 @@ impl Searcher {
 
     #[inline(always)]
     fn should_stop(&mut self, ply_from_root: u8, max_depth: u8) -> bool {
-        if max_depth - ply_from_root >= 4 {
+        if self.node_limit_reached() {
+            true
+        } else if max_depth - ply_from_root >= 4 {
             self.controls.stop.load(Ordering::Relaxed)
-        } else if ply_from_root == 0 {
+        } else if ply_from_root == 0 && self.time_managed {
             self.last_search_start.elapsed() > Duration::from_millis(500)
         } else {
             false
@@ -325,26 +998,768 @@ impl Searcher {
     fn maybe_emit_perf_msg(&mut self, ply_from_root: u8, max_depth: u8) -> Result<(), EngineError> {
         if max_depth - ply_from_root >= 4 {
             if self.last_perf_info.elapsed().as_secs() > 3 {
-                self.emit_perf_msg()?;
+                self.emit_perf_msg(None, None)?;
             }
         }
 
         Ok(())
     }
 
-    fn emit_perf_msg(&mut self) -> Result<(), EngineError> {
+    fn emit_perf_msg(
+        &mut self,
+        root_report: Option<RootReport>,
+        current_move: Option<(Move, u16)>,
+    ) -> Result<(), EngineError> {
         if let Some(perf_sender) = &self.controls.perf_info {
             perf_sender.send(PerfInfo {
-                transposition_load: self.t_table.load(),
-                nodes: self.nodes_searched,
-                nodes_per_second: self.nodes_searched as f32
-                    / self.last_search_start.elapsed().as_secs_f32(),
-                table_hits: 0,
-                shredder_hits: 0,
+                root_report,
+                current_move,
+                ..self.perf_info()
             })?;
         }
         self.last_perf_info = Instant::now();
 
         Ok(())
     }
+
+    /// Log a note on what the search is doing, and while the debug control is set also send it to
+    /// whoever is listening for performance info, eg for a UCI "info string".
+    ///
+    /// The debug control is checked afresh for every note, so it can be toggled mid-search.
+    pub fn debug_info(&self, note: String) -> Result<(), EngineError> {
+        tracing::debug!("{}", note);
+
+        if !self.controls.debug.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if let Some(perf_sender) = &self.controls.perf_info {
+            perf_sender.send(PerfInfo {
+                info_string: Some(note),
+                ..self.perf_info()
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// The performance of the search so far, without any of the optional reports
+    fn perf_info(&self) -> PerfInfo {
+        PerfInfo {
+            transposition_load: self.t_table.load(),
+            nodes: self.nodes_searched,
+            nodes_per_second: self.nodes_searched as f32
+                / self.last_search_start.elapsed().as_secs_f32(),
+            table_hits: 0,
+            shredder_hits: 0,
+            root_report: None,
+            current_move: None,
+            info_string: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::fen::parse_fen;
+
+    fn test_searcher() -> Searcher {
+        Searcher::new(SearchControls {
+            stop: Arc::new(AtomicBool::new(false)),
+            perf_info: None,
+            ..SearchControls::default()
+        })
+    }
+
+    #[test]
+    fn test_time_allowance_holds_back_move_overhead() {
+        let overhead = Duration::from_millis(30);
+        let timings = Timings {
+            white_remaining: Some(Duration::from_secs(1)),
+            black_remaining: Some(Duration::from_secs(20)),
+            move_time: Some(Duration::from_millis(500)),
+            ..Timings::default()
+        };
+
+        let bare_kings = |to_play| parse_fen(&format!("4k3/8/8/8/8/8/8/4K3 {} - - 0 1", to_play));
+        let white = bare_kings("w").unwrap();
+        let black = bare_kings("b").unwrap();
+
+        // A tenth of white's remaining time in the endgame, and black's requested move time
+        for (state, allowance) in [(&white, 100), (&black, 500)] {
+            let allowance = Duration::from_millis(allowance);
+            assert_eq!(time_allowance(&timings, state, Duration::ZERO), allowance);
+            assert_eq!(
+                time_allowance(&timings, state, overhead),
+                allowance - overhead
+            );
+        }
+
+        // Only a twentieth in the opening, with the rest of the game still to play
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(
+            time_allowance(&timings, &start, Duration::ZERO),
+            Duration::from_millis(50)
+        );
+
+        // The overhead can use up everything, but no more
+        let overhead = Duration::from_secs(1);
+        assert_eq!(time_allowance(&timings, &white, overhead), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_draw_score() {
+        let mut searcher = test_searcher();
+        for ply in 0..4 {
+            assert_eq!(searcher.draw_score(ply), eval::consts::DRAW);
+        }
+
+        // Draws are bad for the player to move at the root, and so good for their opponent
+        searcher.set_contempt(30);
+        assert_eq!(searcher.draw_score(0), -30);
+        assert_eq!(searcher.draw_score(1), 30);
+        assert_eq!(searcher.draw_score(2), -30);
+        assert_eq!(searcher.draw_score(3), 30);
+
+        // The lone king is stalemated, which is good for it when its opponent is at the root
+        let state = parse_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let score = searcher.quiescence_search(
+            &state,
+            1,
+            0,
+            eval::consts::NEG_INFINITY,
+            eval::consts::POS_INFINITY,
+        );
+        assert_eq!(score, 30);
+
+        // Black is a queen up, so white's best option is still to repeat the position, but now that
+        // costs white the contempt
+        let state = parse_fen("4k3/8/8/8/8/8/q7/4K1N1 w - - 10 20").unwrap();
+        let repeated = state.apply_move(Move::from_long_algebraic("g1f3").unwrap());
+        searcher.set_time_managed(false);
+        searcher.set_game_history(vec![repeated.zobrist]);
+        let mut score = None;
+        searcher
+            .search_with_callback(&state, 4, Timings::default(), false, |info| {
+                score = Some(info.score)
+            })
+            .expect("Expected the search to find a move");
+        assert_eq!(score, Some(-30));
+    }
+
+    #[test]
+    fn test_contempt_draws_dont_carry_over_to_the_other_player() {
+        let white = parse_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let black = parse_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+        let stored = |searcher: &Searcher| {
+            searcher
+                .t_table
+                .probe(
+                    &white,
+                    0,
+                    eval::consts::POS_INFINITY,
+                    eval::consts::NEG_INFINITY,
+                )
+                .is_some()
+        };
+
+        for (contempt, kept) in [(0, true), (30, false)] {
+            let mut searcher = test_searcher();
+            searcher.set_time_managed(false);
+            searcher.set_contempt(contempt);
+
+            searcher
+                .search(&white, 4, Timings::default(), false)
+                .unwrap();
+            assert!(stored(&searcher));
+
+            // Searching for white again keeps the table, but with contempt the draws in it are
+            // the wrong way round for black
+            searcher
+                .search(&white, 4, Timings::default(), false)
+                .unwrap();
+            assert!(stored(&searcher));
+            searcher
+                .search(&black, 4, Timings::default(), false)
+                .unwrap();
+            assert_eq!(stored(&searcher), kept, "contempt {}", contempt);
+        }
+    }
+
+    #[test]
+    fn test_quiescence_sees_lone_king_stalemate() {
+        let state = parse_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let score = test_searcher().quiescence_search(
+            &state,
+            0,
+            0,
+            eval::consts::NEG_INFINITY,
+            eval::consts::POS_INFINITY,
+        );
+        assert_eq!(score, eval::consts::DRAW);
+    }
+
+    #[test]
+    fn test_quiescence_scores_lone_king_mate_by_distance() {
+        // The lone king has been mated by Qg7#, which the quiescence search has to spot itself
+        let state = parse_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+
+        for ply_from_root in [0, 3, 6] {
+            let score = test_searcher().quiescence_search(
+                &state,
+                ply_from_root,
+                0,
+                eval::consts::NEG_INFINITY,
+                eval::consts::POS_INFINITY,
+            );
+            assert_eq!(score, Score::mated_in_plies(ply_from_root));
+        }
+
+        // So a mate three plies from the root is scored as a mate in two for the root player
+        let score = -Score::mated_in_plies(3);
+        assert_eq!(Score::from_evaluation(score), Score::Mate(2));
+    }
+
+    #[test]
+    fn test_quiescence_depth_is_capped() {
+        // Every knight can take an enemy knight, and every capture can be recaptured, so playing out
+        // every capture sequence in full visits hundreds of thousands of nodes
+        let state = parse_fen("k7/8/2nnnn2/2NNNN2/2nnnn2/2NNNN2/8/K7 w - - 0 1").unwrap();
+
+        let mut searcher = test_searcher();
+        searcher.quiescence_search(
+            &state,
+            0,
+            0,
+            eval::consts::NEG_INFINITY,
+            eval::consts::POS_INFINITY,
+        );
+        let capped_nodes = searcher.quiescence_nodes_searched();
+        assert!(capped_nodes < 100_000, "{} quiescence nodes", capped_nodes);
+
+        let mut searcher = test_searcher();
+        searcher.set_max_quiescence_ply(2);
+        searcher.quiescence_search(
+            &state,
+            0,
+            0,
+            eval::consts::NEG_INFINITY,
+            eval::consts::POS_INFINITY,
+        );
+        assert!(searcher.quiescence_nodes_searched() < capped_nodes);
+    }
+
+    #[test]
+    fn test_search_avoids_stalemating_lone_king() {
+        // Qf7 stalemates, while Qe8, Qf8, and Qh7 are all mate
+        let state = parse_fen("7k/4Q3/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+
+        for max_depth in 2..=4 {
+            let m = test_searcher()
+                .search(&state, max_depth, Timings::default(), false)
+                .expect("Expected the search to find a move");
+            let next_state = state.apply_move(m);
+            assert!(next_state.in_check(), "{} doesn't give mate", m);
+            assert!(!legal_moves(&next_state).any(), "{} doesn't give mate", m);
+        }
+    }
+
+    /// Search to a fixed depth, ignoring the clock, returning the best move and the node count
+    fn fixed_depth_search(fen: &str, max_depth: u8, futility_pruning: bool) -> (Move, u64) {
+        let state = parse_fen(fen).unwrap();
+        let mut searcher = test_searcher();
+        searcher.set_time_managed(false);
+        searcher.set_futility_pruning(futility_pruning);
+        let m = searcher
+            .search(&state, max_depth, Timings::default(), false)
+            .expect("Expected the search to find a move");
+        (m, searcher.nodes_searched())
+    }
+
+    #[test]
+    fn test_futility_pruning_keeps_tactics() {
+        let tactical = [
+            // Qxf7 is mate
+            (
+                "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+                "h5f7",
+            ),
+            // Nc7+ forks the king and rook
+            ("r3k3/8/8/3N4/8/8/8/4K3 w - - 0 1", "d5c7"),
+        ];
+
+        for (fen, expected) in tactical {
+            let (pruned_move, _) = fixed_depth_search(fen, 5, true);
+            let (full_move, _) = fixed_depth_search(fen, 5, false);
+            assert_eq!(pruned_move, full_move, "{}", fen);
+            assert_eq!(pruned_move.format_long_algebraic(), expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_futility_pruning_reduces_nodes() {
+        let quiet = "rnbqkb1r/ppp2ppp/4pn2/3p4/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq - 2 4";
+
+        let (_, pruned_nodes) = fixed_depth_search(quiet, 5, true);
+        let (_, full_nodes) = fixed_depth_search(quiet, 5, false);
+        assert!(
+            pruned_nodes < full_nodes,
+            "{} nodes with pruning, {} without",
+            pruned_nodes,
+            full_nodes
+        );
+    }
+
+    #[test]
+    fn test_check_extensions_find_deeper_mates() {
+        // Philidor's legacy: 1. Nh6+ Kh8 2. Qg8+ Rxg8 3. Nf7#
+        let state = parse_fen("5rk1/5Npp/8/8/2Q5/8/8/6K1 w - - 0 1").unwrap();
+
+        // Without extensions, the nominal depth is three plies short of seeing the mate
+        let final_score = |check_extensions: bool| {
+            let mut searcher = test_searcher();
+            searcher.set_time_managed(false);
+            searcher.set_check_extensions(check_extensions);
+
+            let mut score = None;
+            searcher
+                .search_with_callback(&state, 4, Timings::default(), false, |info| {
+                    score = Some(info.score)
+                })
+                .expect("Expected the search to find a move");
+            score.expect("Expected at least one completed iteration")
+        };
+
+        let mate_in_three = Score::Mate(3);
+        assert_eq!(Score::from_evaluation(final_score(true)), mate_in_three);
+        assert!(Score::from_evaluation(final_score(false)) < mate_in_three);
+    }
+
+    #[test]
+    fn test_cutoff_move_is_stored_for_ordering() {
+        // Ra8 is the only move that mates, and it's a quiet move so would otherwise be given no
+        // priority by the move ordering.
+        let state = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mate = Move::from_long_algebraic("a1a8").unwrap();
+
+        let mut searcher = test_searcher();
+        let beta = 5000;
+        let result = searcher
+            .search_moves(&state, 0, 1, 0, eval::consts::NEG_INFINITY, beta)
+            .unwrap();
+        assert_eq!(result.eval, beta);
+
+        let entry = searcher
+            .t_table
+            .probe(
+                &state,
+                0,
+                eval::consts::POS_INFINITY,
+                eval::consts::NEG_INFINITY,
+            )
+            .expect("Expected the cutoff to be stored in the transposition table");
+        assert_eq!(entry.node_type, NodeType::LowerBound);
+        assert_eq!(entry.m, Some(mate));
+
+        let mut moves = legal_moves(&state).iter().collect::<Vec<_>>();
+        order_moves(&state, &mut moves, &searcher.t_table);
+        assert_eq!(moves[0], mate);
+    }
+
+    #[test]
+    fn test_transposition_mates_count_from_the_root() {
+        // Ra8 is mate
+        let state = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut searcher = test_searcher();
+        searcher.set_time_managed(false);
+
+        // Returns the evaluation, and the number of nodes it took to find it
+        let mut search_at_ply = |ply_from_root| {
+            let nodes = searcher.nodes_searched();
+            let eval = searcher
+                .search_moves(
+                    &state,
+                    ply_from_root,
+                    ply_from_root + 2,
+                    0,
+                    eval::consts::NEG_INFINITY,
+                    eval::consts::POS_INFINITY,
+                )
+                .unwrap()
+                .eval;
+            (eval, searcher.nodes_searched() - nodes)
+        };
+
+        let (eval, nodes) = search_at_ply(0);
+        assert_eq!(eval, -Score::mated_in_plies(1));
+        assert!(nodes > 1);
+
+        // The same position met again two plies further from the root is taken from the table,
+        // and the mate is two plies further away with it
+        assert_eq!(search_at_ply(2), (-Score::mated_in_plies(3), 1));
+        assert_eq!(search_at_ply(0), (-Score::mated_in_plies(1), 1));
+    }
+
+    #[test]
+    fn test_stopped_nodes_are_kept_out_of_the_transposition_table() {
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let any_entry = |searcher: &Searcher, state: &State| {
+            searcher.t_table.probe(
+                state,
+                0,
+                eval::consts::POS_INFINITY,
+                eval::consts::NEG_INFINITY,
+            )
+        };
+
+        // With the stop signal already raised, the root gives up after its first move. Nodes this
+        // close to the leaves don't look at the signal, so the first move is still searched in full.
+        let mut searcher = test_searcher();
+        searcher.set_time_managed(false);
+        searcher.controls.stop.store(true, Ordering::Relaxed);
+        let result = searcher
+            .search_moves(
+                &state,
+                0,
+                4,
+                0,
+                eval::consts::NEG_INFINITY,
+                eval::consts::POS_INFINITY,
+            )
+            .unwrap();
+        assert!(result.cut_short);
+        assert!(any_entry(&searcher, &state).is_none());
+
+        let mut moves = legal_moves(&state).iter().collect::<Vec<_>>();
+        order_moves(&state, &mut moves, &TranspositionTable::new_empty());
+        assert!(any_entry(&searcher, &state.apply_move(moves[0])).is_some());
+    }
+
+    #[test]
+    fn test_nodes_cut_short_below_the_last_move_are_kept_out_of_the_transposition_table() {
+        // White's only legal move is to block the check with Re1
+        let state = parse_fen("3q2k1/5ppp/8/8/8/8/4RPPP/r6K w - - 0 1").unwrap();
+        assert_eq!(legal_moves(&state).len(), 1);
+
+        // The node limit is reached while searching the root's one and only move
+        let mut searcher = test_searcher();
+        searcher.set_time_managed(false);
+        searcher.set_max_nodes(Some(50));
+        let result = searcher
+            .search_moves(
+                &state,
+                0,
+                5,
+                0,
+                eval::consts::NEG_INFINITY,
+                eval::consts::POS_INFINITY,
+            )
+            .unwrap();
+        assert!(result.cut_short);
+        assert!(searcher
+            .t_table
+            .probe(
+                &state,
+                0,
+                eval::consts::POS_INFINITY,
+                eval::consts::NEG_INFINITY,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_repetition_draws_are_kept_out_of_the_transposition_table() {
+        // Black is a queen up, so white's best hope is a draw by repetition
+        let state = parse_fen("4k3/8/8/8/8/8/q7/4K1N1 w - - 10 20").unwrap();
+        let repeated = state.apply_move(Move::from_long_algebraic("g1f3").unwrap());
+
+        let final_score = |searcher: &mut Searcher| {
+            let mut score = None;
+            searcher
+                .search_with_callback(&state, 4, Timings::default(), false, |info| {
+                    score = Some(info.score)
+                })
+                .expect("Expected the search to find a move");
+            score.expect("Expected at least one completed iteration")
+        };
+
+        // In a game where Nf3 has been played before, playing it again draws
+        let mut searcher = test_searcher();
+        searcher.set_time_managed(false);
+        searcher.set_game_history(vec![repeated.zobrist]);
+        assert_eq!(final_score(&mut searcher), eval::consts::DRAW);
+
+        // Reaching the same position by a different path doesn't offer that draw, so the score
+        // from the first search mustn't be reused.
+        let mut fresh = test_searcher();
+        fresh.set_time_managed(false);
+        let expected = final_score(&mut fresh);
+        assert!(expected < eval::consts::DRAW);
+
+        searcher.set_game_history(Vec::new());
+        assert_eq!(final_score(&mut searcher), expected);
+    }
+
+    #[test]
+    fn test_max_nodes() {
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let search = |max_nodes| {
+            let mut searcher = test_searcher();
+            searcher.set_time_managed(false);
+            searcher.set_max_nodes(max_nodes);
+
+            let mut depths = Vec::new();
+            let m = searcher
+                .search_with_callback(&state, 6, Timings::default(), false, |info| {
+                    depths.push(info.depth)
+                })
+                .expect("Expected the search to find a move");
+            (m, depths, searcher.nodes_searched())
+        };
+
+        let (_, full_depths, full_nodes) = search(None);
+        assert_eq!(full_depths, [1, 2, 3, 4, 5]);
+
+        // The limit is only checked between moves, so can be overshot by the subtree of one move,
+        // but no further iterations are started once it has been reached
+        let max_nodes = full_nodes / 10;
+        let (m, depths, nodes) = search(Some(max_nodes));
+        assert!(
+            nodes < full_nodes / 2,
+            "{} nodes with a limit of {}",
+            nodes,
+            max_nodes
+        );
+        assert!(depths.len() < full_depths.len());
+        assert!(legal_moves(&state).iter().any(|legal| legal == m));
+
+        // Even the smallest limit leaves a move to play
+        let (m, depths, _) = search(Some(1));
+        assert!(depths.is_empty());
+        assert!(legal_moves(&state).iter().any(|legal| legal == m));
+    }
+
+    #[test]
+    fn test_rank_root_moves_limits() {
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let legal = legal_moves(&state).len();
+
+        let mut searcher = test_searcher();
+        searcher.set_time_managed(false);
+        let ranked = searcher
+            .rank_root_moves(&state, 2, Timings::default(), false)
+            .unwrap();
+        assert_eq!(ranked.len(), legal);
+
+        // Once stopped, only the move already being searched is ranked
+        searcher.controls.stop.store(true, Ordering::Relaxed);
+        let ranked = searcher
+            .rank_root_moves(&state, 2, Timings::default(), false)
+            .unwrap();
+        assert_eq!(ranked.len(), 1);
+
+        // Likewise once there's no time left
+        let no_time = Timings {
+            move_time: Some(Duration::ZERO),
+            ..Timings::default()
+        };
+        let mut searcher = test_searcher();
+        let ranked = searcher.rank_root_moves(&state, 2, no_time, false).unwrap();
+        assert_eq!(ranked.len(), 1);
+
+        // An infinite search ignores the clock, and doesn't finish until it's stopped
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut searcher = Searcher::new(SearchControls {
+            stop: stop.clone(),
+            ..SearchControls::default()
+        });
+        let (ranked_tx, ranked_rx) = crossbeam_channel::bounded(1);
+        let handle = std::thread::spawn(move || {
+            let ranked = searcher.rank_root_moves(&state, 2, no_time, true).unwrap();
+            ranked_tx.send(ranked).unwrap();
+        });
+        assert!(ranked_rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+        assert!(!ranked_rx.recv().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_perf_info_reports_nps() {
+        let (perf_tx, perf_rx) = crossbeam_channel::unbounded();
+        let mut searcher = Searcher::new(SearchControls {
+            stop: Arc::new(AtomicBool::new(false)),
+            perf_info: Some(perf_tx),
+            ..SearchControls::default()
+        });
+        searcher.set_time_managed(false);
+
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        searcher
+            .search(&state, 4, Timings::default(), false)
+            .expect("Expected the search to find a move");
+
+        let last = perf_rx
+            .try_iter()
+            .last()
+            .expect("Expected at least one performance update from the search");
+        assert!(last.nodes > 0);
+        assert!(last.nodes_per_second.is_finite() && last.nodes_per_second > 0.0);
+        assert!(last.transposition_load > 0.0);
+    }
+
+    #[test]
+    fn test_perf_info_reports_current_move() {
+        let (perf_tx, perf_rx) = crossbeam_channel::unbounded();
+        let mut searcher = Searcher::new(SearchControls {
+            stop: Arc::new(AtomicBool::new(false)),
+            perf_info: Some(perf_tx),
+            ..SearchControls::default()
+        });
+        searcher.set_time_managed(false);
+
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let legal = legal_moves(&state).iter().collect::<Vec<_>>();
+        searcher
+            .search(
+                &state,
+                MIN_CURRENT_MOVE_DEPTH + 1,
+                Timings::default(),
+                false,
+            )
+            .expect("Expected the search to find a move");
+
+        // Only the one iteration is deep enough to report its root moves, which it does for every
+        // legal move in turn
+        let reported = perf_rx
+            .try_iter()
+            .filter_map(|perf| perf.current_move)
+            .collect::<Vec<_>>();
+        assert_eq!(reported.len(), legal.len());
+        for (idx, (m, number)) in reported.iter().enumerate() {
+            assert_eq!(*number as usize, idx + 1);
+            assert!(legal.contains(m));
+        }
+    }
+
+    #[test]
+    fn test_root_report() {
+        let (perf_tx, perf_rx) = crossbeam_channel::unbounded();
+        let mut searcher = Searcher::new(SearchControls {
+            stop: Arc::new(AtomicBool::new(false)),
+            perf_info: Some(perf_tx),
+            ..SearchControls::default()
+        });
+        searcher.set_time_managed(false);
+
+        let report = |searcher: &mut Searcher, fen: &str, game_history: &[&str]| {
+            let state = parse_fen(fen).unwrap();
+            let game_history = game_history
+                .iter()
+                .map(|fen| parse_fen(fen).unwrap().zobrist)
+                .collect();
+            searcher.set_game_history(game_history);
+            searcher
+                .search(&state, 3, Timings::default(), false)
+                .expect("Expected the search to find a move");
+
+            perf_rx
+                .try_iter()
+                .filter_map(|perf| perf.root_report)
+                .last()
+                .expect("Expected the search to report on the root")
+        };
+
+        // Nothing can be mated with a lone bishop, and the fifty move rule has already passed
+        let dead_draw = report(&mut searcher, "8/8/4k3/8/8/2B5/8/4K3 w - - 100 120", &[]);
+        assert_eq!(dead_draw.verdict, RootVerdict::Drawn);
+        assert_eq!(dead_draw.score, eval::consts::DRAW);
+        assert_eq!(dead_draw.draw_claim, Some(DrawClaim::FiftyMove));
+
+        // Once the root position has occurred twice before, a draw can be claimed by repetition
+        let repeated = "8/8/4k3/8/8/2B5/8/4K3 w - - 8 120";
+        let elsewhere = "8/8/4k3/8/8/2B5/4K3/8 b - - 7 119";
+        let history = [repeated, elsewhere, repeated, elsewhere];
+        assert_eq!(
+            report(&mut searcher, repeated, &history).draw_claim,
+            Some(DrawClaim::ThreefoldRepetition)
+        );
+        assert_eq!(
+            report(&mut searcher, repeated, &history[1..]).draw_claim,
+            None
+        );
+
+        // Ra8 is mate
+        let winning = report(&mut searcher, "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &[]);
+        assert_eq!(winning.verdict, RootVerdict::Won);
+        assert_eq!(winning.pv, [Move::from_long_algebraic("a1a8").unwrap()]);
+        assert_eq!(winning.draw_claim, None);
+    }
+
+    #[test]
+    fn test_mate_scores_at_the_root() {
+        // Ra8 is mate, so the score at the root is as close to the infinities as mates get
+        let winning = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        // White can only push the h-pawn, after which Qb2 is mate
+        let losing = parse_fen("8/8/8/8/8/1q6/2k4P/K7 w - - 0 1").unwrap();
+
+        for max_depth in 2..=5 {
+            for (state, expected) in [(&winning, Score::Mate(1)), (&losing, Score::Mate(-1))] {
+                let (perf_tx, perf_rx) = crossbeam_channel::unbounded();
+                let mut searcher = Searcher::new(SearchControls {
+                    stop: Arc::new(AtomicBool::new(false)),
+                    perf_info: Some(perf_tx),
+                    ..SearchControls::default()
+                });
+                searcher.set_time_managed(false);
+                searcher
+                    .search(state, max_depth, Timings::default(), false)
+                    .expect("Expected the search to find a move");
+
+                let report = perf_rx
+                    .try_iter()
+                    .filter_map(|perf| perf.root_report)
+                    .last()
+                    .expect("Expected the search to report on the root");
+                assert_eq!(Score::from_evaluation(report.score), expected);
+                assert!(report.score.abs() < eval::consts::POS_INFINITY);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_mate() {
+        let (perf_tx, perf_rx) = crossbeam_channel::unbounded();
+        let mut searcher = Searcher::new(SearchControls {
+            stop: Arc::new(AtomicBool::new(false)),
+            perf_info: Some(perf_tx),
+            ..SearchControls::default()
+        });
+
+        // 1. Nf6+ gxf6 2. Bxf7#
+        let state =
+            parse_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1").unwrap();
+        let m = searcher
+            .search_mate(&state, 2)
+            .expect("Expected the search to find a move");
+        assert_eq!(m, Move::from_long_algebraic("d5f6").unwrap());
+
+        let report = perf_rx
+            .try_iter()
+            .filter_map(|perf| perf.root_report)
+            .last()
+            .expect("Expected the search to report on the root");
+        assert_eq!(Score::from_evaluation(report.score), Score::Mate(2));
+        assert_eq!(report.verdict, RootVerdict::Won);
+
+        let mated = state.apply_moves(&report.pv);
+        assert_eq!(report.pv.len(), 3);
+        assert!(mated.in_check() && !legal_moves(&mated).any());
+    }
 }
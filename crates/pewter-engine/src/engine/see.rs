@@ -0,0 +1,275 @@
+//! Static exchange evaluation, which scores a move by playing out every capture on its destination
+//! square, least valuable attacker first, with either side free to stop capturing whenever
+//! continuing would lose material.
+//!
+//! Only the first move can promote, and pins are ignored.
+
+use pewter_core::bitboard::masks;
+use pewter_core::chessmove::move_kind;
+use pewter_core::movegen::pseudo_legal::king_moves;
+use pewter_core::{BitBoard, BoardPos, Color, Move, Piece, State};
+
+use super::eval::Evaluation;
+
+/// The pieces in the order they're used to capture, cheapest first
+const CAPTURE_ORDER: [Piece; 6] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+/// Every piece of either color attacking the given square, treating only the squares in `occupied`
+/// as occupied, so that sliders lined up behind pieces that have already been exchanged are seen
+fn attackers(state: &State, pos: BoardPos, occupied: BitBoard) -> BitBoard {
+    let board = &state.board;
+    let queens = board.piece_board(Piece::Queen);
+
+    let sliders = board
+        .piece_board(Piece::Rook)
+        .union_with(queens)
+        .intersect_with(masks::rook_rays(pos))
+        .union_with(
+            board
+                .piece_board(Piece::Bishop)
+                .union_with(queens)
+                .intersect_with(masks::bishop_rays(pos)),
+        )
+        .intersect_with(occupied);
+
+    let mut attackers = BitBoard::new_empty();
+    for slider in sliders.iter_set() {
+        if !masks::between(pos, slider).intersect_with(occupied).any() {
+            attackers.set(slider);
+        }
+    }
+
+    let pawns = board
+        .color_piece_board(Color::White, Piece::Pawn)
+        .intersect_with(masks::pawn_attacks(Color::Black, pos))
+        .union_with(
+            board
+                .color_piece_board(Color::Black, Piece::Pawn)
+                .intersect_with(masks::pawn_attacks(Color::White, pos)),
+        );
+
+    attackers
+        .union_with(pawns)
+        .union_with(
+            board
+                .piece_board(Piece::Knight)
+                .intersect_with(masks::knight_moves(pos)),
+        )
+        .union_with(
+            board
+                .piece_board(Piece::King)
+                .intersect_with(king_moves(pos, BitBoard::new_empty())),
+        )
+        .intersect_with(occupied)
+}
+
+/// The cheapest of the given attackers, along with its square
+fn least_valuable(state: &State, attackers: BitBoard) -> Option<(BoardPos, Piece)> {
+    CAPTURE_ORDER.iter().find_map(|&piece| {
+        state
+            .board
+            .piece_board(piece)
+            .intersect_with(attackers)
+            .first_set()
+            .map(|pos| (pos, piece))
+    })
+}
+
+/// The material won by the move itself, the value of the piece left standing on its destination,
+/// and the occupancy of the board just after it
+fn first_capture(state: &State, m: Move) -> (Evaluation, Evaluation, BitBoard) {
+    let (_, piece) = state
+        .board
+        .get(m.from)
+        .expect("No piece on the square being moved from");
+    let mut occupied = state.board.all_union_board().with_cleared(m.from);
+
+    let kind = move_kind(state, m);
+    let mut gain = kind.captured_piece().map_or(0, Piece::value);
+    if kind.is_en_passant() {
+        occupied.clear(m.to.forward(!state.to_play).unwrap());
+    }
+
+    let mut standing = piece.value();
+    if let Some(promotion) = m.promotion {
+        gain += promotion.value() - Piece::Pawn.value();
+        standing = promotion.value();
+    }
+
+    (gain, standing, occupied.with_set(m.to))
+}
+
+/// The material that the player to move wins by playing the given move, assuming that both players
+/// then keep capturing on its destination square for as long as it benefits them.
+pub fn static_exchange_eval(state: &State, m: Move) -> Evaluation {
+    let (gain, mut standing, mut occupied) = first_capture(state, m);
+
+    // The material balance after each capture, from the perspective of the player who made it
+    let mut gains = vec![gain];
+    let mut side = !state.to_play;
+    loop {
+        let all_attackers = attackers(state, m.to, occupied);
+        let our_attackers = all_attackers.intersect_with(state.board.color_board(side));
+        let (pos, piece) = match least_valuable(state, our_attackers) {
+            Some(attacker) => attacker,
+            None => break,
+        };
+
+        // The king can only recapture if nothing can take it back
+        let their_attackers = all_attackers.intersect_with(state.board.color_board(!side));
+        if piece == Piece::King && their_attackers.any() {
+            break;
+        }
+
+        gains.push(standing - gains.last().unwrap());
+        standing = piece.value();
+        occupied.clear(pos);
+        side = !side;
+    }
+
+    // Each player only makes a capture if it's better for them than stopping before it
+    while gains.len() > 1 {
+        let reply = gains.pop().unwrap();
+        let last = gains.last_mut().unwrap();
+        *last = -std::cmp::max(-*last, reply);
+    }
+    gains[0]
+}
+
+/// Whether `static_exchange_eval(state, m) >= threshold`, stopping as soon as the answer is known
+/// rather than playing out the whole exchange.
+pub fn see_ge(state: &State, m: Move, threshold: Evaluation) -> bool {
+    let (gain, standing, mut occupied) = first_capture(state, m);
+
+    // How far the exchange is above the threshold for whoever just captured, assuming that the
+    // piece they captured with is lost. Capturing is only worth it if this stays non-negative even
+    // in the worst case.
+    let mut swap = gain - threshold;
+    if swap < 0 {
+        return false;
+    }
+    swap = standing - swap;
+    if swap <= 0 {
+        return true;
+    }
+
+    // Whether the threshold is met, assuming that the side to move stops capturing here
+    let mut result = true;
+    let mut side = !state.to_play;
+    loop {
+        let all_attackers = attackers(state, m.to, occupied);
+        let our_attackers = all_attackers.intersect_with(state.board.color_board(side));
+        let (pos, piece) = match least_valuable(state, our_attackers) {
+            Some(attacker) => attacker,
+            None => break,
+        };
+        result = !result;
+
+        if piece == Piece::King {
+            let their_attackers = all_attackers.intersect_with(state.board.color_board(!side));
+            return if their_attackers.any() {
+                !result
+            } else {
+                result
+            };
+        }
+
+        swap = piece.value() - swap;
+        if swap < result as Evaluation {
+            break;
+        }
+        occupied.clear(pos);
+        side = !side;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::fen::parse_fen;
+    use pewter_core::movegen::legal_moves;
+
+    fn see(fen: &str, lan: &str) -> Evaluation {
+        let state = parse_fen(fen).unwrap();
+        static_exchange_eval(&state, Move::from_long_algebraic(lan).unwrap())
+    }
+
+    #[test]
+    fn test_static_exchange_eval() {
+        let pawn = Piece::Pawn.value();
+        let knight = Piece::Knight.value();
+        let rook = Piece::Rook.value();
+
+        // An undefended pawn, and the same pawn defended by another pawn
+        assert_eq!(see("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"), pawn);
+        assert_eq!(
+            see("4k3/8/4p3/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"),
+            pawn - rook
+        );
+
+        // Doubled rooks win the pawn, as the defending rook can't recapture without losing itself
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), pawn);
+
+        // The knight takes a defended pawn, and is taken back by a pawn
+        assert_eq!(
+            see("4k3/2p5/3p4/8/4N3/8/8/4K3 w - - 0 1", "e4d6"),
+            pawn - knight
+        );
+
+        // The king can't recapture a piece that is still defended
+        assert_eq!(see("8/8/8/4k3/3p4/8/1B6/3RK3 w - - 0 1", "d1d4"), pawn);
+        assert_eq!(see("8/8/8/4k3/3p4/8/8/3RK3 w - - 0 1", "d1d4"), pawn - rook);
+
+        // Quiet moves onto an attacked square lose the piece
+        assert_eq!(see("4k3/8/4p3/8/8/8/8/3RK3 w - - 0 1", "d1d5"), -rook);
+        assert_eq!(see("4k3/8/8/8/8/8/8/3RK3 w - - 0 1", "d1d5"), 0);
+
+        // En-passant, and a promotion that can't be recaptured
+        assert_eq!(see("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1", "d5e6"), pawn);
+        assert_eq!(
+            see("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q"),
+            Piece::Queen.value() - pawn
+        );
+    }
+
+    #[test]
+    fn test_see_ge_matches_static_exchange_eval() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1",
+            "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+            "8/8/8/4k3/3p4/8/1B6/3RK3 w - - 0 1",
+            "r1bqk2r/ppp2ppp/2n5/3np3/1bB5/2N2N2/PPPP1PPP/R1BQK2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+
+        for fen in fens {
+            let state = parse_fen(fen).unwrap();
+            for m in legal_moves(&state).iter() {
+                let see = static_exchange_eval(&state, m);
+                for threshold in (-1500..=1500).step_by(25).chain([see - 1, see, see + 1]) {
+                    assert_eq!(
+                        see_ge(&state, m, threshold),
+                        see >= threshold,
+                        "{} {} (SEE {}) >= {}",
+                        fen,
+                        m,
+                        see,
+                        threshold
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Deliberately weakened play, so that the engine can give human opponents a chance.
+
+use rand::Rng;
+
+use pewter_core::Move;
+
+use super::Evaluation;
+
+/// The skill level at which the engine plays at full strength
+pub const MAX_SKILL_LEVEL: u8 = 20;
+
+/// How to weaken the engine's play for a given skill level.
+///
+/// Weakened searches are kept shallow, and rather than always playing the best move found the
+/// engine sometimes plays one of the other top few moves instead. Both get worse as the level
+/// drops.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Skill {
+    level: u8,
+}
+
+impl Skill {
+    pub fn new(level: u8) -> Self {
+        Self {
+            level: level.min(MAX_SKILL_LEVEL),
+        }
+    }
+
+    pub fn is_full_strength(self) -> bool {
+        self.level == MAX_SKILL_LEVEL
+    }
+
+    /// The depth that the root moves should be searched to before choosing between them
+    pub fn search_depth(self) -> u8 {
+        2 + self.level / 4
+    }
+
+    /// The number of the best root moves that might be chosen from
+    fn candidate_count(self) -> usize {
+        1 + (MAX_SKILL_LEVEL - self.level) as usize / 4
+    }
+
+    /// The probability of playing a move other than the best one
+    fn mistake_chance(self) -> f64 {
+        (MAX_SKILL_LEVEL - self.level) as f64 / 25.0
+    }
+
+    /// Pick a move from the given root moves, which must be sorted from best to worst.
+    ///
+    /// Returns None if there are no moves to pick from.
+    pub fn choose_move(self, ranked: &[(Move, Evaluation)], rng: &mut impl Rng) -> Option<Move> {
+        let (best, _) = *ranked.first()?;
+
+        let candidates = ranked.len().min(self.candidate_count());
+        if candidates > 1 && rng.gen_bool(self.mistake_chance()) {
+            Some(ranked[rng.gen_range(1..candidates)].0)
+        } else {
+            Some(best)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    #[test]
+    fn test_choose_move() {
+        let ranked = ["e2e4", "d2d4", "g1f3", "c2c4", "b2b3", "g2g4", "f2f3"]
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (Move::from_long_algebraic(m).unwrap(), -(i as Evaluation)))
+            .collect::<Vec<_>>();
+        let best = ranked[0].0;
+
+        let mistakes = |level: u8| {
+            let mut rng = SmallRng::seed_from_u64(1234);
+            (0..200)
+                .map(|_| Skill::new(level).choose_move(&ranked, &mut rng).unwrap())
+                .filter(|&m| m != best)
+                .count()
+        };
+
+        assert_eq!(mistakes(MAX_SKILL_LEVEL), 0);
+        assert!(mistakes(0) > 100, "{} mistakes at level 0", mistakes(0));
+        assert!(mistakes(10) < mistakes(0));
+
+        assert_eq!(
+            Skill::new(0).choose_move(&[], &mut SmallRng::seed_from_u64(1)),
+            None
+        );
+    }
+}
@@ -1,11 +1,17 @@
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasherDefault;
 
-use rand::prelude::*;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
-use pewter_core::{State, Move, zobrist::ZobristHash};
+use pewter_core::{movegen::legal_moves, zobrist::ZobristHash, Move, State};
 
 use super::Evaluation;
 
+/// The size of a table made by `TranspositionTable::new_empty`, in megabytes
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeType {
     Exact,
@@ -21,26 +27,89 @@ pub struct TranspositionEntry {
     pub depth: u8,
 }
 
+/// A snapshot of how a transposition table has been used, see `TranspositionTable::stats`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TableStats {
+    /// The number of probes that returned an entry
+    pub hits: usize,
+
+    /// The number of probes that found nothing usable
+    pub misses: usize,
+
+    /// The number of entries currently in the table
+    pub entries: usize,
+
+    /// A number between 0 and 1, representing how full the table is
+    pub load: f32,
+}
+
+/// Whether an entry for a position should be overwritten by a new result for the same position.
+///
+/// Deeper results are preferred as they are more accurate, but an exact result always replaces
+/// whatever was there before, as it is what the principal variation is rebuilt from.
+pub fn should_replace(
+    existing: &TranspositionEntry,
+    incoming_depth: u8,
+    incoming_type: NodeType,
+) -> bool {
+    incoming_type == NodeType::Exact || incoming_depth >= existing.depth
+}
+
+#[derive(Clone)]
 pub struct TranspositionTable {
     /// The maximum number of keys that should appear in the table
     max_size: usize,
 
-    storage: HashMap<ZobristHash, TranspositionEntry>,
-    hit_count: usize,
-    miss_count: usize,
+    // Hashed with fixed keys, so that which entry gets evicted only depends on `rng`
+    storage: HashMap<ZobristHash, TranspositionEntry, BuildHasherDefault<DefaultHasher>>,
+
+    /// Chooses the entries evicted when the table is full, see `set_seed`
+    rng: SmallRng,
+
+    // Probing doesn't otherwise need to mutate the table, so these are counted through a shared
+    // reference
+    hit_count: Cell<usize>,
+    miss_count: Cell<usize>,
 }
 
 impl TranspositionTable {
     pub fn new_empty() -> Self {
-        let max_size = 100_000_000;
+        Self::with_size_mb(DEFAULT_HASH_SIZE_MB)
+    }
+
+    /// An empty table taking up roughly the given number of megabytes once it is full
+    pub fn with_size_mb(size_mb: usize) -> Self {
+        Self::with_max_entries(Self::entries_for_size_mb(size_mb))
+    }
+
+    fn with_max_entries(max_size: usize) -> Self {
         Self {
-            storage: HashMap::with_capacity(max_size),
+            storage: HashMap::with_capacity_and_hasher(max_size, Default::default()),
             max_size,
-            hit_count: 0,
-            miss_count: 0,
+            rng: SmallRng::from_entropy(),
+            hit_count: Cell::new(0),
+            miss_count: Cell::new(0),
         }
     }
+
+    /// The number of entries that fit in the given number of megabytes, counting the key and the
+    /// control byte that the HashMap keeps alongside each entry.
+    pub fn entries_for_size_mb(size_mb: usize) -> usize {
+        let entry_size = std::mem::size_of::<(ZobristHash, TranspositionEntry)>() + 1;
+        std::cmp::max(1, size_mb * 1024 * 1024 / entry_size)
+    }
+
+    /// The most entries that this table holds before it starts evicting them
+    pub fn max_entries(&self) -> usize {
+        self.max_size
+    }
     
+    /// Reseed the choice of which entries to evict once the table is full, so that the table's
+    /// contents are reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
     /// Returns a number between 0 and 1, representing how full this table is
     pub fn load(&self) -> f32 {
         self.storage.len() as f32 / self.max_size as f32
@@ -48,26 +117,44 @@ impl TranspositionTable {
     
     /// Returns the fraction of cache hits as a number between 0 and 1.
     pub fn hit_rate(&self) -> f32 {
-       let total = self.hit_count + self.miss_count;
+       let total = self.hit_count.get() + self.miss_count.get();
        if total > 0 {
-           self.hit_count as f32 / total as f32
+           self.hit_count.get() as f32 / total as f32
        } else {
            0f32
        }
     }
+
+    /// The hit and miss counts of every probe since this table was created or last cleared,
+    /// along with how full it is
+    pub fn stats(&self) -> TableStats {
+        TableStats {
+            hits: self.hit_count.get(),
+            misses: self.miss_count.get(),
+            entries: self.storage.len(),
+            load: self.load(),
+        }
+    }
     
     /// Remove all entries from this table
     pub fn clear(&mut self) {
         self.storage.clear();
-        self.hit_count = 0;
-        self.miss_count = 0;
+        self.hit_count.set(0);
+        self.miss_count.set(0);
     }
     
-    /// Insert the given evaluation into this table
+    /// Insert the given evaluation into this table, unless `should_replace` says that the
+    /// existing entry for the position is worth more.
     pub fn insert(&mut self, state: &State, depth: u8, node_value: Evaluation, node_type: NodeType, m: Option<Move>) {
+        if let Some(existing) = self.storage.get(&state.zobrist) {
+            if !should_replace(existing, depth, node_type) {
+                return;
+            }
+        }
+
         while self.storage.len() >= self.max_size {
             // TODO: non-random eviction
-            let unlucky_key = thread_rng().gen_range(0..self.storage.len());
+            let unlucky_key = self.rng.gen_range(0..self.storage.len());
             let unlucky_key = *self.storage.keys().skip(unlucky_key).next().unwrap();
             self.storage.remove(&unlucky_key);
         }
@@ -81,6 +168,21 @@ impl TranspositionTable {
     }
     
     pub fn probe(&self, state: &State, min_depth: u8, alpha: Evaluation, beta: Evaluation) -> Option<TranspositionEntry> {
+        let entry = self.probe_inner(state, min_depth, alpha, beta);
+        match entry {
+            Some(_) => self.hit_count.set(self.hit_count.get() + 1),
+            None => self.miss_count.set(self.miss_count.get() + 1),
+        }
+        entry
+    }
+
+    fn probe_inner(
+        &self,
+        state: &State,
+        min_depth: u8,
+        alpha: Evaluation,
+        beta: Evaluation,
+    ) -> Option<TranspositionEntry> {
         let entry = self.storage.get(&state.zobrist)?;
         
         // If the stored evaluation didn't look as far ahead as we need, this
@@ -115,4 +217,180 @@ impl TranspositionTable {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Reconstruct the principal variation from the given state by repeatedly following the best
+    /// move of Exact entries, up to at most `max_len` moves.
+    ///
+    /// Bound entries don't know the true best move, so the line ends at the first position that
+    /// doesn't have an Exact entry. It also ends if it would revisit a position, or if the stored
+    /// move isn't legal (which can happen after a hash collision).
+    pub fn principal_variation(&self, state: &State, max_len: usize) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut visited = HashSet::new();
+        let mut state = *state;
+
+        while pv.len() < max_len && visited.insert(state.zobrist) {
+            let m = match self.storage.get(&state.zobrist) {
+                Some(TranspositionEntry {
+                    node_type: NodeType::Exact,
+                    m: Some(m),
+                    ..
+                }) => *m,
+                _ => break,
+            };
+
+            if !legal_moves(&state).iter().any(|legal| legal == m) {
+                break;
+            }
+
+            pv.push(m);
+            state = state.apply_move(m);
+        }
+
+        pv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pewter_core::io::fen::parse_fen;
+
+    fn lan(m: &str) -> Move {
+        Move::from_long_algebraic(m).unwrap()
+    }
+
+    /// Insert an Exact entry with the given best move for every position along the line
+    fn insert_line(t: &mut TranspositionTable, start: &State, line: &[Move]) {
+        let mut state = *start;
+        for &m in line {
+            t.insert(&state, 1, 0, NodeType::Exact, Some(m));
+            state = state.apply_move(m);
+        }
+    }
+
+    #[test]
+    fn test_principal_variation_follows_exact_entries() {
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let line = ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"].map(lan);
+
+        let mut t = TranspositionTable::new_empty();
+        insert_line(&mut t, &start, &line);
+
+        assert_eq!(t.principal_variation(&start, 10), line);
+        assert_eq!(t.principal_variation(&start, 3), line[..3]);
+
+        // A bound entry part way along the line cuts the variation short
+        let after_two = start.apply_move(line[0]).apply_move(line[1]);
+        t.insert(&after_two, 1, 0, NodeType::LowerBound, Some(line[2]));
+        assert_eq!(t.principal_variation(&start, 10), line[..2]);
+    }
+
+    fn entry(node_type: NodeType, depth: u8) -> TranspositionEntry {
+        TranspositionEntry {
+            node_type,
+            node_value: 0,
+            m: None,
+            depth,
+        }
+    }
+
+    #[test]
+    fn test_should_replace() {
+        use NodeType::*;
+
+        // Bounds only replace entries that looked no further ahead than they did
+        for &existing_type in &[Exact, LowerBound, UpperBound] {
+            let existing = entry(existing_type, 4);
+            for &incoming_type in &[LowerBound, UpperBound] {
+                assert!(!should_replace(&existing, 3, incoming_type));
+                assert!(should_replace(&existing, 4, incoming_type));
+                assert!(should_replace(&existing, 5, incoming_type));
+            }
+
+            // Exact results replace anything, however shallow
+            assert!(should_replace(&existing, 0, Exact));
+            assert!(should_replace(&existing, 5, Exact));
+        }
+    }
+
+    #[test]
+    fn test_insert_prefers_deeper_entries() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut t = TranspositionTable::new_empty();
+
+        t.insert(&state, 5, 10, NodeType::LowerBound, Some(lan("e2e4")));
+        t.insert(&state, 2, 20, NodeType::UpperBound, Some(lan("d2d4")));
+        let stored = t.probe(&state, 0, 100, 0).unwrap();
+        assert_eq!((stored.depth, stored.node_value), (5, 10));
+
+        t.insert(&state, 1, 30, NodeType::Exact, Some(lan("c2c4")));
+        let stored = t.probe(&state, 0, 100, 0).unwrap();
+        assert_eq!((stored.depth, stored.node_type), (1, NodeType::Exact));
+        assert_eq!(stored.m, Some(lan("c2c4")));
+
+        // Too shallow for the probe, and a position that isn't in the table at all
+        assert!(t.probe(&state, 2, 100, 0).is_none());
+        assert!(t.probe(&state.apply_move(lan("e2e4")), 0, 100, 0).is_none());
+
+        assert_eq!(
+            t.stats(),
+            TableStats {
+                hits: 2,
+                misses: 2,
+                entries: 1,
+                load: t.load(),
+            }
+        );
+
+        t.clear();
+        assert_eq!(
+            (t.stats().hits, t.stats().misses, t.stats().entries),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_table_size() {
+        let mut t = TranspositionTable::with_size_mb(1);
+        assert_eq!(t.max_entries(), TranspositionTable::entries_for_size_mb(1));
+        assert_eq!(
+            TranspositionTable::with_size_mb(2).max_entries(),
+            2 * t.max_entries()
+        );
+        assert_eq!(TranspositionTable::entries_for_size_mb(0), 1);
+        assert_eq!(
+            TranspositionTable::new_empty().max_entries(),
+            TranspositionTable::entries_for_size_mb(DEFAULT_HASH_SIZE_MB)
+        );
+
+        // Filling the table past its size evicts entries rather than growing it, here with the
+        // positions along random games of up to 100 plies
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut state = start;
+        let mut rng = SmallRng::seed_from_u64(0);
+        for i in 0..t.max_entries() + 100 {
+            t.insert(&state, 1, 0, NodeType::Exact, None);
+
+            let moves = legal_moves(&state).sorted_vec();
+            state = if moves.is_empty() || i % 100 == 99 {
+                start
+            } else {
+                state.apply_move(moves[rng.gen_range(0..moves.len())])
+            };
+        }
+        assert!(t.stats().entries <= t.max_entries());
+    }
+
+    #[test]
+    fn test_principal_variation_stops_at_cycles() {
+        let start = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"].map(lan);
+
+        let mut t = TranspositionTable::new_empty();
+        insert_line(&mut t, &start, &shuffle);
+
+        assert_eq!(t.principal_variation(&start, 100), shuffle);
+    }
+}
@@ -1,3 +1,8 @@
 pub mod engine;
+pub mod logging;
+pub mod uci;
 
-pub use crate::engine::Engine;
\ No newline at end of file
+#[cfg(feature = "uci_server")]
+pub mod uci_server;
+
+pub use crate::engine::Engine;
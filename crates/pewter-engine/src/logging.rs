@@ -0,0 +1,115 @@
+//! Where the engine's logs are written, configured through environment variables so that it can be
+//! changed for engines launched by a GUI.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::prelude::*;
+
+/// The most verbose level to log, eg "info", or "off" to not log at all
+pub const LEVEL_VAR: &str = "PEWTER_LOG";
+
+/// The directory to write log files to, which can be set to be empty to not write any
+pub const DIRECTORY_VAR: &str = "PEWTER_LOG_DIR";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogConfig {
+    /// The most verbose level that is logged
+    pub level: LevelFilter,
+
+    /// The directory that hourly log files are written to, or None to not write any
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::DEBUG,
+            directory: Some(PathBuf::from("./logs")),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Read the configuration from `LEVEL_VAR` and `DIRECTORY_VAR`, using the defaults for any
+    /// that aren't set
+    pub fn from_env() -> Result<Self> {
+        let level = std::env::var(LEVEL_VAR).ok();
+        let directory = std::env::var(DIRECTORY_VAR).ok();
+        Self::from_vars(level.as_deref(), directory.as_deref())
+    }
+
+    /// The configuration given by the values of `LEVEL_VAR` and `DIRECTORY_VAR`
+    pub fn from_vars(level: Option<&str>, directory: Option<&str>) -> Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(level) = level {
+            config.level = level
+                .parse()
+                .map_err(|_| anyhow!("Invalid {} level \"{}\"", LEVEL_VAR, level))?;
+        }
+
+        if let Some(directory) = directory {
+            config.directory = match directory {
+                "" => None,
+                dir => Some(PathBuf::from(dir)),
+            };
+        }
+
+        Ok(config)
+    }
+
+    /// Whether anything will be written to log files
+    pub fn file_sink_enabled(&self) -> bool {
+        self.directory.is_some() && self.level != LevelFilter::OFF
+    }
+}
+
+/// Install the global tracing subscriber for the given configuration.
+///
+/// Panics if a global subscriber has already been installed.
+pub fn init_logging(config: &LogConfig) {
+    // Skip creating the appender entirely when it wouldn't be written to, as it creates the
+    // directory
+    let file_dir = config
+        .directory
+        .as_ref()
+        .filter(|_| config.file_sink_enabled());
+    let file_layer = file_dir.map(|dir| {
+        let file = tracing_appender::rolling::hourly(dir, "pewter.log");
+        tracing_subscriber::fmt::layer()
+            .with_writer(file)
+            .with_filter(config.level)
+    });
+
+    tracing_subscriber::registry().with(file_layer).init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_config_from_vars() {
+        assert_eq!(
+            LogConfig::from_vars(None, None).unwrap(),
+            LogConfig::default()
+        );
+        assert!(LogConfig::default().file_sink_enabled());
+
+        let quiet = LogConfig::from_vars(Some("warn"), Some("/tmp/pewter")).unwrap();
+        assert_eq!(quiet.level, LevelFilter::WARN);
+        assert_eq!(quiet.directory, Some(PathBuf::from("/tmp/pewter")));
+        assert!(quiet.file_sink_enabled());
+
+        // Either turning logging off or dropping the directory disables the file
+        let off = LogConfig::from_vars(Some("off"), None).unwrap();
+        assert!(!off.file_sink_enabled());
+        let no_file = LogConfig::from_vars(None, Some("")).unwrap();
+        assert_eq!(no_file.directory, None);
+        assert!(!no_file.file_sink_enabled());
+
+        assert!(LogConfig::from_vars(Some("loud"), None).is_err());
+    }
+}
@@ -0,0 +1,503 @@
+//! Drives an `EngineServer` from a `UciInterface`
+
+use std::time::Duration;
+
+use anyhow::Result;
+use crossbeam_channel::{select, Sender};
+
+use pewter_core::io::uci::*;
+use pewter_core::state::MoveSequenceError;
+use pewter_core::zobrist::ZobristHash;
+use pewter_core::{Move, State};
+
+use crate::engine::engine_server::{BestMove, EngineServer};
+use crate::engine::score::Score;
+use crate::engine::skill::MAX_SKILL_LEVEL;
+use crate::engine::{DrawClaim, EngineConfig, PerfInfo, RootVerdict};
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    debug: bool,
+
+    /// The configuration that the engine should be using, updated by each option
+    engine_config: EngineConfig,
+}
+
+impl Options {
+    pub fn engine_config(&self) -> &EngineConfig {
+        &self.engine_config
+    }
+
+    /// Whether the engine should send "info string"s describing what it's doing
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+}
+
+/// The largest "Move Overhead" that can be configured, in milliseconds
+const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+/// The largest transposition table that can be configured with "Hash", in megabytes
+const MAX_HASH_SIZE_MB: usize = 4096;
+
+fn check_option(name: &str, default: bool) -> OptionMessage {
+    OptionMessage {
+        option_name: name.to_string(),
+        option_type: OptionType::Check,
+        default: Some(default.to_string()),
+        min: None,
+        max: None,
+        combo_options: None,
+    }
+}
+
+/// The value of a check option, which some GUIs send capitalised, eg "True"
+fn parse_check(value: &str) -> Result<bool, ()> {
+    if value.eq_ignore_ascii_case("true") {
+        Ok(true)
+    } else if value.eq_ignore_ascii_case("false") {
+        Ok(false)
+    } else {
+        Err(())
+    }
+}
+
+// TODO: implementing this trait might be better handled by a macro
+impl UciOptions for Options {
+    type SetOptionError = ();
+
+    fn all_options() -> Vec<OptionMessage> {
+        let defaults = EngineConfig::default();
+        vec![
+            OptionMessage {
+                option_name: "debug".to_string(),
+                option_type: OptionType::Check,
+                default: Some("off".to_string()),
+                min: None,
+                max: None,
+                combo_options: None,
+            },
+            OptionMessage {
+                option_name: "Hash".to_string(),
+                option_type: OptionType::Spin,
+                default: Some(defaults.hash_size_mb.to_string()),
+                min: Some(1),
+                max: Some(MAX_HASH_SIZE_MB as i32),
+                combo_options: None,
+            },
+            check_option("OwnBook", defaults.own_book),
+            check_option("UCI_AnalyseMode", defaults.analyse_mode),
+            OptionMessage {
+                option_name: "Skill Level".to_string(),
+                option_type: OptionType::Spin,
+                default: Some(defaults.skill_level.to_string()),
+                min: Some(0),
+                max: Some(MAX_SKILL_LEVEL as i32),
+                combo_options: None,
+            },
+            OptionMessage {
+                option_name: "Move Overhead".to_string(),
+                option_type: OptionType::Spin,
+                default: Some(defaults.move_overhead.as_millis().to_string()),
+                min: Some(0),
+                max: Some(MAX_MOVE_OVERHEAD_MS as i32),
+                combo_options: None,
+            },
+        ]
+    }
+
+    fn set_value(&mut self, option_name: &str, value: &str) -> Result<(), Self::SetOptionError> {
+        match option_name {
+            "debug" => match value {
+                "on" => self.debug = true,
+                "off" => self.debug = false,
+                _ => Err(())?,
+            },
+            "Hash" => match value.parse() {
+                Ok(size_mb) if (1..=MAX_HASH_SIZE_MB).contains(&size_mb) => {
+                    self.engine_config.hash_size_mb = size_mb
+                }
+                _ => Err(())?,
+            },
+            "OwnBook" => self.engine_config.own_book = parse_check(value)?,
+            "UCI_AnalyseMode" => self.engine_config.analyse_mode = parse_check(value)?,
+            "Skill Level" => match value.parse() {
+                Ok(level) if level <= MAX_SKILL_LEVEL => self.engine_config.skill_level = level,
+                _ => Err(())?,
+            },
+            "Move Overhead" => match value.parse() {
+                Ok(ms) if ms <= MAX_MOVE_OVERHEAD_MS => {
+                    self.engine_config.move_overhead = Duration::from_millis(ms)
+                }
+                _ => Err(())?,
+            },
+            _ => Err(())?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Respond to UCI commands using the given engine, until a Quit command is received
+pub fn run_session(uci: UciInterface<Options>, mut engine: EngineServer) -> Result<()> {
+    loop {
+        select! {
+            recv(uci.rx) -> uci_msg => if handle_uci_cmd(uci_msg?, &uci, &mut engine)? {
+                break Ok(());
+            },
+            recv(engine.perf_rx) -> perf => handle_engine_perf(perf?, &uci.tx)?,
+            recv(engine.best_move_rx) -> m => {
+                // The final report of the search has to reach the GUI before the best move does
+                for perf in engine.perf_rx.try_iter() {
+                    handle_engine_perf(perf, &uci.tx)?;
+                }
+                handle_engine_best_move(m?, &uci.tx)?
+            },
+        }
+    }
+}
+
+fn handle_uci_cmd(
+    msg: UciCommand,
+    uci: &UciInterface<Options>,
+    engine: &mut EngineServer,
+) -> Result<bool> {
+    let uci_tx = &uci.tx;
+    match msg {
+        UciCommand::Uci => {
+            uci_tx.send(UciMessage::Id(EngineId::Name("pewter".to_string())))?;
+            uci_tx.send(UciMessage::Id(EngineId::Author("Joe Roberts".to_string())))?;
+            for option in Options::all_options() {
+                uci_tx.send(UciMessage::Option(option))?;
+            }
+            uci_tx.send(UciMessage::UciOk)?;
+        }
+        UciCommand::SetOption { option_name, value } => {
+            let mut opts = uci.opts.write().expect("UCI options lock was poisoned");
+            let value = value.unwrap_or_default();
+            if opts.set_value(&option_name, &value).is_ok() {
+                engine.configure(opts.engine_config().clone())?;
+                engine.set_debug(opts.debug())?;
+            } else {
+                tracing::warn!("Ignoring bad option \"{}\" = \"{}\"", option_name, value);
+            }
+        }
+        UciCommand::Debug(debug) => {
+            let mut opts = uci.opts.write().expect("UCI options lock was poisoned");
+            opts.debug = debug;
+            engine.set_debug(debug)?;
+        }
+        UciCommand::IsReady => uci_tx.send(UciMessage::ReadyOk)?,
+        UciCommand::Quit => {
+            tracing::info!("Received quit command, shutting down");
+            return Ok(true);
+        }
+        UciCommand::Position { position, moves } => {
+            // Parse the position, and resolve any moves passed in. A bad position is ignored,
+            // leaving the engine with whichever one it had before.
+            let start = match pewter_core::io::fen::parse_fen(position.fen()) {
+                Ok(start) => start,
+                Err(e) => {
+                    tracing::warn!(
+                        "Ignoring position with bad FEN \"{}\": {:?}",
+                        position.fen(),
+                        e
+                    );
+                    return Ok(false);
+                }
+            };
+            let (state, game_history) = match apply_position_moves(start, &moves) {
+                Ok(resolved) => resolved,
+                Err(MoveSequenceError { index, error }) => {
+                    tracing::warn!(
+                        "Ignoring position whose move {} ({}) can't be played: {}",
+                        index,
+                        moves[index],
+                        error
+                    );
+                    return Ok(false);
+                }
+            };
+
+            tracing::info!(
+                "Setting position to \"{}\"",
+                pewter_core::io::fen::format_fen(&state)
+            );
+            engine.set_state(state, game_history)?;
+        }
+        UciCommand::Go(go) => {
+            let timings = crate::engine::Timings {
+                white_remaining: go.white_time,
+                black_remaining: go.black_time,
+                white_increment: go.white_increment.unwrap_or(Duration::ZERO),
+                black_increment: go.black_increment.unwrap_or(Duration::ZERO),
+                move_time: go.move_time,
+            };
+
+            engine.begin_search(
+                go.infinite,
+                go.depth,
+                go.nodes,
+                go.mate,
+                Some(timings),
+                go.search_moves,
+            )?;
+        }
+        UciCommand::UciNewGame => engine.new_game()?,
+        UciCommand::Stop => engine.stop_search()?,
+        _ => (),
+    }
+
+    Ok(false)
+}
+
+/// Play the moves of a "position" command from its starting position, returning the final state
+/// along with the hashes of every position before it, oldest first.
+fn apply_position_moves(
+    start: State,
+    moves: &[Move],
+) -> Result<(State, Vec<ZobristHash>), MoveSequenceError> {
+    let mut state = start;
+    let mut game_history = Vec::with_capacity(moves.len());
+    for (index, &m) in moves.iter().enumerate() {
+        game_history.push(state.zobrist);
+        state = state
+            .apply_move_checked(m)
+            .map_err(|error| MoveSequenceError { index, error })?;
+    }
+
+    Ok((state, game_history))
+}
+
+/// The "hashfull" of an info message, which is in permille, for a transposition table load
+fn hash_full(transposition_load: f32) -> u16 {
+    (transposition_load * 1000.0) as u16
+}
+
+fn handle_engine_perf(msg: PerfInfo, uci_tx: &Sender<UciMessage>) -> Result<()> {
+    if let Some(note) = msg.info_string {
+        uci_tx.send(UciMessage::Info(InfoMessage {
+            string: Some(note),
+            ..InfoMessage::default()
+        }))?;
+        return Ok(());
+    }
+
+    uci_tx.send(UciMessage::Info(InfoMessage {
+        nodes: Some(msg.nodes),
+        nodes_per_second: Some(msg.nodes_per_second as u64),
+        hash_full: Some(hash_full(msg.transposition_load)),
+        curr_move: msg.current_move.map(|(m, _)| m),
+        curr_move_number: msg.current_move.map(|(_, number)| number),
+        ..InfoMessage::default()
+    }))?;
+
+    if let Some(report) = msg.root_report {
+        uci_tx.send(UciMessage::Info(InfoMessage {
+            score: Some(Score::from_evaluation(report.score).into()),
+            principal_variation: Some(report.pv).filter(|pv| !pv.is_empty()),
+            ..InfoMessage::default()
+        }))?;
+
+        let mut notes = Vec::new();
+        match report.verdict {
+            RootVerdict::Drawn => notes.push("position is a forced draw"),
+            RootVerdict::Lost => notes.push("position is lost"),
+            RootVerdict::Won | RootVerdict::Undecided => (),
+        }
+        match report.draw_claim {
+            Some(DrawClaim::FiftyMove) => notes.push("draw claim available by the fifty move rule"),
+            Some(DrawClaim::ThreefoldRepetition) => {
+                notes.push("draw claim available by threefold repetition")
+            }
+            None => (),
+        }
+
+        if !notes.is_empty() {
+            uci_tx.send(UciMessage::Info(InfoMessage {
+                string: Some(notes.join(", ")),
+                ..InfoMessage::default()
+            }))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_engine_best_move(best: BestMove, uci_tx: &Sender<UciMessage>) -> Result<()> {
+    uci_tx.send(UciMessage::BestMove {
+        best_move: best.best_move,
+        ponder_move: best.ponder_move,
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crossbeam_channel::unbounded;
+
+    #[test]
+    fn test_hash_full() {
+        assert_eq!(hash_full(0.0), 0);
+        assert_eq!(hash_full(0.5), 500);
+        assert_eq!(hash_full(1.0), 1000);
+    }
+
+    #[test]
+    fn test_check_options_ignore_case() {
+        let mut opts = Options::default();
+        for (value, expected) in [("true", true), ("False", false), ("TRUE", true)] {
+            assert_eq!(opts.set_value("OwnBook", value), Ok(()), "{}", value);
+            assert_eq!(opts.engine_config().own_book, expected, "{}", value);
+        }
+
+        assert_eq!(opts.set_value("UCI_AnalyseMode", "yes"), Err(()));
+        assert_eq!(opts.set_value("UCI_AnalyseMode", ""), Err(()));
+        assert_eq!(
+            opts.engine_config().analyse_mode,
+            EngineConfig::default().analyse_mode
+        );
+    }
+
+    #[test]
+    fn test_hash_option() {
+        let mut opts = Options::default();
+        assert_eq!(opts.set_value("Hash", "64"), Ok(()));
+        assert_eq!(opts.engine_config().hash_size_mb, 64);
+
+        for bad in ["0", "100000", "lots"] {
+            assert_eq!(opts.set_value("Hash", bad), Err(()), "{}", bad);
+        }
+        assert_eq!(opts.engine_config().hash_size_mb, 64);
+    }
+
+    #[test]
+    fn test_position_moves_report_repetition_claims() {
+        let uci = UciInterface::<Options>::with_io(Cursor::new(Vec::new()), std::io::sink())
+            .expect("Expected to be able to start the UCI interface");
+        let mut engine = EngineServer::startup_with_config(EngineConfig {
+            book_path: None,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+
+        let mut info_strings = |position: &str| {
+            for cmd in [position, "go depth 2"] {
+                handle_uci_cmd(parse_command(cmd).unwrap(), &uci, &mut engine).unwrap();
+            }
+
+            engine
+                .best_move_rx
+                .recv_timeout(Duration::from_secs(60))
+                .expect("Expected the search to finish");
+            let (info_tx, info_rx) = unbounded();
+            for perf in engine.perf_rx.try_iter() {
+                handle_engine_perf(perf, &info_tx).unwrap();
+            }
+            drop(info_tx);
+
+            info_rx
+                .iter()
+                .filter_map(|msg| match msg {
+                    UciMessage::Info(info) => info.string,
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The knights return home twice, so the starting position is on the board for the third time
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+        let claims = |notes: Vec<String>| {
+            notes
+                .iter()
+                .any(|note| note.contains("draw claim available by threefold repetition"))
+        };
+        assert!(!claims(info_strings(&format!(
+            "position startpos moves {}",
+            shuffle
+        ))));
+        assert!(claims(info_strings(&format!(
+            "position startpos moves {} {}",
+            shuffle, shuffle
+        ))));
+    }
+
+    #[test]
+    fn test_session_survives_illegal_position_moves() {
+        let uci = UciInterface::<Options>::with_io(Cursor::new(Vec::new()), std::io::sink())
+            .expect("Expected to be able to start the UCI interface");
+        let mut engine = EngineServer::startup_with_config(EngineConfig {
+            book_path: None,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+
+        let commands = [
+            "position startpos moves e2e4",
+            "position startpos moves e2e4 e2e4",
+            "position fen this is not a fen",
+            "go depth 2",
+        ];
+        for cmd in commands {
+            let quit = handle_uci_cmd(parse_command(cmd).unwrap(), &uci, &mut engine)
+                .expect("Expected the session to carry on");
+            assert!(!quit, "{}", cmd);
+        }
+
+        // The search runs from the last good position, where it's black to play
+        let best = engine
+            .best_move_rx
+            .recv_timeout(Duration::from_secs(60))
+            .expect("Expected the search to finish");
+        let state = pewter_core::io::fen::parse_fen(Position::StartPos.fen())
+            .unwrap()
+            .apply_move(Move::from_long_algebraic("e2e4").unwrap());
+        assert!(pewter_core::movegen::legal_moves(&state)
+            .iter()
+            .any(|m| m == best.best_move));
+    }
+
+    #[test]
+    fn test_debug_command_toggles_info_strings() {
+        let uci = UciInterface::<Options>::with_io(Cursor::new(Vec::new()), std::io::sink())
+            .expect("Expected to be able to start the UCI interface");
+        let mut engine = EngineServer::startup_with_config(EngineConfig {
+            book_path: None,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+
+        let mut run = |commands: &[&str]| {
+            for cmd in commands {
+                handle_uci_cmd(parse_command(cmd).unwrap(), &uci, &mut engine).unwrap();
+            }
+
+            let best_move = engine
+                .best_move_rx
+                .recv_timeout(Duration::from_secs(60))
+                .expect("Expected the search to finish");
+            let (info_tx, info_rx) = unbounded();
+            for perf in engine.perf_rx.try_iter() {
+                handle_engine_perf(perf, &info_tx).unwrap();
+            }
+            handle_engine_best_move(best_move, &info_tx).unwrap();
+            drop(info_tx);
+
+            info_rx
+                .iter()
+                .filter(|msg| matches!(msg, UciMessage::Info(info) if info.string.is_some()))
+                .count()
+        };
+
+        let position =
+            "position fen r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        assert!(run(&["debug on", position, "go depth 3"]) > 0);
+        assert!(uci.opts.read().unwrap().debug());
+        assert_eq!(run(&["debug off", position, "go depth 3"]), 0);
+    }
+}
@@ -0,0 +1,76 @@
+//! Serves the UCI protocol over TCP, so that the engine can be driven remotely
+
+use std::io::BufReader;
+use std::net::{Shutdown, TcpListener, TcpStream};
+
+use anyhow::Result;
+
+use pewter_core::io::uci::UciInterface;
+
+use crate::engine::engine_server::EngineServer;
+use crate::uci::{run_session, Options};
+
+/// Accept connections forever, running an independent engine for each one
+pub fn serve(listener: TcpListener) -> Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+
+        std::thread::Builder::new()
+            .name(format!("UCI connection {}", peer))
+            .spawn(move || {
+                tracing::info!(%peer, "Accepted UCI connection");
+                let res = serve_connection(stream);
+                tracing::info!(%peer, ?res, "Closed UCI connection");
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Run a single UCI session over the given stream, returning once the client quits or disconnects
+pub fn serve_connection(stream: TcpStream) -> Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let uci = UciInterface::<Options>::with_io(reader, stream.try_clone()?)?;
+    let engine = EngineServer::startup()?;
+
+    let res = run_session(uci, engine);
+
+    // Unblocks the UCI reader thread if the session ended with an explicit quit
+    let _ = stream.shutdown(Shutdown::Both);
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, Write};
+    use std::time::Duration;
+
+    #[test]
+    fn test_uci_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        writeln!(stream, "uci").unwrap();
+
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut received = Vec::new();
+        while !received.iter().any(|l| l == "uciok") {
+            let line = lines
+                .next()
+                .expect("Expected the connection to stay open")
+                .expect("Expected to receive a response");
+            received.push(line);
+        }
+
+        writeln!(stream, "quit").unwrap();
+    }
+}
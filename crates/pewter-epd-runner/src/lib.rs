@@ -0,0 +1,142 @@
+//! Runs the engine over each position of an EPD test suite, checking the moves it chooses against
+//! the "bm" (best move) and "am" (avoid move) operations of each record.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use pewter_core::io::epd::EpdRecord;
+use pewter_core::io::pgn::format_san_move;
+use pewter_core::{Move, State};
+use pewter_engine::engine::{search::SearchControls, Timings};
+use pewter_engine::Engine;
+
+/// How long the engine is given to search each position
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunLimits {
+    /// The time to spend on each position, or None to only stop at `depth`
+    pub move_time: Option<Duration>,
+
+    /// The depth to search each position to, or None to only stop at `move_time`
+    pub depth: Option<u8>,
+}
+
+/// The outcome of running the engine on a single position of a suite
+#[derive(Clone, Debug)]
+pub struct PositionResult {
+    pub id: Option<String>,
+    pub state: State,
+
+    /// The move the engine chose
+    pub chosen: Move,
+
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+
+    /// How long the engine took to choose its move
+    pub elapsed: Duration,
+}
+
+impl PositionResult {
+    /// Whether the chosen move is one of the best moves, and none of the moves to avoid.
+    ///
+    /// A record with neither operation never passes, as there's nothing to check against.
+    pub fn passed(&self) -> bool {
+        let avoided = !self.avoid_moves.contains(&self.chosen);
+        if self.best_moves.is_empty() {
+            !self.avoid_moves.is_empty() && avoided
+        } else {
+            self.best_moves.contains(&self.chosen) && avoided
+        }
+    }
+}
+
+impl std::fmt::Display for PositionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let san = |moves: &[Move]| {
+            moves
+                .iter()
+                .map(|&m| format_san_move(&self.state, m))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        write!(
+            f,
+            "{}: {} played {}",
+            self.id.as_deref().unwrap_or("<no id>"),
+            if self.passed() { "PASS" } else { "FAIL" },
+            format_san_move(&self.state, self.chosen),
+        )?;
+        if !self.best_moves.is_empty() {
+            write!(f, ", bm {}", san(&self.best_moves))?;
+        }
+        if !self.avoid_moves.is_empty() {
+            write!(f, ", am {}", san(&self.avoid_moves))?;
+        }
+        write!(f, " ({}ms)", self.elapsed.as_millis())
+    }
+}
+
+/// Search a single position of a suite with the given engine.
+///
+/// The opening book is never consulted. Searches without a move time aren't time managed, so give
+/// the same result on every run.
+pub fn run_position(
+    engine: &mut Engine,
+    record: &EpdRecord,
+    limits: RunLimits,
+) -> Result<PositionResult> {
+    let best_moves = record.best_moves()?;
+    let avoid_moves = record.avoid_moves()?;
+
+    let timings = limits.move_time.map(|move_time| Timings {
+        move_time: Some(move_time),
+        ..Timings::default()
+    });
+    // Each position is unrelated to the last, and shouldn't depend on the order they're run in
+    engine.new_game();
+    engine.set_deterministic(limits.move_time.is_none());
+    engine.set_board_state(record.state);
+
+    let start = Instant::now();
+    let chosen = engine.search_best_move(
+        false,
+        limits.depth,
+        None,
+        timings,
+        false,
+        SearchControls {
+            stop: Arc::new(AtomicBool::new(false)),
+            perf_info: None,
+            ..SearchControls::default()
+        },
+    )?;
+
+    Ok(PositionResult {
+        id: record.id().map(str::to_string),
+        state: record.state,
+        chosen,
+        best_moves,
+        avoid_moves,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Search every position of a suite in turn, calling `on_result` as each one finishes
+pub fn run_suite(
+    records: &[EpdRecord],
+    limits: RunLimits,
+    mut on_result: impl FnMut(&PositionResult),
+) -> Result<Vec<PositionResult>> {
+    let mut engine = Engine::new();
+    records
+        .iter()
+        .map(|record| {
+            let result = run_position(&mut engine, record, limits)?;
+            on_result(&result);
+            Ok(result)
+        })
+        .collect()
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use pewter_core::io::epd::parse_multi_epd;
+use pewter_epd_runner::{run_suite, RunLimits};
+
+/// Run the engine over an EPD test suite, reporting which positions it found the best move in
+#[derive(Parser, Debug)]
+#[clap(about, version, author, name = "epd_runner")]
+struct Args {
+    /// EPD file to read the positions from
+    #[clap(long)]
+    epd: PathBuf,
+
+    /// Milliseconds to search each position for, defaulting to 1000 unless --depth is set
+    #[clap(long)]
+    move_time_ms: Option<u64>,
+
+    /// Depth to search each position to
+    #[clap(long)]
+    depth: Option<u8>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let move_time = match (args.move_time_ms, args.depth) {
+        (Some(ms), _) => Some(Duration::from_millis(ms)),
+        (None, Some(_)) => None,
+        (None, None) => Some(Duration::from_millis(1000)),
+    };
+    let limits = RunLimits {
+        move_time,
+        depth: args.depth,
+    };
+
+    let records = parse_multi_epd(&std::fs::read_to_string(&args.epd)?)
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let results = run_suite(&records, limits, |result| println!("{}", result))?;
+
+    let passed = results.iter().filter(|r| r.passed()).count();
+    println!("Passed {}/{}", passed, results.len());
+
+    Ok(())
+}
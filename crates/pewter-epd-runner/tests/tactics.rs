@@ -0,0 +1,27 @@
+use pewter_core::io::epd::parse_multi_epd;
+use pewter_epd_runner::{run_suite, RunLimits};
+
+const EASY_TACTICS: &str = "\
+6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank mate\";
+4k3/8/8/3q4/8/8/3R4/4K3 w - - bm Rxd5; id \"hanging queen\";
+4k3/8/2p5/3p4/8/8/8/3QK3 w - - am Qxd5; id \"defended pawn\";
+";
+
+#[test]
+fn test_solves_easy_tactics() {
+    let records = parse_multi_epd(EASY_TACTICS)
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let limits = RunLimits {
+        move_time: None,
+        depth: Some(4),
+    };
+
+    let results = run_suite(&records, limits, |_| ()).unwrap();
+
+    assert_eq!(results.len(), 3);
+    for result in results {
+        assert!(result.passed(), "{}", result);
+    }
+}
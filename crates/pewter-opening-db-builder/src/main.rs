@@ -86,14 +86,14 @@ async fn get_all_games(cache_dir: &Path) -> Result<Vec<Game>> {
     Ok(all_games)
 }
 
-fn build_db_from_games(games: &[Game]) -> OpeningDb {
+fn build_db_from_games(games: &[Game], max_ply: Option<usize>) -> OpeningDb {
     println!("Building single DB from {} games", games.len());
     let mut db = games
         .par_iter()
         .fold(
             || OpeningDb::new_empty(),
             |mut db, game| {
-                db.add_game(game);
+                db.add_game(game, max_ply);
                 db
             },
         )
@@ -135,6 +135,11 @@ struct Args {
     #[clap(long)]
     db_path: PathBuf,
 
+    /// Only record the first this many plies of each game, so that the book doesn't reach far
+    /// past the opening
+    #[clap(long)]
+    max_ply: Option<usize>,
+
     /// Just load the database from the given path, don't rebuild it from scratch
     #[clap(long)]
     no_build: bool,
@@ -155,7 +160,7 @@ async fn main() -> Result<()> {
             .pgn_cache
             .expect("PGN cache directory required when building DB");
         let all_games = get_all_games(&cache_dir).await?;
-        let db = build_db_from_games(&all_games);
+        let db = build_db_from_games(&all_games, args.max_ply);
         save_db_to_disk(&db, &args.db_path).await?;
         db
     };
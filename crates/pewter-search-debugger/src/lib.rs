@@ -0,0 +1,185 @@
+//! Searches positions outside of the UCI server, streaming the result of each iteration of the
+//! search so that changes to the engine's behaviour in specific positions can be inspected.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use pewter_core::io::epd::parse_epd;
+use pewter_core::io::fen::parse_fen;
+use pewter_core::{Move, State};
+use pewter_engine::engine::eval::Evaluation;
+use pewter_engine::engine::score::Score;
+use pewter_engine::engine::search::SearchInfo;
+use pewter_engine::engine::SearchLimits;
+use pewter_engine::Engine;
+
+/// A position to search, as read from a positions file
+#[derive(Clone, Debug)]
+pub struct DebugPosition {
+    /// The EPD "id" of the position, if it had one
+    pub id: Option<String>,
+    pub state: State,
+}
+
+/// The result of the final iteration of searching a single position
+#[derive(Clone, Debug)]
+pub struct SearchSummary {
+    pub id: Option<String>,
+    pub state: State,
+    pub best_move: Move,
+
+    /// The evaluation of the best move, from the perspective of the player to move
+    pub score: Evaluation,
+
+    pub depth: u8,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+/// Parse each non-blank line of a positions file as either a FEN string or an EPD record.
+///
+/// Lines starting with a '#' are treated as comments.
+pub fn parse_positions(positions_str: &str) -> Result<Vec<DebugPosition>> {
+    positions_str
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            if let Ok(state) = parse_fen(line) {
+                return Ok(DebugPosition { id: None, state });
+            }
+
+            let record = parse_epd(line)
+                .with_context(|| format!("Line {} is neither a FEN nor an EPD", line_number))?;
+            Ok(DebugPosition {
+                id: record.id().map(str::to_string),
+                state: record.state,
+            })
+        })
+        .collect()
+}
+
+/// Search a single position to the given depth, calling `on_iteration` with the result of each
+/// iteration of the search as it completes.
+///
+/// Searches without a depth go to the engine's configured default depth. The opening book is
+/// never consulted.
+pub fn search_position(
+    engine: &mut Engine,
+    position: &DebugPosition,
+    depth: Option<u8>,
+    mut on_iteration: impl FnMut(&SearchInfo),
+) -> Result<SearchSummary> {
+    engine.set_deterministic(true);
+    let limits = SearchLimits {
+        max_depth: depth,
+        ..SearchLimits::default()
+    };
+
+    let mut last_info = None;
+    for info in engine.analyze(&position.state, limits)? {
+        on_iteration(&info);
+        last_info = Some(info);
+    }
+
+    let last_info = last_info.ok_or_else(|| anyhow!("Search finished without any results"))?;
+    let best_move = *last_info
+        .pv
+        .first()
+        .ok_or_else(|| anyhow!("Search finished without a best move"))?;
+
+    Ok(SearchSummary {
+        id: position.id.clone(),
+        state: position.state,
+        best_move,
+        score: last_info.score,
+        depth: last_info.depth,
+        nodes: last_info.nodes,
+        time: last_info.time,
+    })
+}
+
+/// Search each position in turn with a single engine, calling `on_iteration` with the index of
+/// the position being searched and the result of each iteration.
+pub fn search_positions(
+    positions: &[DebugPosition],
+    depth: Option<u8>,
+    mut on_iteration: impl FnMut(usize, &SearchInfo),
+) -> Result<Vec<SearchSummary>> {
+    let mut engine = Engine::new();
+    positions
+        .iter()
+        .enumerate()
+        .map(|(idx, position)| {
+            // Each position is unrelated to the last, and shouldn't depend on the order they're
+            // searched in
+            engine.new_game();
+            search_position(&mut engine, position, depth, |info| on_iteration(idx, info))
+        })
+        .collect()
+}
+
+/// Format an evaluation as either centipawns or the number of moves until mate, eg "cp 35" or
+/// "mate -2"
+pub fn format_score(score: Evaluation) -> String {
+    match Score::from_evaluation(score) {
+        Score::Cp(cp) => format!("cp {}", cp),
+        Score::Mate(moves) => format!("mate {}", moves),
+    }
+}
+
+/// Lay out the summaries as a table, with one row per position
+pub fn format_summary_table(summaries: &[SearchSummary]) -> String {
+    let header = [
+        "Position",
+        "Best move",
+        "Eval",
+        "Depth",
+        "Nodes",
+        "Time (ms)",
+    ];
+    let rows = summaries
+        .iter()
+        .enumerate()
+        .map(|(idx, summary)| {
+            [
+                summary.id.clone().unwrap_or_else(|| (idx + 1).to_string()),
+                summary.best_move.format_long_algebraic(),
+                format_score(summary.score),
+                summary.depth.to_string(),
+                summary.nodes.to_string(),
+                summary.time.as_millis().to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: &[&str]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut table = format_row(&header);
+    table.push('\n');
+    table.push_str(&widths.map(|w| "-".repeat(w)).join("-+-"));
+    for row in &rows {
+        table.push('\n');
+        table.push_str(&format_row(
+            &row.iter().map(String::as_str).collect::<Vec<_>>(),
+        ));
+    }
+    table
+}
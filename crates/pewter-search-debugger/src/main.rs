@@ -4,18 +4,35 @@ use std::{fs::File, path::PathBuf, sync::atomic::AtomicBool};
 use anyhow::Result;
 use clap::Parser;
 use crossbeam_channel::unbounded;
-use pewter_core::{io::fen::parse_fen};
-use pewter_engine::{engine::search::SearchControls, Engine};
-
-/// Run a single best_move search, without any UCI server logic
+use pewter_core::{
+    io::{fen::parse_fen, uci::Position},
+    State,
+};
+use pewter_engine::{
+    engine::{eval::evaluate_explained, search::SearchControls},
+    Engine,
+};
+use pewter_search_debugger::{
+    format_score, format_summary_table, parse_positions, search_positions,
+};
+
+/// Run best_move searches, without any UCI server logic
 #[derive(Parser, Debug)]
 #[clap(about, version, author, name = "search_debugger")]
 struct Args {
     /// Fen string to start search at
-    #[clap(long)]
-    fen: String,
+    #[clap(long, required_unless_present_any = &["startpos", "positions-file"])]
+    fen: Option<String>,
+
+    /// Start the search at the conventional starting position
+    #[clap(long, conflicts_with = "fen")]
+    startpos: bool,
+
+    /// File of FEN strings or EPD records, one per line, to search each of in turn and summarize
+    #[clap(long, conflicts_with_all = &["fen", "startpos", "node-count-histogram"])]
+    positions_file: Option<PathBuf>,
 
-    /// Depth to search to
+    /// Depth to search to, defaulting to the engine's configured default depth
     #[clap(long)]
     depth: Option<u8>,
 
@@ -29,7 +46,9 @@ struct Args {
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    if args.node_count_histogram {
+    if args.positions_file.is_some() {
+        multi_search(&args)?;
+    } else if args.node_count_histogram {
         nodes_searched_histogram(&args)?;
     } else {
         single_search(&args)?;
@@ -38,25 +57,42 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// The single position given by either --fen or --startpos
+fn initial_state(args: &Args) -> Result<State> {
+    let fen = match &args.fen {
+        Some(fen) => fen.as_str(),
+        None => Position::StartPos.fen(),
+    };
+    Ok(parse_fen(fen)?)
+}
+
 fn single_search(args: &Args) -> Result<()> {
-    let initial_state = parse_fen(&args.fen)?;
+    let initial_state = initial_state(args)?;
 
     println!("Initial board state:");
     println!("{}", initial_state.pretty_format());
 
+    let breakdown = evaluate_explained(&initial_state);
+    println!(
+        "Static evaluation = {}, from the perspective of the player to move:",
+        breakdown.total()
+    );
+    println!("{:#?}", breakdown);
+
     let mut engine = Engine::new();
     engine.set_board_state(initial_state);
 
     let (perf_tx, perf_rx) = unbounded();
-    let max_depth = args.depth.or(Some(5));
     let best_move = engine.search_best_move(
         false,
-        max_depth,
+        args.depth,
         None,
         None,
+        false,
         SearchControls {
             stop: AtomicBool::new(false).into(),
             perf_info: Some(perf_tx),
+            ..SearchControls::default()
         },
     )?;
 
@@ -68,8 +104,36 @@ fn single_search(args: &Args) -> Result<()> {
     Ok(())
 }
 
+fn multi_search(args: &Args) -> Result<()> {
+    let positions_file = args.positions_file.as_ref().unwrap();
+    let positions = parse_positions(&std::fs::read_to_string(positions_file)?)?;
+
+    let summaries = search_positions(&positions, args.depth, |idx, info| {
+        let pv = info
+            .pv
+            .iter()
+            .map(|m| m.format_long_algebraic())
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!(
+            "Position {}: depth {}, {}, {} nodes, {}ms, pv {}",
+            idx + 1,
+            info.depth,
+            format_score(info.score),
+            info.nodes,
+            info.time.as_millis(),
+            pv
+        );
+    })?;
+
+    println!();
+    println!("{}", format_summary_table(&summaries));
+
+    Ok(())
+}
+
 fn nodes_searched_histogram(args: &Args) -> Result<()> {
-    let initial_state = parse_fen(&args.fen)?;
+    let initial_state = initial_state(args)?;
 
     println!("Initial board state:");
     println!("{}", initial_state.pretty_format());
@@ -88,9 +152,11 @@ fn nodes_searched_histogram(args: &Args) -> Result<()> {
             ),
             None,
             None,
+            false,
             SearchControls {
                 stop: AtomicBool::new(false).into(),
                 perf_info: Some(perf_tx),
+                ..SearchControls::default()
             },
         )?;
 
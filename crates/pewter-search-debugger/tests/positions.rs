@@ -0,0 +1,33 @@
+use pewter_core::movegen::legal_moves;
+use pewter_search_debugger::{format_summary_table, parse_positions, search_positions};
+
+const POSITIONS: &str = "\
+# One full FEN and one EPD record
+r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3
+4k3/8/8/3q4/8/8/3R4/4K3 w - - bm Rxd5; id \"hanging queen\";
+";
+
+#[test]
+fn test_searches_each_position() {
+    let positions = parse_positions(POSITIONS).unwrap();
+    assert_eq!(positions.len(), 2);
+    assert_eq!(positions[1].id.as_deref(), Some("hanging queen"));
+
+    let mut iterations = vec![0; positions.len()];
+    let summaries = search_positions(&positions, Some(4), |idx, _| iterations[idx] += 1).unwrap();
+
+    assert_eq!(summaries.len(), 2);
+    for (summary, count) in summaries.iter().zip(iterations) {
+        assert!(count > 0);
+        assert!(
+            legal_moves(&summary.state)
+                .iter()
+                .any(|m| m == summary.best_move),
+            "{} is not legal",
+            summary.best_move
+        );
+    }
+
+    // A header, a separator, then one row per position
+    assert_eq!(format_summary_table(&summaries).lines().count(), 4);
+}